@@ -0,0 +1,150 @@
+#import bevy_pbr::{
+    mesh_functions,
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_bindings,
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::{alpha_discard, apply_pbr_lighting, main_pass_post_lighting_processing},
+    view_transformations::position_world_to_clip,
+    mesh_view_bindings::view,
+}
+
+struct TerrainSettings {
+    // world units covered by one repetition of the textures
+    texture_scale: f32,
+    // higher values narrow the blend between the triplanar projections
+    triplanar_sharpness: f32,
+    // how much of the vertex color tints the textures
+    vertex_color_tint: f32,
+    normal_strength: f32,
+    // shows the vertex colors alone, for the debug colorings
+    debug_vertex_colors: f32,
+}
+
+@group(2) @binding(100) var<uniform> terrain: TerrainSettings;
+@group(2) @binding(101) var albedo_texture: texture_2d_array<f32>;
+@group(2) @binding(102) var albedo_sampler: sampler;
+@group(2) @binding(103) var normal_texture: texture_2d_array<f32>;
+@group(2) @binding(104) var normal_sampler: sampler;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(5) color: vec4<f32>,
+    // grass, rock, sand, snow
+    @location(8) splat: vec4<f32>,
+#ifdef TERRAIN_MORPH
+    // height offset to the parent level, then the morph start and end distances
+    @location(9) morph: vec4<f32>,
+#endif
+};
+
+struct TerrainVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(5) color: vec4<f32>,
+    @location(6) @interpolate(flat) instance_index: u32,
+    @location(8) splat: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> TerrainVertexOutput {
+    var out: TerrainVertexOutput;
+
+    let model = mesh_functions::get_model_matrix(vertex.instance_index);
+    var position = vertex.position;
+#ifdef TERRAIN_MORPH
+    // geomorph towards the coarser level as the camera moves away
+    let unmorphed = mesh_functions::mesh_position_local_to_world(model, vec4<f32>(position, 1.0));
+    let distance = length(unmorphed.xyz - view.world_position);
+    let morph = clamp((distance - vertex.morph.y) / max(vertex.morph.z - vertex.morph.y, 0.0001), 0.0, 1.0);
+    position.y += vertex.morph.x * morph;
+#endif
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(vertex.normal, vertex.instance_index);
+    out.world_position = mesh_functions::mesh_position_local_to_world(model, vec4<f32>(position, 1.0));
+    out.position = position_world_to_clip(out.world_position.xyz);
+    out.uv = vertex.uv;
+    out.color = vertex.color;
+    out.instance_index = vertex.instance_index;
+    out.splat = vertex.splat;
+
+    return out;
+}
+
+fn triplanar_weights(normal: vec3<f32>) -> vec3<f32> {
+    let weights = pow(abs(normal), vec3<f32>(terrain.triplanar_sharpness));
+    return weights / (weights.x + weights.y + weights.z);
+}
+
+fn sample_albedo(position: vec3<f32>, blend: vec3<f32>, layer: u32) -> vec3<f32> {
+    let x = textureSample(albedo_texture, albedo_sampler, position.zy, layer).rgb;
+    let y = textureSample(albedo_texture, albedo_sampler, position.xz, layer).rgb;
+    let z = textureSample(albedo_texture, albedo_sampler, position.xy, layer).rgb;
+    return x * blend.x + y * blend.y + z * blend.z;
+}
+
+// Whiteout blend of the three tangent space normals onto the surface normal.
+fn sample_normal(position: vec3<f32>, normal: vec3<f32>, blend: vec3<f32>, layer: u32) -> vec3<f32> {
+    let strength = vec2<f32>(terrain.normal_strength);
+    let tx = textureSample(normal_texture, normal_sampler, position.zy, layer).xyz * 2.0 - 1.0;
+    let ty = textureSample(normal_texture, normal_sampler, position.xz, layer).xyz * 2.0 - 1.0;
+    let tz = textureSample(normal_texture, normal_sampler, position.xy, layer).xyz * 2.0 - 1.0;
+
+    let nx = vec3<f32>(tx.xy * strength + normal.zy, abs(tx.z) * normal.x);
+    let ny = vec3<f32>(ty.xy * strength + normal.xz, abs(ty.z) * normal.y);
+    let nz = vec3<f32>(tz.xy * strength + normal.xy, abs(tz.z) * normal.z);
+
+    return nx.zyx * blend.x + ny.xzy * blend.y + nz.xyz * blend.z;
+}
+
+@fragment
+fn fragment(
+    mesh: TerrainVertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var in: VertexOutput;
+    in.position = mesh.position;
+    in.world_position = mesh.world_position;
+    in.world_normal = mesh.world_normal;
+#ifdef VERTEX_UVS
+    in.uv = mesh.uv;
+#endif
+#ifdef VERTEX_COLORS
+    in.color = mesh.color;
+#endif
+#ifdef VERTEX_OUTPUT_INSTANCE_INDEX
+    in.instance_index = mesh.instance_index;
+#endif
+
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+
+    let position = mesh.world_position.xyz / terrain.texture_scale;
+    let normal = normalize(mesh.world_normal);
+    let blend = triplanar_weights(normal);
+    let splat = mesh.splat / max(dot(mesh.splat, vec4<f32>(1.0)), 0.0001);
+
+    var albedo = vec3<f32>(0.0);
+    var detail_normal = vec3<f32>(0.0);
+    for (var layer = 0u; layer < 4u; layer++) {
+        albedo += sample_albedo(position, blend, layer) * splat[layer];
+        detail_normal += sample_normal(position, normal, blend, layer) * splat[layer];
+    }
+
+    let tint = mix(vec3<f32>(1.0), mesh.color.rgb, terrain.vertex_color_tint);
+    let color = mix(albedo * tint, mesh.color.rgb, terrain.debug_vertex_colors);
+    pbr_input.material.base_color = vec4<f32>(
+        color * pbr_bindings::material.base_color.rgb,
+        pbr_bindings::material.base_color.a,
+    );
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+    pbr_input.N = normalize(detail_normal);
+
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+
+    return out;
+}
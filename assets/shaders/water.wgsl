@@ -0,0 +1,119 @@
+#import bevy_pbr::{
+    mesh_functions,
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_bindings,
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+    view_transformations::position_world_to_clip,
+    mesh_view_bindings::globals,
+}
+
+struct WaterSettings {
+    shallow_color: vec4<f32>,
+    deep_color: vec4<f32>,
+    // alpha scales the foam
+    foam_color: vec4<f32>,
+    // world units of water over which the color turns deep
+    depth_scale: f32,
+    // depth under which the shore foams and the waves calm down
+    foam_depth: f32,
+    wave_amplitude: f32,
+    wave_length: f32,
+    wave_speed: f32,
+}
+
+@group(2) @binding(100) var<uniform> water: WaterSettings;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(8) depth: f32,
+};
+
+struct WaterVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(6) @interpolate(flat) instance_index: u32,
+    @location(8) depth: f32,
+};
+
+// Two crossing sine waves. Returns the height offset, then its slope along x
+// and z.
+fn waves(position: vec2<f32>) -> vec3<f32> {
+    let k = 6.2831853 / water.wave_length;
+    let t = globals.time * water.wave_speed * k;
+    let first_direction = vec2<f32>(0.8, 0.6);
+    let second_direction = vec2<f32>(-0.4, 0.9);
+
+    let first = dot(position, first_direction) * k - t;
+    let second = dot(position, second_direction) * k * 1.7 - t * 1.3;
+
+    let height = water.wave_amplitude * (sin(first) + 0.5 * sin(second));
+    let slope = water.wave_amplitude * k
+        * (first_direction * cos(first) + 0.85 * second_direction * cos(second));
+    return vec3<f32>(height, slope);
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> WaterVertexOutput {
+    var out: WaterVertexOutput;
+
+    let model = mesh_functions::get_model_matrix(vertex.instance_index);
+    var world_position = mesh_functions::mesh_position_local_to_world(model, vec4<f32>(vertex.position, 1.0));
+
+    // waves calm down in shallow water instead of climbing the shore
+    let calm = clamp(vertex.depth / water.foam_depth, 0.0, 1.0);
+    let wave = waves(world_position.xz) * calm;
+    world_position.y += wave.x;
+
+    out.world_position = world_position;
+    out.world_normal = normalize(vec3<f32>(-wave.y, 1.0, -wave.z));
+    out.position = position_world_to_clip(world_position.xyz);
+    out.uv = vertex.uv;
+    out.instance_index = vertex.instance_index;
+    out.depth = vertex.depth;
+
+    return out;
+}
+
+@fragment
+fn fragment(
+    mesh: WaterVertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var in: VertexOutput;
+    in.position = mesh.position;
+    in.world_position = mesh.world_position;
+    in.world_normal = mesh.world_normal;
+#ifdef VERTEX_UVS
+    in.uv = mesh.uv;
+#endif
+#ifdef VERTEX_OUTPUT_INSTANCE_INDEX
+    in.instance_index = mesh.instance_index;
+#endif
+
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+
+    let depth = max(mesh.depth, 0.0);
+    let deep = 1.0 - exp(-depth / water.depth_scale);
+    var color = mix(water.shallow_color, water.deep_color, deep);
+
+    // foam bands along the shore, washing in and out
+    let shore = 1.0 - clamp(depth / water.foam_depth, 0.0, 1.0);
+    let bands = 0.5 + 0.5 * sin(depth * 8.0 - globals.time * 2.0);
+    let foam = smoothstep(0.45, 0.6, shore * (0.5 + 0.5 * bands)) * water.foam_color.a;
+    color = mix(color, vec4<f32>(water.foam_color.rgb, 1.0), foam);
+
+    pbr_input.material.base_color = color * pbr_bindings::material.base_color;
+    pbr_input.N = normalize(mesh.world_normal);
+
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+
+    return out;
+}
@@ -1,17 +1,124 @@
+use std::f32::consts::PI;
+
+use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::transform::TransformSystem;
+use bevy::window::CursorGrabMode;
 use bevy::{pbr::CascadeShadowConfigBuilder, prelude::*};
 use bevy_atmosphere::prelude::*;
+use bevy_dolly::prelude::*;
 use bevy_flycam::FlyCam;
+use bevy_rapier3d::prelude::*;
+
+use crate::console::{parse_arg, AddConsoleCommand, CommandResult, ConsoleCommand, ConsoleError};
+use crate::generation::chunk::ChunkLoader;
+use crate::input::{Action, Actions};
+use crate::player::Player;
+
+/// Distance in world units up to which objects retain visibility (>= 5% contrast)
+pub const FOG_VISIBILITY: f32 = 512.0;
+/// Illuminance of the sun when right overhead.
+const SUN_ILLUMINANCE: f32 = 50000.0;
 
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(Msaa::Sample4)
-            .insert_resource(AtmosphereModel::default());
-        app.add_systems(Startup, setup_camera);
+        app.insert_resource(AtmosphereModel::default())
+            .add_console_command(
+                "time",
+                ConsoleCommand::new(
+                    "time <hour>",
+                    "Moves the sun to an hour of the day",
+                    time_command,
+                ),
+            );
+        app.init_state::<CameraMode>()
+            .init_resource::<CameraTransition>()
+            .add_systems(Startup, setup_camera)
+            .add_systems(Update, cycle_camera_mode)
+            .add_systems(OnEnter(CameraMode::Fly), enable_flycam)
+            .add_systems(OnExit(CameraMode::Fly), disable_flycam)
+            .add_systems(OnEnter(CameraMode::Orbit), enter_orbit)
+            .add_systems(OnEnter(CameraMode::Follow), enter_follow)
+            .add_systems(
+                PostUpdate,
+                (
+                    orbit_camera.run_if(in_state(CameraMode::Orbit)),
+                    follow_camera.run_if(in_state(CameraMode::Follow)),
+                    blend_camera_transition,
+                )
+                    .chain()
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// How the camera moves, cycled with `Action::CycleCamera`.
+#[derive(States, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CameraMode {
+    /// Free flight with the flycam.
+    #[default]
+    Fly,
+    /// Turns around `CameraController::focus`.
+    Orbit,
+    /// Trails behind the player.
+    Follow,
+}
+
+impl CameraMode {
+    fn next(self) -> CameraMode {
+        match self {
+            CameraMode::Fly => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Follow,
+            CameraMode::Follow => CameraMode::Fly,
+        }
     }
 }
 
+/// Distance kept between the camera and the terrain it collides with.
+const CAMERA_COLLISION_MARGIN: f32 = 0.5;
+
+/// Blends from the transform the camera had when the mode changed to the one
+/// the new mode computes.
+#[derive(Resource)]
+pub struct CameraTransition {
+    from: Transform,
+    timer: Timer,
+}
+
+impl Default for CameraTransition {
+    fn default() -> Self {
+        let mut transition = CameraTransition {
+            from: Transform::IDENTITY,
+            timer: Timer::from_seconds(0.6, TimerMode::Once),
+        };
+        transition.finish();
+
+        transition
+    }
+}
+
+impl CameraTransition {
+    fn start(&mut self, from: Transform) {
+        self.from = from;
+        self.timer.reset();
+    }
+
+    fn finish(&mut self) {
+        let duration = self.timer.duration();
+        self.timer.tick(duration);
+    }
+
+    /// Moves the start of the blend along with a recentering of the world.
+    pub fn shift(&mut self, offset: Vec3) {
+        self.from.translation -= offset;
+    }
+}
+
+/// The camera the game renders through.
+#[derive(Component)]
+pub struct MainCamera;
+
 #[derive(Component)]
 pub struct CameraController {
     pub sensitivity: f32,
@@ -22,6 +129,7 @@ pub struct CameraController {
     pub focus: Vec3,
     pub distance: f32,
     pub min_distance: f32,
+    pub max_distance: f32,
 }
 
 impl Default for CameraController {
@@ -34,15 +142,69 @@ impl Default for CameraController {
             focus: Vec3::ZERO,
             distance: 100.0,
             min_distance: 10.0,
+            max_distance: 800.0,
         }
     }
 }
 
+/// Offset of the follow camera from the player, in the rig's frame.
+const FOLLOW_ARM: Vec3 = Vec3::new(0.0, 1.5, 8.0);
+
+/// Dolly rig used by the follow mode.
+pub fn follow_rig(rotation: Quat) -> Rig {
+    let (yaw, _, _) = rotation.to_euler(EulerRot::YXZ);
+
+    Rig::builder()
+        .with(Position::new(Vec3::ZERO))
+        .with(
+            YawPitch::new()
+                .yaw_degrees(yaw.to_degrees())
+                .pitch_degrees(-20.0),
+        )
+        .with(Smooth::new_position_rotation(1.0, 1.0))
+        .with(Arm::new(FOLLOW_ARM))
+        .build()
+}
+
+/// Moves the follow rig by `offset` along with a recentering of the world.
+/// The smoothing state of dolly is private, so a new rig is primed with the
+/// shifted smoothed transform before getting its target back.
+pub fn shift_rig(rig: &mut Rig, offset: Vec3) {
+    let target = rig.driver::<Position>().position - offset;
+    let (yaw, pitch) = {
+        let yaw_pitch = rig.driver::<YawPitch>();
+        (yaw_pitch.yaw_degrees, yaw_pitch.pitch_degrees)
+    };
+    let smoothed = rig.final_transform;
+
+    *rig = follow_rig(smoothed.rotation);
+    rig.driver_mut::<Position>().position =
+        smoothed.translation - smoothed.rotation * FOLLOW_ARM - offset;
+    rig.driver_mut::<YawPitch>()
+        .set_rotation_quat(smoothed.rotation);
+    rig.update(0.0);
+
+    rig.driver_mut::<Position>().position = target;
+    let yaw_pitch = rig.driver_mut::<YawPitch>();
+    yaw_pitch.yaw_degrees = yaw;
+    yaw_pitch.pitch_degrees = pitch;
+}
+
+pub fn fog_falloff(visibility: f32) -> FogFalloff {
+    FogFalloff::from_visibility_colors(
+        visibility,
+        Color::rgb(0.35, 0.5, 0.66), // atmospheric extinction color (after light is lost due to absorption by atmospheric particles)
+        Color::rgb(0.8, 0.844, 1.0), // atmospheric inscattering color (light gained due to scattering from the sun)
+    )
+}
+
 fn setup_camera(mut commands: Commands) {
     commands.spawn((
         Camera3dBundle {
             projection: PerspectiveProjection {
                 fov: 50.0_f32.to_radians(),
+                // far enough for the horizon and the quadtree terrain
+                far: 20000.0,
                 ..default()
             }
             .into(),
@@ -54,18 +216,17 @@ fn setup_camera(mut commands: Commands) {
             sensitivity: (0.173) / 500.0,
             ..default()
         },
+        MainCamera,
         AtmosphereCamera::default(),
         FogSettings {
             color: Color::rgba(0.35, 0.48, 0.66, 1.0),
             directional_light_color: Color::rgba(1.0, 0.95, 0.85, 1.0),
             directional_light_exponent: 300.0,
-            falloff: FogFalloff::from_visibility_colors(
-                512.0, // distance in world units up to which objects retain visibility (>= 5% contrast)
-                Color::rgb(0.35, 0.5, 0.66), // atmospheric extinction color (after light is lost due to absorption by atmospheric particles)
-                Color::rgb(0.8, 0.844, 1.0), // atmospheric inscattering color (light gained due to scattering from the sun)
-            ),
+            falloff: fog_falloff(FOG_VISIBILITY),
         },
         FlyCam,
+        follow_rig(Quat::IDENTITY),
+        ChunkLoader,
     ));
 
     let cascade_shadow_config = CascadeShadowConfigBuilder {
@@ -77,7 +238,7 @@ fn setup_camera(mut commands: Commands) {
 
     commands.spawn(DirectionalLightBundle {
         directional_light: DirectionalLight {
-            illuminance: 50000.0,
+            illuminance: SUN_ILLUMINANCE,
             shadows_enabled: true,
             ..default()
         },
@@ -86,3 +247,203 @@ fn setup_camera(mut commands: Commands) {
         ..default()
     });
 }
+
+fn time_command(world: &mut World, args: &[&str]) -> CommandResult {
+    let [hour] = args else {
+        return Err(ConsoleError::Usage);
+    };
+    let hour: f32 = parse_arg(hour)?;
+    if !(0.0..=24.0).contains(&hour) {
+        return Err(ConsoleError::InvalidArgument(hour.to_string(), "hour"));
+    }
+
+    // rises at 6, is highest at noon and sets at 18
+    let angle = (hour - 6.0) / 12.0 * PI;
+    let sun = Vec3::new(angle.cos(), angle.sin(), 0.3).normalize();
+
+    let mut lights = world.query::<(&mut Transform, &mut DirectionalLight)>();
+    for (mut transform, mut light) in lights.iter_mut(world) {
+        *transform = Transform::IDENTITY.looking_to(-sun, Vec3::Y);
+        light.illuminance = SUN_ILLUMINANCE * sun.y.max(0.0);
+    }
+    if let Some(mut atmosphere) = world.get_resource_mut::<AtmosphereModel>() {
+        *atmosphere = AtmosphereModel::new(Nishita {
+            sun_position: sun,
+            ..default()
+        });
+    }
+
+    Ok(format!("time set to {hour}:00"))
+}
+
+fn cycle_camera_mode(
+    actions: Actions,
+    mode: Res<State<CameraMode>>,
+    mut next_mode: ResMut<NextState<CameraMode>>,
+) {
+    if actions.just_pressed(Action::CycleCamera) {
+        next_mode.set(mode.get().next());
+    }
+}
+
+fn enable_flycam(
+    mut commands: Commands,
+    mut transition: ResMut<CameraTransition>,
+    cameras: Query<Entity, With<CameraController>>,
+) {
+    // the flycam moves from wherever the camera is, nothing to blend
+    transition.finish();
+    for camera in cameras.iter() {
+        commands.entity(camera).insert(FlyCam);
+    }
+}
+
+fn disable_flycam(mut commands: Commands, cameras: Query<Entity, With<CameraController>>) {
+    for camera in cameras.iter() {
+        commands.entity(camera).remove::<FlyCam>();
+    }
+}
+
+/// Orbits around the point the camera was looking at.
+fn enter_orbit(
+    rapier_context: Res<RapierContext>,
+    mut transition: ResMut<CameraTransition>,
+    mut cameras: Query<(&Transform, &mut CameraController)>,
+) {
+    for (transform, mut controller) in cameras.iter_mut() {
+        let distance = rapier_context
+            .cast_ray(
+                transform.translation,
+                *transform.forward(),
+                controller.distance,
+                true,
+                QueryFilter::only_fixed(),
+            )
+            .map_or(controller.distance, |(_, toi)| toi)
+            .max(controller.min_distance);
+
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        controller.rotation = Vec3::new(pitch, yaw, 0.0);
+        controller.focus = transform.translation + *transform.forward() * distance;
+        controller.distance = distance;
+
+        transition.start(*transform);
+    }
+}
+
+fn enter_follow(
+    mut transition: ResMut<CameraTransition>,
+    players: Query<&Transform, With<Player>>,
+    mut cameras: Query<(&Transform, &mut Rig), With<CameraController>>,
+) {
+    let Ok(player) = players.get_single() else {
+        return;
+    };
+
+    for (transform, mut rig) in cameras.iter_mut() {
+        *rig = follow_rig(transform.rotation);
+        rig.driver_mut::<Position>().position = player.translation;
+        transition.start(*transform);
+    }
+}
+
+/// Mouse motion since the last frame, only while the cursor is grabbed.
+fn mouse_delta(windows: &Query<&Window>, motion: &mut EventReader<MouseMotion>) -> Vec2 {
+    let delta = motion.read().map(|event| event.delta).sum();
+
+    match windows.get_single() {
+        Ok(window) if window.cursor.grab_mode != CursorGrabMode::None => delta,
+        _ => Vec2::ZERO,
+    }
+}
+
+/// Pulls `target` toward `pivot` when the terrain is between them.
+fn collide_camera(rapier_context: &RapierContext, pivot: Vec3, target: Vec3) -> Vec3 {
+    let offset = target - pivot;
+    let distance = offset.length();
+    if distance <= f32::EPSILON {
+        return target;
+    }
+
+    let direction = offset / distance;
+    match rapier_context.cast_ray(pivot, direction, distance, true, QueryFilter::only_fixed()) {
+        Some((_, toi)) => pivot + direction * (toi - CAMERA_COLLISION_MARGIN).max(0.0),
+        None => target,
+    }
+}
+
+fn orbit_camera(
+    windows: Query<&Window>,
+    mut motion: EventReader<MouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+    rapier_context: Res<RapierContext>,
+    mut cameras: Query<(&mut Transform, &mut CameraController)>,
+) {
+    let delta = mouse_delta(&windows, &mut motion);
+    let scroll: f32 = wheel.read().map(|event| event.y).sum();
+
+    for (mut transform, mut controller) in cameras.iter_mut() {
+        let sensitivity = controller.sensitivity;
+        controller.rotation.x = (controller.rotation.x - delta.y * sensitivity)
+            .clamp(-controller.rotate_lock, controller.rotate_lock);
+        controller.rotation.y -= delta.x * sensitivity;
+        controller.distance = (controller.distance - scroll * controller.scroll_sensitivity)
+            .clamp(controller.min_distance, controller.max_distance);
+
+        let rotation = Quat::from_euler(
+            EulerRot::YXZ,
+            controller.rotation.y,
+            controller.rotation.x,
+            0.0,
+        );
+        let target = controller.focus + rotation * Vec3::Z * controller.distance;
+
+        transform.translation = collide_camera(&rapier_context, controller.focus, target);
+        transform.rotation = rotation;
+    }
+}
+
+fn follow_camera(
+    time: Res<Time>,
+    windows: Query<&Window>,
+    mut motion: EventReader<MouseMotion>,
+    rapier_context: Res<RapierContext>,
+    players: Query<&Transform, (With<Player>, Without<CameraController>)>,
+    mut cameras: Query<(&mut Transform, &mut Rig, &CameraController)>,
+) {
+    let Ok(player) = players.get_single() else {
+        return;
+    };
+    let delta = mouse_delta(&windows, &mut motion);
+
+    for (mut transform, mut rig, controller) in cameras.iter_mut() {
+        let degrees = controller.sensitivity.to_degrees();
+        rig.driver_mut::<Position>().position = player.translation;
+        rig.driver_mut::<YawPitch>()
+            .rotate_yaw_pitch(-delta.x * degrees, -delta.y * degrees);
+
+        let rig_transform = rig.update(time.delta_seconds());
+        let pivot = player.translation + Vec3::Y;
+
+        transform.translation = collide_camera(&rapier_context, pivot, rig_transform.translation);
+        transform.rotation = rig_transform.rotation;
+    }
+}
+
+fn blend_camera_transition(
+    time: Res<Time>,
+    mut transition: ResMut<CameraTransition>,
+    mut cameras: Query<&mut Transform, With<CameraController>>,
+) {
+    if transition.timer.finished() {
+        return;
+    }
+    transition.timer.tick(time.delta());
+
+    // ease out, fast at first then settling on the new mode
+    let t = 1.0 - (1.0 - transition.timer.fraction()).powi(3);
+    for mut transform in cameras.iter_mut() {
+        transform.translation = transition.from.translation.lerp(transform.translation, t);
+        transform.rotation = transition.from.rotation.slerp(transform.rotation, t);
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy_egui::egui::text::{CCursor, CCursorRange};
+use bevy_egui::egui::{self, Align2, Color32, Key, Modifiers, RichText};
+use bevy_egui::{EguiContexts, EguiPlugin};
+use thiserror::Error;
+
+use crate::input::{Action, Actions};
+
+/// Lines kept in the console, older ones are dropped.
+const MAX_LOG_LINES: usize = 500;
+const CONSOLE_HEIGHT: f32 = 320.0;
+
+/// Drop-down console toggled with `Action::ToggleConsole`, running the
+/// commands other plugins register with `AddConsoleCommand`.
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        // the editor may have added it already
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+
+        app.init_resource::<Console>()
+            .init_resource::<ConsoleCommands>()
+            .add_console_command(
+                "clear",
+                ConsoleCommand::new("clear", "Clears the console", |world, args| {
+                    if !args.is_empty() {
+                        return Err(ConsoleError::Usage);
+                    }
+                    world.resource_mut::<Console>().log.clear();
+                    Ok(String::new())
+                }),
+            )
+            .add_systems(
+                PreUpdate,
+                (toggle_console, capture_input).chain().after(InputSystem),
+            )
+            .add_systems(Update, (console_ui, run_console_commands).chain());
+    }
+}
+
+pub type CommandResult = Result<String, ConsoleError>;
+
+type CommandFn = Box<dyn Fn(&mut World, &[&str]) -> CommandResult + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum ConsoleError {
+    /// The arguments don't match the command usage, which is printed instead.
+    #[error("wrong arguments")]
+    Usage,
+    #[error("`{0}` is not a valid {1}")]
+    InvalidArgument(String, &'static str),
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// A command, run with exclusive access to the world and its arguments split
+/// on whitespace. The text it returns is printed to the console.
+pub struct ConsoleCommand {
+    /// Arguments the command takes, e.g. `tp <x> <z>`.
+    pub usage: &'static str,
+    pub help: &'static str,
+    /// Words offered by the autocompletion after the command name.
+    pub keywords: &'static [&'static str],
+    run: CommandFn,
+}
+
+impl ConsoleCommand {
+    pub fn new(
+        usage: &'static str,
+        help: &'static str,
+        run: impl Fn(&mut World, &[&str]) -> CommandResult + Send + Sync + 'static,
+    ) -> Self {
+        ConsoleCommand {
+            usage,
+            help,
+            keywords: &[],
+            run: Box::new(run),
+        }
+    }
+
+    pub fn with_keywords(mut self, keywords: &'static [&'static str]) -> Self {
+        self.keywords = keywords;
+        self
+    }
+}
+
+/// Commands the console knows, by name. Systems can add their own at any
+/// time.
+#[derive(Resource, Default)]
+pub struct ConsoleCommands {
+    commands: BTreeMap<&'static str, ConsoleCommand>,
+}
+
+impl ConsoleCommands {
+    /// Adds a command, replacing any command of the same name.
+    pub fn add(&mut self, name: &'static str, command: ConsoleCommand) {
+        self.commands.insert(name, command);
+    }
+
+    /// Runs a line typed in the console, returning the text to print.
+    fn run(&self, world: &mut World, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, args)) = words.split_first() else {
+            return Ok(String::new());
+        };
+
+        if name == "help" {
+            return self.help(args.first().copied());
+        }
+
+        let Some(command) = self.commands.get(name) else {
+            return Err(format!(
+                "unknown command `{name}`, type `help` for the list"
+            ));
+        };
+        (command.run)(world, args).map_err(|error| match error {
+            ConsoleError::Usage => format!("usage: {}", command.usage),
+            error => error.to_string(),
+        })
+    }
+
+    fn help(&self, name: Option<&str>) -> Result<String, String> {
+        match name {
+            Some(name) => match self.commands.get(name) {
+                Some(command) => Ok(format!("{}\n    {}", command.usage, command.help)),
+                None => Err(format!("unknown command `{name}`")),
+            },
+            None => Ok(self
+                .commands
+                .values()
+                .map(|command| format!("{:<32} {}", command.usage, command.help))
+                .collect::<Vec<_>>()
+                .join("\n")),
+        }
+    }
+
+    /// Words that could complete the last word of `input`.
+    fn candidates(&self, input: &str) -> Vec<&'static str> {
+        let mut words: Vec<&str> = input.split_whitespace().collect();
+        if input.is_empty() || input.ends_with(char::is_whitespace) {
+            words.push("");
+        }
+        let Some((&current, previous)) = words.split_last() else {
+            return Vec::new();
+        };
+
+        let choices: Vec<&'static str> = match previous.first() {
+            None => std::iter::once("help")
+                .chain(self.commands.keys().copied())
+                .collect(),
+            Some(&"help") if previous.len() == 1 => self.commands.keys().copied().collect(),
+            Some(name) => self
+                .commands
+                .get(name)
+                .map(|command| command.keywords.to_vec())
+                .unwrap_or_default(),
+        };
+
+        choices
+            .into_iter()
+            .filter(|choice| choice.starts_with(current))
+            .collect()
+    }
+}
+
+/// Adds commands to the console from a plugin. Plugins can add them whether
+/// or not the console itself is enabled.
+pub trait AddConsoleCommand {
+    fn add_console_command(&mut self, name: &'static str, command: ConsoleCommand) -> &mut Self;
+}
+
+impl AddConsoleCommand for App {
+    fn add_console_command(&mut self, name: &'static str, command: ConsoleCommand) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .add(name, command);
+        self
+    }
+}
+
+/// Parses a command argument.
+pub fn parse_arg<T: FromStr>(arg: &str) -> Result<T, ConsoleError> {
+    arg.parse().map_err(|_| {
+        let kind = std::any::type_name::<T>();
+        ConsoleError::InvalidArgument(arg.to_string(), kind)
+    })
+}
+
+/// Parses an `on` or `off` argument.
+pub fn parse_switch(arg: &str) -> Result<bool, ConsoleError> {
+    match arg {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err(ConsoleError::InvalidArgument(arg.to_string(), "switch")),
+    }
+}
+
+pub fn switch_label(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LineKind {
+    Input,
+    Output,
+    Error,
+}
+
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    log: Vec<(LineKind, String)>,
+    /// Lines entered, oldest first.
+    history: Vec<String>,
+    /// Entry of the history being browsed with the arrows.
+    history_index: Option<usize>,
+    /// Lines entered this frame, run once the UI is done.
+    pending: Vec<String>,
+    /// Completions of the last word, shown after pressing tab.
+    completions: Vec<&'static str>,
+    /// Moves the cursor to the end of the input, after it was replaced.
+    cursor_to_end: bool,
+}
+
+impl Console {
+    /// Prints a line to the console.
+    pub fn print(&mut self, text: impl Into<String>) {
+        self.push(LineKind::Output, text.into());
+    }
+
+    pub fn print_error(&mut self, text: impl Into<String>) {
+        self.push(LineKind::Error, text.into());
+    }
+
+    fn push(&mut self, kind: LineKind, text: String) {
+        self.log.push((kind, text));
+        let overflow = self.log.len().saturating_sub(MAX_LOG_LINES);
+        self.log.drain(..overflow);
+    }
+
+    fn submit(&mut self) {
+        let line = std::mem::take(&mut self.input);
+        self.history_index = None;
+        self.completions.clear();
+
+        let line = line.trim().to_string();
+        self.push(LineKind::Input, format!("> {line}"));
+        if line.is_empty() {
+            return;
+        }
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        self.pending.push(line);
+    }
+
+    fn browse_history(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+
+        self.history_index = match (self.history_index, older) {
+            (None, true) => Some(self.history.len() - 1),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < self.history.len() => Some(index + 1),
+            (Some(_), false) => None,
+        };
+        self.input = match self.history_index {
+            Some(index) => self.history[index].clone(),
+            None => String::new(),
+        };
+        self.cursor_to_end = true;
+    }
+
+    /// Completes the last word as far as all the candidates agree, listing
+    /// them when there are several.
+    fn complete(&mut self, commands: &ConsoleCommands) {
+        let candidates = commands.candidates(&self.input);
+        let Some(first) = candidates.first() else {
+            self.completions.clear();
+            return;
+        };
+
+        let common = candidates.iter().fold(first.len(), |length, candidate| {
+            first
+                .chars()
+                .zip(candidate.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a.len_utf8())
+                .sum::<usize>()
+                .min(length)
+        });
+        let start = self
+            .input
+            .rfind(char::is_whitespace)
+            .map_or(0, |index| index + 1);
+
+        self.input.truncate(start);
+        self.input.push_str(&first[..common]);
+        if candidates.len() == 1 {
+            self.input.push(' ');
+            self.completions.clear();
+        } else {
+            self.completions = candidates;
+        }
+        self.cursor_to_end = true;
+    }
+}
+
+fn toggle_console(actions: Actions, mut console: ResMut<Console>) {
+    if actions.just_pressed(Action::ToggleConsole) {
+        console.open = !console.open;
+    }
+}
+
+/// Keeps the game from reacting to what is typed in the console, or to the
+/// clicks on it.
+fn capture_input(
+    console: Res<Console>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut mouse: ResMut<ButtonInput<MouseButton>>,
+) {
+    if console.open {
+        keys.reset_all();
+        mouse.reset_all();
+    }
+}
+
+fn console_ui(
+    mut contexts: EguiContexts,
+    mut console: ResMut<Console>,
+    commands: Res<ConsoleCommands>,
+) {
+    if !console.open {
+        return;
+    }
+    let console = &mut *console;
+
+    let ctx = contexts.ctx_mut();
+    let width = ctx.screen_rect().width();
+    egui::Window::new("Console")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(Align2::CENTER_TOP, [0.0, 0.0])
+        .fixed_size([width, CONSOLE_HEIGHT])
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(CONSOLE_HEIGHT - 48.0)
+                .auto_shrink([false, false])
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for (kind, text) in &console.log {
+                        let color = match kind {
+                            LineKind::Input => Color32::GRAY,
+                            LineKind::Output => Color32::WHITE,
+                            LineKind::Error => Color32::LIGHT_RED,
+                        };
+                        ui.label(RichText::new(text).monospace().color(color));
+                    }
+                });
+
+            if !console.completions.is_empty() {
+                ui.label(RichText::new(console.completions.join("  ")).weak());
+            }
+
+            // taken before the text field moves the focus or cursor with them
+            let (tab, up, down) = ui.input_mut(|input| {
+                (
+                    input.consume_key(Modifiers::NONE, Key::Tab),
+                    input.consume_key(Modifiers::NONE, Key::ArrowUp),
+                    input.consume_key(Modifiers::NONE, Key::ArrowDown),
+                )
+            });
+            if tab {
+                console.complete(&commands);
+            }
+            if up || down {
+                console.browse_history(up);
+            }
+
+            let output = egui::TextEdit::singleline(&mut console.input)
+                .font(egui::TextStyle::Monospace)
+                .desired_width(f32::INFINITY)
+                .lock_focus(true)
+                .show(ui);
+            let response = output.response;
+
+            if console.cursor_to_end {
+                console.cursor_to_end = false;
+                let mut state = output.state;
+                let end = CCursor::new(console.input.chars().count());
+                state.cursor.set_char_range(Some(CCursorRange::one(end)));
+                state.store(ui.ctx(), response.id);
+            }
+
+            if response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter)) {
+                console.submit();
+            }
+            response.request_focus();
+        });
+}
+
+fn run_console_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<Console>().pending);
+    if pending.is_empty() {
+        return;
+    }
+
+    world.resource_scope(|world, commands: Mut<ConsoleCommands>| {
+        for line in pending {
+            let output = commands.run(world, &line);
+            let mut console = world.resource_mut::<Console>();
+            match output {
+                Ok(text) => {
+                    for line in text.lines() {
+                        console.print(line);
+                    }
+                }
+                Err(error) => console.print_error(error),
+            }
+        }
+    });
+}
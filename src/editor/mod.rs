@@ -1,31 +1,63 @@
 use std::any::TypeId;
 
-use bevy::prelude::*;
 use bevy::asset::{ReflectAsset, UntypedAssetId};
+use bevy::prelude::*;
+use bevy::reflect::TypeRegistry;
+use bevy::render::camera::{CameraProjection, Viewport};
+use bevy::window::PrimaryWindow;
 use bevy_egui::EguiContext;
+use bevy_egui::EguiSet;
 use bevy_inspector_egui::bevy_inspector::hierarchy::{hierarchy_ui, SelectedEntities};
 use bevy_inspector_egui::bevy_inspector::{
     self, ui_for_entities_shared_components, ui_for_entity_with_children,
 };
 use bevy_inspector_egui::{egui, DefaultInspectorConfigPlugin};
-// use bevy_mod_picking::backends::egui::EguiPointer;
-// use bevy_mod_picking::prelude::*;
-use bevy_egui::EguiSet;
-use bevy::reflect::TypeRegistry;
-use bevy::render::camera::{CameraProjection, Viewport};
-use bevy::window::PrimaryWindow;
 use egui_dock::{DockArea, DockState, NodeIndex, Style};
 use egui_gizmo::{Gizmo, GizmoMode, GizmoOrientation};
 
-pub struct UiDockPlugin;
+use crate::camera::MainCamera;
+use crate::generation::biome::BiomeTable;
+use crate::generation::chunk::{Chunk, RenderDistance};
+use crate::generation::color_ramp::{TerrainColorRamp, TerrainColoring};
+use crate::generation::horizon::HorizonSettings;
+use crate::generation::material::TerrainMaterialConfig;
+use crate::generation::mesh::TerrainMeshing;
+use crate::generation::quadtree::QuadtreeSettings;
+use crate::input::{Action, Actions};
+use crate::sculpt::SculptBrush;
+use crate::water::WaterSettings;
+
+use self::node_graph::*;
+use self::noise_panel::*;
+use self::picking::*;
+use self::placement::*;
+
+mod node_graph;
+mod noise_panel;
+mod picking;
+mod placement;
+
+/// Terrain editor: the game renders in the viewport tab, docked between the
+/// generation settings and the inspector. Enabled with `--editor`.
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        // the console may have added it already
+        if !app.is_plugin_added::<bevy_egui::EguiPlugin>() {
+            app.add_plugins(bevy_egui::EguiPlugin);
+        }
 
-impl Plugin for UiDockPlugin {
-    fn build(&self, app :&mut App) {
         app.add_plugins(DefaultInspectorConfigPlugin)
-            // .add_plugins(bevy_framepace::FramepacePlugin) // reduces input lag
-            .add_plugins(bevy_egui::EguiPlugin)
-            // .add_plugins(bevy_mod_picking::plugins::DefaultPickingPlugins)
             .insert_resource(UiState::new())
+            .add_systems(Startup, (setup_noise_preview, setup_node_graph))
+            .add_systems(
+                Update,
+                (
+                    update_noise_preview.run_if(resource_exists::<NoisePreview>),
+                    update_node_graph.run_if(resource_exists::<NodeGraphEditor>),
+                ),
+            )
             .add_systems(
                 PostUpdate,
                 show_ui_system
@@ -34,49 +66,11 @@ impl Plugin for UiDockPlugin {
             )
             .add_systems(PostUpdate, set_camera_viewport.after(show_ui_system))
             .add_systems(Update, set_gizmo_mode)
-            // .add_systems(Update, auto_add_raycast_target)
-            // .add_systems(Update, handle_pick_events)
             .register_type::<Option<Handle<Image>>>()
             .register_type::<AlphaMode>();
     }
 }
 
-fn auto_add_raycast_target(
-    mut commands: Commands,
-    query: Query<Entity, (Without<PickRaycastTarget>, With<Handle<Mesh>>)>,
-) {
-    for entity in &query {
-        commands
-            .entity(entity)
-            .insert((PickRaycastTarget::default(), PickableBundle::default()));
-    }
-}
-
-fn handle_pick_events(
-    mut ui_state: ResMut<UiState>,
-    mut click_events: EventReader<PointerClick>,
-    mut egui: ResMut<EguiContext>,
-    egui_entity: Query<&EguiPointer>,
-) {
-    let egui_context = egui.ctx_mut();
-
-    for click in click_events.iter() {
-        if egui_entity.get(click.target()).is_ok() {
-            continue;
-        };
-
-        let modifiers = egui_context.input().modifiers;
-        let add = modifiers.ctrl || modifiers.shift;
-
-        ui_state
-            .selected_entities
-            .select_maybe_add(click.target(), add);
-    }
-}
-
-#[derive(Component)]
-pub struct MainCamera;
-
 fn show_ui_system(world: &mut World) {
     let Ok(egui_context) = world
         .query_filtered::<&mut EguiContext, With<PrimaryWindow>>()
@@ -106,8 +100,8 @@ fn set_camera_viewport(
 
     let scale_factor = window.scale_factor() * egui_settings.scale_factor;
 
-    let viewport_pos = ui_state.viewport_rect.left_top().to_vec2() * scale_factor as f32;
-    let viewport_size = ui_state.viewport_rect.size() * scale_factor as f32;
+    let viewport_pos = ui_state.viewport_rect.left_top().to_vec2() * scale_factor;
+    let viewport_size = ui_state.viewport_rect.size() * scale_factor;
 
     cam.viewport = Some(Viewport {
         physical_position: UVec2::new(viewport_pos.x as u32, viewport_pos.y as u32),
@@ -116,13 +110,13 @@ fn set_camera_viewport(
     });
 }
 
-fn set_gizmo_mode(input: Res<ButtonInput<KeyCode>>, mut ui_state: ResMut<UiState>) {
-    for (key, mode) in [
-        (KeyCode::KeyR, GizmoMode::Rotate),
-        (KeyCode::KeyT, GizmoMode::Translate),
-        (KeyCode::KeyS, GizmoMode::Scale),
+fn set_gizmo_mode(actions: Actions, mut ui_state: ResMut<UiState>) {
+    for (action, mode) in [
+        (Action::GizmoRotate, GizmoMode::Rotate),
+        (Action::GizmoTranslate, GizmoMode::Translate),
+        (Action::GizmoScale, GizmoMode::Scale),
     ] {
-        if input.just_pressed(key) {
+        if actions.just_pressed(action) {
             ui_state.gizmo_mode = mode;
         }
     }
@@ -142,6 +136,7 @@ struct UiState {
     selected_entities: SelectedEntities,
     selection: InspectorSelection,
     gizmo_mode: GizmoMode,
+    placement: Placement,
 }
 
 impl UiState {
@@ -150,9 +145,27 @@ impl UiState {
         let tree = state.main_surface_mut();
         let [game, _inspector] =
             tree.split_right(NodeIndex::root(), 0.75, vec![EguiWindow::Inspector]);
-        let [game, _hierarchy] = tree.split_left(game, 0.2, vec![EguiWindow::Hierarchy]);
-        let [_game, _bottom] =
-            tree.split_below(game, 0.8, vec![EguiWindow::Resources, EguiWindow::Assets]);
+        let [game, _generation] = tree.split_left(
+            game,
+            0.2,
+            vec![
+                EguiWindow::Generation,
+                EguiWindow::Noise,
+                EguiWindow::Biomes,
+                EguiWindow::Hierarchy,
+                EguiWindow::Palette,
+            ],
+        );
+        let [_game, _bottom] = tree.split_below(
+            game,
+            0.8,
+            vec![
+                EguiWindow::Chunks,
+                EguiWindow::NodeGraph,
+                EguiWindow::Resources,
+                EguiWindow::Assets,
+            ],
+        );
 
         Self {
             state,
@@ -160,6 +173,7 @@ impl UiState {
             selection: InspectorSelection::Entities,
             viewport_rect: egui::Rect::NOTHING,
             gizmo_mode: GizmoMode::Translate,
+            placement: Placement::default(),
         }
     }
 
@@ -170,6 +184,7 @@ impl UiState {
             selected_entities: &mut self.selected_entities,
             selection: &mut self.selection,
             gizmo_mode: self.gizmo_mode,
+            placement: &mut self.placement,
         };
         DockArea::new(&mut self.state)
             .style(Style::from_egui(ctx.style().as_ref()))
@@ -180,7 +195,13 @@ impl UiState {
 #[derive(Debug)]
 enum EguiWindow {
     GameView,
+    Generation,
+    Noise,
+    Chunks,
+    NodeGraph,
+    Biomes,
     Hierarchy,
+    Palette,
     Resources,
     Assets,
     Inspector,
@@ -192,6 +213,7 @@ struct TabViewer<'a> {
     selection: &'a mut InspectorSelection,
     viewport_rect: &'a mut egui::Rect,
     gizmo_mode: GizmoMode,
+    placement: &'a mut Placement,
 }
 
 impl egui_dock::TabViewer for TabViewer<'_> {
@@ -205,21 +227,76 @@ impl egui_dock::TabViewer for TabViewer<'_> {
             EguiWindow::GameView => {
                 *self.viewport_rect = ui.clip_rect();
 
-                draw_gizmo(ui, self.world, self.selected_entities, self.gizmo_mode);
+                // added before the gizmo, which takes the clicks on top of it
+                let viewport = ui.interact(
+                    *self.viewport_rect,
+                    ui.id().with("viewport"),
+                    egui::Sense::click(),
+                );
+                let gizmo_used = draw_gizmo(
+                    ui,
+                    self.world,
+                    self.selected_entities,
+                    self.gizmo_mode,
+                    self.placement,
+                );
+
+                let cursor = ui.input(|input| input.pointer.interact_pos());
+                if let (Some(item), Some(cursor)) =
+                    (viewport.dnd_release_payload::<PaletteItem>(), cursor)
+                {
+                    let viewport_rect = *self.viewport_rect;
+                    if let Some(entity) =
+                        drop_item(self.world, &item, self.placement, viewport_rect, cursor)
+                    {
+                        self.selected_entities.select_replace(entity);
+                        *self.selection = InspectorSelection::Entities;
+                    }
+                }
+
+                // clicks sculpt the terrain instead while a brush is out
+                let sculpting = self
+                    .world
+                    .get_resource::<SculptBrush>()
+                    .is_some_and(|brush| brush.enabled);
+                if let Some(cursor) = viewport.interact_pointer_pos() {
+                    if viewport.clicked() && !gizmo_used && !sculpting {
+                        select_under_cursor(
+                            self.world,
+                            *self.viewport_rect,
+                            cursor,
+                            self.selected_entities,
+                        );
+                        *self.selection = InspectorSelection::Entities;
+                    }
+                }
             }
+            EguiWindow::Generation => generation_ui(ui, self.world),
+            EguiWindow::Noise => noise_ui(ui, self.world),
+            EguiWindow::Chunks => {
+                if chunk_list(ui, self.world, self.selected_entities) {
+                    *self.selection = InspectorSelection::Entities;
+                }
+            }
+            EguiWindow::NodeGraph => node_graph_ui(ui, self.world),
+            EguiWindow::Biomes => biomes_ui(ui, self.world),
             EguiWindow::Hierarchy => {
                 let selected = hierarchy_ui(self.world, ui, self.selected_entities);
                 if selected {
                     *self.selection = InspectorSelection::Entities;
                 }
             }
+            EguiWindow::Palette => palette_ui(ui, self.placement),
             EguiWindow::Resources => select_resource(ui, &type_registry, self.selection),
             EguiWindow::Assets => select_asset(ui, &type_registry, self.world, self.selection),
             EguiWindow::Inspector => match *self.selection {
-                InspectorSelection::Entities => match self.selected_entities.as_slice() {
-                    &[entity] => ui_for_entity_with_children(self.world, entity, ui),
-                    entities => ui_for_entities_shared_components(self.world, entities, ui),
-                },
+                InspectorSelection::Entities => {
+                    pick_ui(ui, self.world, self.selected_entities);
+                    match self.selected_entities.as_slice() {
+                        &[entity] => ui_for_entity_with_children(self.world, entity, ui),
+                        entities => ui_for_entities_shared_components(self.world, entities, ui),
+                    }
+                }
                 InspectorSelection::Resource(type_id, ref name) => {
                     ui.label(name);
                     bevy_inspector::by_type_id::ui_for_resource(
@@ -253,12 +330,15 @@ impl egui_dock::TabViewer for TabViewer<'_> {
     }
 }
 
+/// Moves the selected entity with the gizmo. Returns true while the gizmo is
+/// being dragged.
 fn draw_gizmo(
     ui: &mut egui::Ui,
     world: &mut World,
     selected_entities: &SelectedEntities,
     gizmo_mode: GizmoMode,
-) {
+    placement: &Placement,
+) -> bool {
     let (cam_transform, projection) = world
         .query_filtered::<(&GlobalTransform, &Projection), With<MainCamera>>()
         .single(world);
@@ -266,14 +346,25 @@ fn draw_gizmo(
     let projection_matrix = projection.get_projection_matrix();
 
     if selected_entities.len() != 1 {
-        return;
+        return false;
     }
 
+    let mut used = false;
     for selected in selected_entities.iter() {
         let Some(transform) = world.get::<Transform>(selected) else {
             continue;
         };
-        let model_matrix = transform.compute_matrix();
+        // props are children of the scaled up chunks, the gizmo works in
+        // world space
+        let parent = world
+            .get::<Parent>(selected)
+            .and_then(|parent| world.get::<GlobalTransform>(parent.get()))
+            .copied();
+        let global = match parent {
+            Some(parent) => parent.mul_transform(*transform),
+            None => GlobalTransform::from(*transform),
+        };
+        let model_matrix = global.compute_matrix();
 
         let Some(result) = Gizmo::new(selected)
             .model_matrix(model_matrix.into())
@@ -286,13 +377,91 @@ fn draw_gizmo(
             continue;
         };
 
-        let mut transform = world.get_mut::<Transform>(selected).unwrap();
-        *transform = Transform {
+        used = true;
+        let mut moved = Transform {
             translation: Vec3::from(<[f32; 3]>::from(result.translation)),
             rotation: Quat::from_array(<[f32; 4]>::from(result.rotation)),
             scale: Vec3::from(<[f32; 3]>::from(result.scale)),
         };
+        if placement.snap_to_ground && gizmo_mode == GizmoMode::Translate {
+            moved = snap_to_ground(world, selected, moved, placement);
+        }
+
+        let mut transform = world.get_mut::<Transform>(selected).unwrap();
+        *transform = match parent {
+            Some(parent) => GlobalTransform::from(moved).reparented_to(&parent),
+            None => moved,
+        };
     }
+
+    used
+}
+
+fn generation_ui(ui: &mut egui::Ui, world: &mut World) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        ui.collapsing("Loading", |ui| {
+            bevy_inspector::ui_for_resource::<RenderDistance>(world, ui);
+        });
+        ui.collapsing("Meshing", |ui| {
+            bevy_inspector::ui_for_resource::<TerrainMeshing>(world, ui);
+        });
+        ui.collapsing("Quadtree", |ui| {
+            bevy_inspector::ui_for_resource::<QuadtreeSettings>(world, ui);
+        });
+        ui.collapsing("Horizon", |ui| {
+            bevy_inspector::ui_for_resource::<HorizonSettings>(world, ui);
+        });
+        ui.collapsing("Material", |ui| {
+            bevy_inspector::ui_for_resource::<TerrainMaterialConfig>(world, ui);
+        });
+        if world.contains_resource::<WaterSettings>() {
+            ui.collapsing("Water", |ui| {
+                bevy_inspector::ui_for_resource::<WaterSettings>(world, ui);
+            });
+        }
+    });
+}
+
+fn biomes_ui(ui: &mut egui::Ui, world: &mut World) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        ui.collapsing("Biome table", |ui| {
+            bevy_inspector::ui_for_resource::<BiomeTable>(world, ui);
+        });
+        ui.collapsing("Coloring", |ui| {
+            bevy_inspector::ui_for_resource::<TerrainColoring>(world, ui);
+        });
+        ui.collapsing("Color ramps", |ui| {
+            bevy_inspector::ui_for_assets::<TerrainColorRamp>(world, ui);
+        });
+    });
+}
+
+/// Loaded chunks, nearest to the origin first. Returns true when one was
+/// selected.
+fn chunk_list(ui: &mut egui::Ui, world: &mut World, selected: &mut SelectedEntities) -> bool {
+    let mut chunks: Vec<_> = world
+        .query::<(Entity, &Chunk)>()
+        .iter(world)
+        .map(|(entity, chunk)| (entity, chunk.coords, chunk.lod, chunk.biome))
+        .collect();
+    chunks.sort_by_key(|(_, coords, ..)| (coords.length_squared(), coords.x, coords.y));
+
+    let mut clicked = false;
+    ui.label(format!("{} chunks", chunks.len()));
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for (entity, coords, lod, biome) in chunks {
+            let label = format!("({}, {})  lod {lod}  {biome:?}", coords.x, coords.y);
+            if ui
+                .selectable_label(selected.contains(entity), label)
+                .clicked()
+            {
+                selected.select_replace(entity);
+                clicked = true;
+            }
+        }
+    });
+
+    clicked
 }
 
 fn select_resource(
@@ -310,7 +479,7 @@ fn select_resource(
             )
         })
         .collect();
-    resources.sort_by(|(name_a, _), (name_b, _)| name_a.cmp(name_b));
+    resources.sort_by_key(|(name, _)| *name);
 
     for (resource_name, type_id) in resources {
         let selected = match *selection {
@@ -341,7 +510,7 @@ fn select_asset(
             ))
         })
         .collect();
-    assets.sort_by(|(name_a, ..), (name_b, ..)| name_a.cmp(name_b));
+    assets.sort_by_key(|(name, ..)| *name);
 
     for (asset_name, asset_type_id, reflect_asset) in assets {
         let handles: Vec<_> = reflect_asset.ids(world).collect();
@@ -364,135 +533,3 @@ fn select_asset(
         });
     }
 }
-
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let box_size = 2.0;
-    let box_thickness = 0.15;
-    let box_offset = (box_size + box_thickness) / 2.0;
-
-    // left - red
-    let mut transform = Transform::from_xyz(-box_offset, box_offset, 0.0);
-    transform.rotate(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Cuboid::new(box_size, box_thickness, box_size)),
-        transform,
-        material: materials.add(StandardMaterial {
-            base_color: Color::rgb(0.63, 0.065, 0.05),
-            ..Default::default()
-        }),
-        ..Default::default()
-    });
-    // right - green
-    let mut transform = Transform::from_xyz(box_offset, box_offset, 0.0);
-    transform.rotate(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Cuboid::new(box_size, box_thickness, box_size)),
-        transform,
-        material: materials.add(StandardMaterial {
-            base_color: Color::rgb(0.14, 0.45, 0.091),
-            ..Default::default()
-        }),
-        ..Default::default()
-    });
-    // bottom - white
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Cuboid::new(
-            box_size + 2.0 * box_thickness,
-            box_thickness,
-            box_size,
-        )),
-        material: materials.add(StandardMaterial {
-            base_color: Color::rgb(0.725, 0.71, 0.68),
-            ..Default::default()
-        }),
-        ..Default::default()
-    });
-    // top - white
-    let transform = Transform::from_xyz(0.0, 2.0 * box_offset, 0.0);
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Cuboid::new(
-            box_size + 2.0 * box_thickness,
-            box_thickness,
-            box_size,
-        )),
-        transform,
-        material: materials.add(StandardMaterial {
-            base_color: Color::rgb(0.725, 0.71, 0.68),
-            ..Default::default()
-        }),
-        ..Default::default()
-    });
-    // back - white
-    let mut transform = Transform::from_xyz(0.0, box_offset, -box_offset);
-    transform.rotate(Quat::from_rotation_x(std::f32::consts::FRAC_PI_2));
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Cuboid::new(
-            box_size + 2.0 * box_thickness,
-            box_thickness,
-            box_size + 2.0 * box_thickness,
-        )),
-        transform,
-        material: materials.add(StandardMaterial {
-            base_color: Color::rgb(0.725, 0.71, 0.68),
-            ..Default::default()
-        }),
-        ..Default::default()
-    });
-
-    // ambient light
-    commands.insert_resource(AmbientLight {
-        color: Color::WHITE,
-        brightness: 0.02,
-    });
-    // top light
-    commands
-        .spawn(PbrBundle {
-            mesh: meshes.add(Plane3d::default().mesh().size(0.4, 0.4)),
-            transform: Transform::from_matrix(Mat4::from_scale_rotation_translation(
-                Vec3::ONE,
-                Quat::from_rotation_x(std::f32::consts::PI),
-                Vec3::new(0.0, box_size + 0.5 * box_thickness, 0.0),
-            )),
-            material: materials.add(StandardMaterial {
-                base_color: Color::WHITE,
-                emissive: Color::WHITE * 100.0,
-                ..Default::default()
-            }),
-            ..Default::default()
-        })
-        .with_children(|builder| {
-            builder.spawn(PointLightBundle {
-                point_light: PointLight {
-                    color: Color::WHITE,
-                    intensity: 25000.0,
-                    ..Default::default()
-                },
-                transform: Transform::from_translation((box_thickness + 0.05) * Vec3::Y),
-                ..Default::default()
-            });
-        });
-    // directional light
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            illuminance: 2000.0,
-            ..default()
-        },
-        transform: Transform::from_rotation(Quat::from_rotation_x(-std::f32::consts::PI / 2.0)),
-        ..Default::default()
-    });
-
-    // camera
-    commands.spawn((
-        Camera3dBundle {
-            transform: Transform::from_xyz(0.0, box_offset, 4.0)
-                .looking_at(Vec3::new(0.0, box_offset, 0.0), Vec3::Y),
-            ..Default::default()
-        },
-        MainCamera,
-        // PickRaycastSource,
-    ));
-}
@@ -0,0 +1,830 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy::utils::HashMap;
+use bevy_egui::EguiUserTextures;
+use bevy_inspector_egui::egui;
+use futures_lite::future;
+
+use crate::generation::graph::{input_taken, NodeId, NodeKind, TerrainGraph, CONTINENTS_GRAPH};
+use crate::generation::noise::NoiseSettings;
+
+/// Pixels along each side of a node thumbnail.
+const THUMBNAIL_RESOLUTION: usize = 48;
+/// Noise units covered by half a thumbnail, centered on the noise origin.
+const THUMBNAIL_EXTENT: f64 = 2.0;
+
+const NODE_WIDTH: f32 = 140.0;
+const HEADER_HEIGHT: f32 = 20.0;
+const BODY_HEIGHT: f32 = 72.0;
+const PORT_RADIUS: f32 = 5.0;
+
+struct Thumbnail {
+    image: Handle<Image>,
+    texture: egui::TextureId,
+    /// Why the node can't be evaluated, if it can't.
+    error: Option<String>,
+}
+
+type ThumbnailData = Vec<(NodeId, Result<Vec<u8>, String>)>;
+
+/// Terrain graph open in the node editor.
+#[derive(Resource)]
+pub struct NodeGraphEditor {
+    graph: TerrainGraph,
+    handle: Handle<TerrainGraph>,
+    loaded: bool,
+    /// Where the graph is saved, relative to the asset folder.
+    path: String,
+    pan: egui::Vec2,
+    selected: Option<NodeId>,
+    /// Output port a wire is being dragged from.
+    wire: Option<NodeId>,
+    thumbnails: HashMap<NodeId, Thumbnail>,
+    task: Option<Task<ThumbnailData>>,
+    dirty: bool,
+    status: String,
+}
+
+pub fn setup_node_graph(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(NodeGraphEditor {
+        graph: TerrainGraph::default(),
+        handle: asset_server.load(CONTINENTS_GRAPH),
+        loaded: false,
+        path: CONTINENTS_GRAPH.to_string(),
+        pan: egui::Vec2::new(20.0, 20.0),
+        selected: None,
+        wire: None,
+        thumbnails: HashMap::default(),
+        task: None,
+        dirty: false,
+        status: String::new(),
+    });
+}
+
+/// Picks up the graph once loaded, and re-renders every thumbnail in the
+/// background after each change to the graph.
+pub fn update_node_graph(
+    mut editor: ResMut<NodeGraphEditor>,
+    graphs: Res<Assets<TerrainGraph>>,
+    mut images: ResMut<Assets<Image>>,
+    mut egui_textures: ResMut<EguiUserTextures>,
+) {
+    let editor = &mut *editor;
+    if !editor.loaded {
+        if let Some(graph) = graphs.get(&editor.handle) {
+            editor.graph = graph.clone();
+            editor.selected = None;
+            editor.loaded = true;
+            editor.dirty = true;
+        }
+    }
+
+    if let Some(task) = editor.task.as_mut() {
+        let Some(thumbnails) = block_on(future::poll_once(task)) else {
+            return;
+        };
+        editor.task = None;
+
+        // the nodes removed since the render don't get their thumbnail back
+        editor.thumbnails.retain(|id, thumbnail| {
+            let kept = thumbnails.iter().any(|(rendered, _)| rendered == id);
+            if !kept {
+                images.remove(&thumbnail.image);
+                egui_textures.remove_image(&thumbnail.image);
+            }
+            kept
+        });
+
+        for (id, data) in thumbnails {
+            let (data, error) = match data {
+                Ok(data) => (data, None),
+                Err(error) => (
+                    vec![0; THUMBNAIL_RESOLUTION * THUMBNAIL_RESOLUTION * 4],
+                    Some(error),
+                ),
+            };
+            match editor.thumbnails.get_mut(&id) {
+                Some(thumbnail) => {
+                    images.insert(thumbnail.image.clone(), thumbnail_image(data));
+                    thumbnail.error = error;
+                }
+                None => {
+                    let image = images.add(thumbnail_image(data));
+                    let texture = egui_textures.add_image(image.clone());
+                    editor.thumbnails.insert(
+                        id,
+                        Thumbnail {
+                            image,
+                            texture,
+                            error,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    if !editor.dirty {
+        return;
+    }
+
+    let graph = editor.graph.clone();
+    editor.task = Some(AsyncComputeTaskPool::get().spawn(async move { render_thumbnails(&graph) }));
+    editor.dirty = false;
+}
+
+fn render_thumbnails(graph: &TerrainGraph) -> ThumbnailData {
+    graph
+        .nodes
+        .iter()
+        .map(|node| (node.id, thumbnail_data(graph, node.id)))
+        .collect()
+}
+
+/// Grayscale value of the node, from -1 in black to 1 in white.
+fn thumbnail_data(graph: &TerrainGraph, id: NodeId) -> Result<Vec<u8>, String> {
+    let values = graph
+        .sample(
+            id,
+            THUMBNAIL_EXTENT,
+            THUMBNAIL_RESOLUTION,
+            [-THUMBNAIL_EXTENT, -THUMBNAIL_EXTENT],
+        )
+        .map_err(|error| error.to_string())?;
+
+    Ok(values
+        .into_iter()
+        .flat_map(|value| {
+            let gray = ((value.clamp(-1.0, 1.0) * 0.5 + 0.5) * 255.0) as u8;
+            [gray, gray, gray, 255]
+        })
+        .collect())
+}
+
+fn thumbnail_image(data: Vec<u8>) -> Image {
+    Image::new(
+        Extent3d {
+            width: THUMBNAIL_RESOLUTION as u32,
+            height: THUMBNAIL_RESOLUTION as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+/// Toolbar, canvas, and the parameters of the selected node.
+pub fn node_graph_ui(ui: &mut egui::Ui, world: &mut World) {
+    world.resource_scope::<NodeGraphEditor, _>(|world, mut editor| {
+        let editor = &mut *editor;
+
+        ui.horizontal(|ui| {
+            ui.label("assets/");
+            ui.text_edit_singleline(&mut editor.path);
+            if ui.button("Load").clicked() {
+                editor.handle = world.resource::<AssetServer>().load(editor.path.clone());
+                editor.loaded = false;
+                editor.status.clear();
+            }
+            if ui.button("Save").clicked() {
+                editor.status = match save_graph(&editor.graph, &editor.path) {
+                    Ok(()) => {
+                        // the handle of the path saved to, a new one on save as
+                        editor.handle = world.resource::<AssetServer>().load(editor.path.clone());
+                        world
+                            .resource_mut::<Assets<TerrainGraph>>()
+                            .insert(editor.handle.clone(), editor.graph.clone());
+                        format!("Saved {}", editor.path)
+                    }
+                    Err(error) => error,
+                };
+            }
+            ui.label(&editor.status);
+        });
+        ui.horizontal(|ui| {
+            let mut noise = world.resource_mut::<NoiseSettings>();
+            if ui
+                .button("Apply to terrain")
+                .on_hover_text("Regenerates the terrain from the output of this graph")
+                .clicked()
+            {
+                editor.status = match editor.graph.build_output() {
+                    Ok(_) => {
+                        noise.graph = Some(Arc::new(editor.graph.clone()));
+                        "Terrain generated from the graph".to_string()
+                    }
+                    Err(error) => format!("Could not apply the graph: {error}"),
+                };
+            }
+            if ui
+                .add_enabled(noise.graph.is_some(), egui::Button::new("Use the planet"))
+                .on_hover_text("Regenerates the terrain from the built-in planet noise")
+                .clicked()
+            {
+                noise.graph = None;
+                editor.status = "Terrain generated from the planet".to_string();
+            }
+        });
+        ui.separator();
+
+        let mut changed = false;
+        egui::SidePanel::right("node_graph_properties")
+            .resizable(true)
+            .default_width(220.0)
+            .show_inside(ui, |ui| {
+                changed |= properties_ui(ui, editor);
+            });
+        egui::CentralPanel::default().show_inside(ui, |ui| {
+            changed |= canvas_ui(ui, editor);
+        });
+
+        if changed {
+            editor.dirty = true;
+        }
+    });
+}
+
+fn save_graph(graph: &TerrainGraph, path: &str) -> Result<(), String> {
+    let text = graph
+        .to_ron()
+        .map_err(|error| format!("Could not serialize the graph: {error}"))?;
+    let path = Path::new("assets").join(path);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|error| format!("Could not write {}: {error}", path.display()))?;
+    }
+    std::fs::write(&path, text)
+        .map_err(|error| format!("Could not write {}: {error}", path.display()))
+}
+
+fn node_rect(position: [f32; 2], offset: egui::Vec2) -> egui::Rect {
+    egui::Rect::from_min_size(
+        egui::pos2(position[0], position[1]) + offset,
+        egui::vec2(NODE_WIDTH, HEADER_HEIGHT + BODY_HEIGHT),
+    )
+}
+
+fn input_port(rect: egui::Rect, index: usize, count: usize) -> egui::Pos2 {
+    let step = BODY_HEIGHT / (count + 1) as f32;
+    egui::pos2(
+        rect.left(),
+        rect.top() + HEADER_HEIGHT + step * (index + 1) as f32,
+    )
+}
+
+fn output_port(rect: egui::Rect) -> egui::Pos2 {
+    egui::pos2(rect.right(), rect.top() + HEADER_HEIGHT + BODY_HEIGHT / 2.0)
+}
+
+fn wire(painter: &egui::Painter, from: egui::Pos2, to: egui::Pos2, color: egui::Color32) {
+    let bend = ((to.x - from.x).abs() / 2.0).max(40.0);
+    painter.add(egui::epaint::CubicBezierShape::from_points_stroke(
+        [
+            from,
+            from + egui::vec2(bend, 0.0),
+            to - egui::vec2(bend, 0.0),
+            to,
+        ],
+        false,
+        egui::Color32::TRANSPARENT,
+        egui::Stroke::new(2.0, color),
+    ));
+}
+
+/// Nodes and their wires. Drag the background to pan, drag a node to move
+/// it, drag from an output to an input to connect them, click an input to
+/// unplug it, right click to add nodes. Returns true when the graph changed.
+fn canvas_ui(ui: &mut egui::Ui, editor: &mut NodeGraphEditor) -> bool {
+    let (response, painter) =
+        ui.allocate_painter(ui.available_size(), egui::Sense::click_and_drag());
+    let visuals = ui.visuals().clone();
+    let mut changed = false;
+
+    if response.dragged() && editor.wire.is_none() {
+        editor.pan += response.drag_delta();
+    }
+    if response.clicked() {
+        editor.selected = None;
+    }
+    let offset = response.rect.min.to_vec2() + editor.pan;
+
+    response.context_menu(|ui| {
+        ui.label("Add node");
+        ui.separator();
+        for kind in NodeKind::templates() {
+            if ui.button(kind.name()).clicked() {
+                let position = ui.min_rect().left_top() - offset;
+                let id = editor.graph.add_node(kind, [position.x, position.y]);
+                editor.selected = Some(id);
+                changed = true;
+                ui.close_menu();
+            }
+        }
+    });
+
+    // Move the nodes first so wires follow them this frame.
+    let mut unplugged = None;
+    for node in &mut editor.graph.nodes {
+        let rect = node_rect(node.position, offset);
+        let id = response.id.with(node.id);
+
+        let header = egui::Rect::from_min_size(rect.min, egui::vec2(NODE_WIDTH, HEADER_HEIGHT));
+        let node_response = ui.interact(header, id, egui::Sense::click_and_drag());
+        if node_response.dragged() {
+            node.position[0] += node_response.drag_delta().x;
+            node.position[1] += node_response.drag_delta().y;
+        }
+        if node_response.clicked() || node_response.drag_started() {
+            editor.selected = Some(node.id);
+        }
+
+        let output = output_port(rect);
+        let output_response = ui.interact(
+            egui::Rect::from_center_size(output, egui::Vec2::splat(PORT_RADIUS * 3.0)),
+            id.with("output"),
+            egui::Sense::drag(),
+        );
+        if output_response.drag_started() {
+            editor.wire = Some(node.id);
+        }
+
+        for index in 0..node.inputs.len() {
+            let port = input_port(rect, index, node.inputs.len());
+            let port_response = ui.interact(
+                egui::Rect::from_center_size(port, egui::Vec2::splat(PORT_RADIUS * 3.0)),
+                id.with(index),
+                egui::Sense::click(),
+            );
+            if port_response.clicked() && node.inputs[index].is_some() {
+                unplugged = Some((node.id, index));
+            }
+        }
+    }
+    if let Some((node, index)) = unplugged {
+        editor.graph.node_mut(node).unwrap().inputs[index] = None;
+        changed = true;
+    }
+
+    let rects: HashMap<NodeId, egui::Rect> = editor
+        .graph
+        .nodes
+        .iter()
+        .map(|node| (node.id, node_rect(node.position, offset)))
+        .collect();
+
+    let painter = painter.with_clip_rect(response.rect);
+    for node in &editor.graph.nodes {
+        for (index, source) in node.inputs.iter().enumerate() {
+            let Some(source) = source.and_then(|source| rects.get(&source)) else {
+                continue;
+            };
+            let to = input_port(rects[&node.id], index, node.inputs.len());
+            wire(
+                &painter,
+                output_port(*source),
+                to,
+                visuals.widgets.active.fg_stroke.color,
+            );
+        }
+    }
+
+    for node in &editor.graph.nodes {
+        let rect = rects[&node.id];
+        let selected = editor.selected == Some(node.id);
+        let is_output = editor.graph.output == Some(node.id);
+
+        painter.rect_filled(rect, 4.0, visuals.window_fill);
+        painter.rect_filled(
+            egui::Rect::from_min_size(rect.min, egui::vec2(NODE_WIDTH, HEADER_HEIGHT)),
+            4.0,
+            if is_output {
+                visuals.selection.bg_fill
+            } else {
+                visuals.faint_bg_color
+            },
+        );
+        painter.rect_stroke(
+            rect,
+            4.0,
+            if selected {
+                visuals.selection.stroke
+            } else {
+                visuals.window_stroke
+            },
+        );
+        painter.text(
+            rect.left_top() + egui::vec2(6.0, HEADER_HEIGHT / 2.0),
+            egui::Align2::LEFT_CENTER,
+            node.kind.name(),
+            egui::FontId::proportional(13.0),
+            visuals.text_color(),
+        );
+
+        let thumbnail_rect = egui::Rect::from_center_size(
+            rect.center_top() + egui::vec2(0.0, HEADER_HEIGHT + BODY_HEIGHT / 2.0),
+            egui::Vec2::splat(BODY_HEIGHT - 8.0),
+        );
+        match editor.thumbnails.get(&node.id) {
+            Some(Thumbnail {
+                error: None,
+                texture,
+                ..
+            }) => {
+                painter.image(
+                    *texture,
+                    thumbnail_rect,
+                    egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                    egui::Color32::WHITE,
+                );
+            }
+            Some(Thumbnail { error: Some(_), .. }) => {
+                painter.text(
+                    thumbnail_rect.center(),
+                    egui::Align2::CENTER_CENTER,
+                    "unconnected",
+                    egui::FontId::proportional(11.0),
+                    visuals.warn_fg_color,
+                );
+            }
+            None => {}
+        }
+
+        for (index, (input, name)) in node.inputs.iter().zip(node.kind.inputs()).enumerate() {
+            let port = input_port(rect, index, node.inputs.len());
+            let fill = if input.is_some() {
+                visuals.widgets.active.fg_stroke.color
+            } else {
+                visuals.widgets.inactive.bg_fill
+            };
+            painter.circle(port, PORT_RADIUS, fill, visuals.window_stroke);
+            painter.text(
+                port + egui::vec2(PORT_RADIUS + 2.0, 0.0),
+                egui::Align2::LEFT_CENTER,
+                name,
+                egui::FontId::proportional(10.0),
+                visuals.weak_text_color(),
+            );
+        }
+        painter.circle(
+            output_port(rect),
+            PORT_RADIUS,
+            visuals.widgets.active.fg_stroke.color,
+            visuals.window_stroke,
+        );
+    }
+
+    // Wire being dragged, connected to the input it is dropped on.
+    if let Some(source) = editor.wire {
+        let pointer = ui.input(|input| input.pointer.interact_pos());
+        if let (Some(from), Some(pointer)) = (rects.get(&source), pointer) {
+            wire(
+                &painter,
+                output_port(*from),
+                pointer,
+                visuals.selection.stroke.color,
+            );
+        }
+
+        if ui.input(|input| input.pointer.any_released()) {
+            editor.wire = None;
+            let target = pointer.and_then(|pointer| {
+                editor.graph.nodes.iter().find_map(|node| {
+                    (0..node.inputs.len())
+                        .find(|index| {
+                            let port = input_port(rects[&node.id], *index, node.inputs.len());
+                            port.distance(pointer) <= PORT_RADIUS * 2.0
+                        })
+                        .map(|index| (node.id, index))
+                })
+            });
+            if let Some((node, index)) = target {
+                changed |= editor.graph.connect(source, node, index);
+            }
+        }
+    }
+
+    if editor.graph.nodes.is_empty() {
+        painter.text(
+            response.rect.center(),
+            egui::Align2::CENTER_CENTER,
+            "Right click to add a node",
+            egui::FontId::proportional(14.0),
+            visuals.weak_text_color(),
+        );
+    }
+
+    changed
+}
+
+/// Parameters of the selected node. Returns true when the graph changed.
+fn properties_ui(ui: &mut egui::Ui, editor: &mut NodeGraphEditor) -> bool {
+    let Some(id) = editor.selected else {
+        ui.label("Select a node to edit it");
+        return false;
+    };
+    let error = editor
+        .thumbnails
+        .get(&id)
+        .and_then(|thumbnail| thumbnail.error.clone());
+    let is_output = editor.graph.output == Some(id);
+    let Some(node) = editor.graph.node_mut(id) else {
+        editor.selected = None;
+        return false;
+    };
+
+    let mut changed = false;
+    ui.heading(node.kind.name());
+    if let Some(error) = error {
+        ui.colored_label(ui.visuals().warn_fg_color, error);
+    }
+    ui.separator();
+
+    let (mut set_output, mut remove) = (false, false);
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        changed |= kind_ui(ui, &mut node.kind);
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            set_output = ui
+                .add_enabled(!is_output, egui::Button::new("Set as output"))
+                .on_hover_text("Use this node as the terrain height")
+                .clicked();
+            remove = ui.button("Remove").clicked();
+        });
+    });
+
+    if set_output {
+        editor.graph.output = Some(id);
+        changed = true;
+    }
+    if remove {
+        editor.graph.remove_node(id);
+        editor.selected = None;
+        changed = true;
+    }
+
+    changed
+}
+
+fn kind_ui(ui: &mut egui::Ui, kind: &mut NodeKind) -> bool {
+    let mut changed = false;
+    egui::Grid::new("node_parameters").show(ui, |ui| match kind {
+        NodeKind::Perlin { seed, frequency } | NodeKind::Worley { seed, frequency } => {
+            changed |= parameter(ui, "seed", egui::DragValue::new(seed));
+            changed |= parameter(ui, "frequency", egui::DragValue::new(frequency).speed(0.01));
+        }
+        NodeKind::Fbm {
+            seed,
+            frequency,
+            lacunarity,
+            persistence,
+            octaves,
+        }
+        | NodeKind::Billow {
+            seed,
+            frequency,
+            lacunarity,
+            persistence,
+            octaves,
+        } => {
+            changed |= parameter(ui, "seed", egui::DragValue::new(seed));
+            changed |= parameter(ui, "frequency", egui::DragValue::new(frequency).speed(0.01));
+            changed |= parameter(
+                ui,
+                "lacunarity",
+                egui::DragValue::new(lacunarity).speed(0.01),
+            );
+            changed |= parameter(
+                ui,
+                "persistence",
+                egui::DragValue::new(persistence).speed(0.01),
+            );
+            changed |= parameter(
+                ui,
+                "octaves",
+                egui::DragValue::new(octaves).clamp_range(1..=32),
+            );
+        }
+        NodeKind::RidgedMulti {
+            seed,
+            frequency,
+            lacunarity,
+            octaves,
+        } => {
+            changed |= parameter(ui, "seed", egui::DragValue::new(seed));
+            changed |= parameter(ui, "frequency", egui::DragValue::new(frequency).speed(0.01));
+            changed |= parameter(
+                ui,
+                "lacunarity",
+                egui::DragValue::new(lacunarity).speed(0.01),
+            );
+            changed |= parameter(
+                ui,
+                "octaves",
+                egui::DragValue::new(octaves).clamp_range(1..=32),
+            );
+        }
+        NodeKind::Constant { value } => {
+            changed |= parameter(ui, "value", egui::DragValue::new(value).speed(0.01));
+        }
+        NodeKind::Turbulence {
+            seed,
+            frequency,
+            power,
+            roughness,
+        } => {
+            changed |= parameter(ui, "seed", egui::DragValue::new(seed));
+            changed |= parameter(ui, "frequency", egui::DragValue::new(frequency).speed(0.1));
+            changed |= parameter(ui, "power", egui::DragValue::new(power).speed(0.0001));
+            changed |= parameter(
+                ui,
+                "roughness",
+                egui::DragValue::new(roughness).clamp_range(1..=16),
+            );
+        }
+        NodeKind::ScaleBias { scale, bias } => {
+            changed |= parameter(ui, "scale", egui::DragValue::new(scale).speed(0.01));
+            changed |= parameter(ui, "bias", egui::DragValue::new(bias).speed(0.01));
+        }
+        NodeKind::Clamp { lower, upper } => {
+            changed |= parameter(ui, "lower", egui::DragValue::new(lower).speed(0.01));
+            changed |= parameter(ui, "upper", egui::DragValue::new(upper).speed(0.01));
+        }
+        NodeKind::Exponent { exponent } => {
+            changed |= parameter(ui, "exponent", egui::DragValue::new(exponent).speed(0.01));
+        }
+        NodeKind::Select {
+            lower,
+            upper,
+            falloff,
+        } => {
+            changed |= parameter(ui, "lower", egui::DragValue::new(lower).speed(0.01));
+            changed |= parameter(ui, "upper", egui::DragValue::new(upper).speed(0.01));
+            changed |= parameter(ui, "falloff", egui::DragValue::new(falloff).speed(0.01));
+        }
+        NodeKind::Terrace { control_points, .. } => {
+            for index in 0..control_points.len() {
+                let mut point = control_points[index];
+                if parameter(ui, "point", egui::DragValue::new(&mut point).speed(0.01)) {
+                    // terrace drops a point whose input is taken
+                    let others = control_points
+                        .iter()
+                        .enumerate()
+                        .filter(|(other, _)| *other != index)
+                        .map(|(_, other)| *other);
+                    if !input_taken(others, point) {
+                        control_points[index] = point;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        NodeKind::Curve { .. }
+        | NodeKind::Add
+        | NodeKind::Multiply
+        | NodeKind::Min
+        | NodeKind::Max
+        | NodeKind::Blend
+        | NodeKind::Cache => {}
+    });
+
+    match kind {
+        NodeKind::Curve { control_points } => changed |= curve_editor(ui, control_points),
+        NodeKind::Terrace {
+            control_points,
+            inverted,
+        } => {
+            changed |= ui.checkbox(inverted, "inverted").changed();
+            ui.horizontal(|ui| {
+                if ui.button("Add point").clicked() {
+                    let highest = control_points.iter().copied().reduce(f64::max);
+                    control_points.push(highest.map_or(0.0, |highest| highest + 0.5));
+                    changed = true;
+                }
+                if ui
+                    .add_enabled(control_points.len() > 2, egui::Button::new("Remove point"))
+                    .clicked()
+                {
+                    control_points.pop();
+                    changed = true;
+                }
+            });
+        }
+        _ => {}
+    }
+
+    changed
+}
+
+fn parameter(ui: &mut egui::Ui, label: &str, widget: egui::DragValue) -> bool {
+    ui.label(label);
+    let changed = ui.add(widget).changed();
+    ui.end_row();
+    changed
+}
+
+/// Control points of a curve node on a plot, input along x and output along
+/// y. Drag a point to move it, double click to add one, right click a point
+/// to remove it. Returns true when the curve changed.
+fn curve_editor(ui: &mut egui::Ui, control_points: &mut Vec<[f64; 2]>) -> bool {
+    let size = egui::vec2(ui.available_width().max(120.0), 160.0);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::click());
+    let rect = response.rect.shrink(PORT_RADIUS * 2.0);
+    let visuals = ui.visuals().clone();
+
+    // Plot at least [-1, 1] in both directions.
+    let (mut min, mut max) = ([-1.0f64, -1.0], [1.0f64, 1.0]);
+    for point in control_points.iter() {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+    let to_screen = |point: [f64; 2]| {
+        egui::pos2(
+            rect.left() + ((point[0] - min[0]) / (max[0] - min[0])) as f32 * rect.width(),
+            rect.bottom() - ((point[1] - min[1]) / (max[1] - min[1])) as f32 * rect.height(),
+        )
+    };
+    let from_screen = |position: egui::Pos2| {
+        [
+            min[0] + ((position.x - rect.left()) / rect.width()) as f64 * (max[0] - min[0]),
+            min[1] + ((rect.bottom() - position.y) / rect.height()) as f64 * (max[1] - min[1]),
+        ]
+    };
+
+    painter.rect_filled(response.rect, 2.0, visuals.extreme_bg_color);
+    let axis_stroke = egui::Stroke::new(1.0, visuals.weak_text_color());
+    let origin = to_screen([0.0, 0.0]);
+    painter.hline(rect.x_range(), origin.y, axis_stroke);
+    painter.vline(origin.x, rect.y_range(), axis_stroke);
+    painter.add(egui::Shape::line(
+        control_points.iter().copied().map(to_screen).collect(),
+        egui::Stroke::new(2.0, visuals.widgets.active.fg_stroke.color),
+    ));
+
+    let mut changed = false;
+    let mut removed = None;
+    for index in 0..control_points.len() {
+        let point = control_points[index];
+        let center = to_screen(point);
+        let point_response = ui.interact(
+            egui::Rect::from_center_size(center, egui::Vec2::splat(PORT_RADIUS * 3.0)),
+            response.id.with(index),
+            egui::Sense::click_and_drag(),
+        );
+        if point_response.dragged() {
+            if let Some(position) = point_response.interact_pointer_pos() {
+                // points clamped to the same edge would share their input
+                let moved = from_screen(position.clamp(rect.min, rect.max));
+                let others = control_points
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .map(|(_, other)| other[0]);
+                if !input_taken(others, moved[0]) {
+                    control_points[index] = moved;
+                    changed = true;
+                }
+            }
+        }
+        if point_response.secondary_clicked() {
+            removed = Some(index);
+        }
+
+        let color = if point_response.hovered() || point_response.dragged() {
+            visuals.selection.stroke.color
+        } else {
+            visuals.text_color()
+        };
+        painter.circle_filled(center, PORT_RADIUS, color);
+        point_response.on_hover_text(format!("{:.4} -> {:.4}", point[0], point[1]));
+    }
+
+    // A curve needs four points.
+    if let Some(index) = removed.filter(|_| control_points.len() > 4) {
+        control_points.remove(index);
+        changed = true;
+    }
+    if response.double_clicked() {
+        if let Some(position) = response.interact_pointer_pos() {
+            let added = from_screen(position);
+            if !input_taken(control_points.iter().map(|point| point[0]), added[0]) {
+                control_points.push(added);
+                changed = true;
+            }
+        }
+    }
+    if changed {
+        control_points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+    }
+
+    changed
+}
@@ -0,0 +1,256 @@
+use std::ops::RangeInclusive;
+
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use bevy::reflect::{Struct, TypeInfo, Typed};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_egui::EguiUserTextures;
+use bevy_inspector_egui::egui;
+use futures_lite::future;
+
+use crate::generation::chunk::{ChunkLoader, CHUNK_WORLD_SCALE};
+use crate::generation::noise::{generate_noise_map, NoiseSettings, SEA_LEVEL};
+use crate::origin::WorldOrigin;
+
+/// Pixels along each side of the preview.
+const PREVIEW_RESOLUTION: usize = 192;
+
+/// Noise parameters being edited, and a heightmap of the area around the
+/// camera rendered with them.
+#[derive(Resource)]
+pub struct NoisePreview {
+    /// Applied to the world with the "Apply to world" button.
+    pub draft: NoiseSettings,
+    /// World units covered by each side of the preview.
+    pub size: f32,
+    image: Handle<Image>,
+    texture: egui::TextureId,
+    task: Option<Task<Image>>,
+    dirty: bool,
+}
+
+pub fn setup_noise_preview(
+    mut commands: Commands,
+    noise: Res<NoiseSettings>,
+    mut images: ResMut<Assets<Image>>,
+    mut egui_textures: ResMut<EguiUserTextures>,
+) {
+    let blank = vec![0; PREVIEW_RESOLUTION * PREVIEW_RESOLUTION * 4];
+    let image = images.add(preview_image(blank));
+    let texture = egui_textures.add_image(image.clone());
+
+    commands.insert_resource(NoisePreview {
+        draft: noise.clone(),
+        size: 8192.0,
+        image,
+        texture,
+        task: None,
+        dirty: true,
+    });
+}
+
+/// Re-renders the preview in the background after every change, one render
+/// at a time.
+pub fn update_noise_preview(
+    mut preview: ResMut<NoisePreview>,
+    mut images: ResMut<Assets<Image>>,
+    origin: Res<WorldOrigin>,
+    camera_query: Query<&Transform, With<ChunkLoader>>,
+) {
+    let preview = &mut *preview;
+    if let Some(task) = preview.task.as_mut() {
+        let Some(image) = block_on(future::poll_once(task)) else {
+            return;
+        };
+        images.insert(preview.image.clone(), image);
+        preview.task = None;
+    }
+
+    if !preview.dirty {
+        return;
+    }
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+
+    let center = origin.to_world_xz(camera.translation);
+    let settings = preview.draft.clone();
+    let size = preview.size as f64;
+    preview.task = Some(
+        AsyncComputeTaskPool::get().spawn(async move { render_preview(center, size, &settings) }),
+    );
+    preview.dirty = false;
+}
+
+/// Heightmap of the `size` world units square around `center`, with the
+/// same noise scale as the chunks.
+fn render_preview(center: DVec2, size: f64, settings: &NoiseSettings) -> Image {
+    let scale = CHUNK_WORLD_SCALE as f64;
+    let min = center - size / 2.0;
+    let noise_map = generate_noise_map(
+        size / scale,
+        PREVIEW_RESOLUTION,
+        PREVIEW_RESOLUTION,
+        [min.x * 2.0 / scale, min.y * 2.0 / scale],
+        settings,
+    );
+
+    let mut data = Vec::with_capacity(PREVIEW_RESOLUTION * PREVIEW_RESOLUTION * 4);
+    for y in 0..PREVIEW_RESOLUTION {
+        for x in 0..PREVIEW_RESOLUTION {
+            data.extend(height_color(noise_map.get_value(x, y)));
+        }
+    }
+
+    preview_image(data)
+}
+
+fn preview_image(data: Vec<u8>) -> Image {
+    Image::new(
+        Extent3d {
+            width: PREVIEW_RESOLUTION as u32,
+            height: PREVIEW_RESOLUTION as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+/// Blue under the sea level, then green, brown and white up the mountains.
+fn height_color(height: f64) -> [u8; 4] {
+    let stops: [(f64, [f32; 3]); 6] = [
+        (-1.0, [0.02, 0.05, 0.25]),
+        (SEA_LEVEL, [0.15, 0.45, 0.7]),
+        (SEA_LEVEL + 0.01, [0.8, 0.75, 0.5]),
+        (0.15, [0.25, 0.5, 0.2]),
+        (0.5, [0.45, 0.35, 0.25]),
+        (1.0, [1.0, 1.0, 1.0]),
+    ];
+
+    let height = height.clamp(-1.0, 1.0);
+    let upper = stops
+        .iter()
+        .position(|(position, _)| *position >= height)
+        .unwrap_or(stops.len() - 1)
+        .max(1);
+    let (from_position, from) = stops[upper - 1];
+    let (to_position, to) = stops[upper];
+    let t = ((height - from_position) / (to_position - from_position)).clamp(0.0, 1.0) as f32;
+
+    let channel = |i: usize| ((from[i] + (to[i] - from[i]) * t) * 255.0) as u8;
+    [channel(0), channel(1), channel(2), 255]
+}
+
+/// Slider bounds of the noise parameters.
+fn parameter_range(name: &str) -> RangeInclusive<f64> {
+    match name {
+        "continent_frequency" => 0.1..=4.0,
+        "shelf_level" => -1.0..=SEA_LEVEL,
+        "terrain_offset" => 0.0..=3.0,
+        "mountain_glaciation" => 1.0..=2.0,
+        "continent_height_scale" => 0.0..=1.0,
+        "river_depth" => 0.0..=0.1,
+        name if name.ends_with("_lacunarity") => 1.5..=2.5,
+        name if name.ends_with("_twist") => 0.0..=2.0,
+        _ => 0.0..=1.0,
+    }
+}
+
+/// Every planet parameter, with its doc comment as tooltip, next to the
+/// preview.
+pub fn noise_ui(ui: &mut egui::Ui, world: &mut World) {
+    world.resource_scope::<NoisePreview, _>(|world, mut preview| {
+        let mut changed = false;
+
+        ui.horizontal_top(|ui| {
+            ui.vertical(|ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    changed |= parameter_sliders(ui, &mut preview.draft);
+                });
+            });
+
+            ui.vertical(|ui| {
+                ui.image(egui::load::SizedTexture::new(
+                    preview.texture,
+                    [PREVIEW_RESOLUTION as f32 * 1.5; 2],
+                ));
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut preview.size, 1024.0..=65536.0)
+                            .logarithmic(true)
+                            .text("preview size"),
+                    )
+                    .changed();
+
+                let mut noise = world.resource_mut::<NoiseSettings>();
+                // the terrain graph is applied from the node editor
+                if preview.draft.graph != noise.graph {
+                    preview.draft.graph = noise.graph.clone();
+                    changed = true;
+                }
+                let applied = *noise == preview.draft;
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!applied, egui::Button::new("Apply to world"))
+                        .on_hover_text("Regenerates the terrain with these parameters")
+                        .clicked()
+                    {
+                        *noise = preview.draft.clone();
+                    }
+                    if ui
+                        .add_enabled(!applied, egui::Button::new("Revert"))
+                        .clicked()
+                    {
+                        preview.draft = noise.clone();
+                        changed = true;
+                    }
+                    if ui.button("Defaults").clicked() {
+                        preview.draft = NoiseSettings::default();
+                        changed = true;
+                    }
+                });
+            });
+        });
+
+        if changed {
+            preview.dirty = true;
+        }
+    });
+}
+
+fn parameter_sliders(ui: &mut egui::Ui, settings: &mut NoiseSettings) -> bool {
+    let TypeInfo::Struct(info) = NoiseSettings::type_info() else {
+        return false;
+    };
+
+    let mut changed = false;
+    egui::Grid::new("noise_parameters").show(ui, |ui| {
+        for (index, field) in info.iter().enumerate() {
+            let name = field.name();
+            let tooltip = field.docs().unwrap_or_default();
+            let label = name.replace('_', " ");
+
+            let Some(value) = settings.field_at_mut(index) else {
+                continue;
+            };
+            ui.label(&label).on_hover_text(tooltip);
+
+            let response = if let Some(seed) = value.downcast_mut::<u32>() {
+                ui.add(egui::DragValue::new(seed))
+            } else if let Some(value) = value.downcast_mut::<f64>() {
+                ui.add(egui::Slider::new(value, parameter_range(name)))
+            } else {
+                continue;
+            };
+            changed |= response.on_hover_text(tooltip).changed();
+            ui.end_row();
+        }
+    });
+
+    changed
+}
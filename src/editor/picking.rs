@@ -0,0 +1,122 @@
+use bevy::math::{DVec3, I64Vec2};
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_inspector::hierarchy::SelectedEntities;
+use bevy_inspector_egui::egui;
+use bevy_rapier3d::prelude::*;
+
+use crate::camera::MainCamera;
+use crate::origin::WorldOrigin;
+
+use super::placement::Placed;
+
+/// Farthest a click in the viewport picks, the camera far plane.
+const PICK_DISTANCE: f32 = 20000.0;
+
+/// Where the last click in the viewport hit.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct TerrainPick {
+    /// Entity selected by the click.
+    pub entity: Entity,
+    /// Absolute position of the hit.
+    pub position: DVec3,
+    pub normal: Vec3,
+    /// Absolute coordinates of the chunk under the hit.
+    pub chunk: I64Vec2,
+}
+
+/// Casts a ray from `cursor`, in the viewport tab, to whatever collider is
+/// under it.
+pub fn pick(world: &mut World, viewport: egui::Rect, cursor: egui::Pos2) -> Option<TerrainPick> {
+    let egui_scale = world.resource::<bevy_egui::EguiSettings>().scale_factor;
+    let (camera, camera_transform) = world
+        .query_filtered::<(&Camera, &GlobalTransform), With<MainCamera>>()
+        .get_single(world)
+        .ok()?;
+
+    // egui points to logical pixels, from the corner of the viewport
+    let position = (cursor - viewport.left_top()) * egui_scale;
+    let ray = camera.viewport_to_world(camera_transform, Vec2::new(position.x, position.y))?;
+
+    let (entity, hit) = world.resource::<RapierContext>().cast_ray_and_get_normal(
+        ray.origin,
+        *ray.direction,
+        PICK_DISTANCE,
+        true,
+        QueryFilter::default(),
+    )?;
+
+    let origin = world.resource::<WorldOrigin>();
+    Some(TerrainPick {
+        entity,
+        position: origin.to_world(hit.point),
+        normal: hit.normal,
+        chunk: origin.chunk_at(hit.point),
+    })
+}
+
+/// Selects what is under the cursor: the chunk for the terrain, the prop
+/// itself for props so it can be moved with the gizmo. Clicking the sky
+/// clears the selection.
+pub fn select_under_cursor(
+    world: &mut World,
+    viewport: egui::Rect,
+    cursor: egui::Pos2,
+    selected: &mut SelectedEntities,
+) {
+    let Some(pick) = pick(world, viewport, cursor) else {
+        selected.clear();
+        world.remove_resource::<TerrainPick>();
+        return;
+    };
+
+    // chunks and props are their own colliders, placed models have them on
+    // their meshes
+    let mut entity = pick.entity;
+    let mut ancestor = Some(entity);
+    while let Some(current) = ancestor {
+        if world.get::<Placed>(current).is_some() {
+            entity = current;
+            break;
+        }
+        ancestor = world.get::<Parent>(current).map(Parent::get);
+    }
+    selected.select_replace(entity);
+    world.insert_resource(TerrainPick { entity, ..pick });
+}
+
+/// Details of the last pick, when it hit the selected entity.
+pub fn pick_ui(ui: &mut egui::Ui, world: &World, selected: &SelectedEntities) {
+    let Some(pick) = world.get_resource::<TerrainPick>() else {
+        return;
+    };
+    if !selected.contains(pick.entity) {
+        return;
+    }
+
+    ui.collapsing("Picked", |ui| {
+        egui::Grid::new("picked").num_columns(2).show(ui, |ui| {
+            ui.label("Position");
+            ui.label(format!(
+                "{:.2}, {:.2}, {:.2}",
+                pick.position.x, pick.position.y, pick.position.z
+            ));
+            ui.end_row();
+
+            ui.label("Height");
+            ui.label(format!("{:.2}", pick.position.y));
+            ui.end_row();
+
+            ui.label("Normal");
+            ui.label(format!(
+                "{:.3}, {:.3}, {:.3}",
+                pick.normal.x, pick.normal.y, pick.normal.z
+            ));
+            ui.end_row();
+
+            ui.label("Chunk");
+            ui.label(format!("{}, {}", pick.chunk.x, pick.chunk.y));
+            ui.end_row();
+        });
+    });
+    ui.separator();
+}
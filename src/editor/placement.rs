@@ -0,0 +1,242 @@
+use bevy::prelude::*;
+use bevy::render::primitives::Aabb;
+use bevy_inspector_egui::egui;
+use bevy_rapier3d::prelude::*;
+
+use crate::generation::chunk::{surface_height, Chunk};
+use crate::generation::edits::TerrainEdits;
+use crate::generation::noise::NoiseSettings;
+use crate::generation::quadtree::QuadtreeNode;
+use crate::origin::WorldOrigin;
+
+use super::picking::pick;
+
+/// Height from which the ground under an object is looked for.
+const GROUND_RAY_HEIGHT: f32 = 10000.0;
+/// The fox model is in centimeters.
+const FOX_SCALE: f32 = 0.02;
+const FOX_SCENE: &str = "models/Fox.glb#Scene0";
+
+/// How objects moved with the gizmo or dropped from the palette are placed.
+pub struct Placement {
+    /// Keeps the bottom of the translated entity on the terrain under it.
+    pub snap_to_ground: bool,
+    /// Tilts snapped entities to the terrain slope, keeping their heading.
+    pub align_to_normal: bool,
+    /// Path of the glTF file offered in the palette, in the asset folder.
+    pub gltf_path: String,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement {
+            snap_to_ground: true,
+            align_to_normal: false,
+            gltf_path: "models/Fox.glb".to_string(),
+        }
+    }
+}
+
+/// Root of an object placed from the palette. Clicking any of its meshes
+/// selects it.
+#[derive(Component)]
+pub struct Placed;
+
+/// Objects that can be dragged from the palette to the viewport.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PaletteItem {
+    Sphere,
+    Cube,
+    Fox,
+    /// First scene of a glTF file, by asset path.
+    Gltf(String),
+}
+
+impl PaletteItem {
+    fn label(&self) -> String {
+        match self {
+            PaletteItem::Sphere => "Sphere".to_string(),
+            PaletteItem::Cube => "Cube".to_string(),
+            PaletteItem::Fox => "Fox".to_string(),
+            PaletteItem::Gltf(path) => path.clone(),
+        }
+    }
+
+    /// Distance from the origin of the object to its bottom, which the
+    /// bounding boxes only tell once the object is spawned.
+    fn base_height(&self) -> f32 {
+        match self {
+            PaletteItem::Sphere | PaletteItem::Cube => 1.0,
+            // models have their origin at their feet
+            PaletteItem::Fox | PaletteItem::Gltf(_) => 0.0,
+        }
+    }
+
+    fn spawn(&self, world: &mut World, transform: Transform) -> Entity {
+        let (mesh, color, collider) = match self {
+            PaletteItem::Sphere => (
+                Mesh::from(Sphere::new(1.0)),
+                Color::GRAY,
+                Collider::ball(1.0),
+            ),
+            PaletteItem::Cube => (
+                Mesh::from(Cuboid::new(2.0, 2.0, 2.0)),
+                Color::ORANGE,
+                Collider::cuboid(1.0, 1.0, 1.0),
+            ),
+            PaletteItem::Fox => {
+                return spawn_scene(world, FOX_SCENE.to_string(), transform, FOX_SCALE);
+            }
+            PaletteItem::Gltf(path) => {
+                let path = if path.contains('#') {
+                    path.clone()
+                } else {
+                    format!("{path}#Scene0")
+                };
+                return spawn_scene(world, path, transform, 1.0);
+            }
+        };
+
+        let mesh = world.resource_mut::<Assets<Mesh>>().add(mesh);
+        let material = world.resource_mut::<Assets<StandardMaterial>>().add(color);
+        world
+            .spawn((
+                PbrBundle {
+                    mesh,
+                    material,
+                    transform,
+                    ..default()
+                },
+                collider,
+                Placed,
+                Name::new(self.label()),
+            ))
+            .id()
+    }
+}
+
+fn spawn_scene(world: &mut World, path: String, transform: Transform, scale: f32) -> Entity {
+    let name = Name::new(path.clone());
+    let scene = world.resource::<AssetServer>().load(path);
+    world
+        .spawn((
+            SceneBundle {
+                scene,
+                transform: transform.with_scale(Vec3::splat(scale)),
+                ..default()
+            },
+            // colliders for its meshes once loaded, so it can be picked
+            AsyncSceneCollider::default(),
+            Placed,
+            name,
+        ))
+        .id()
+}
+
+/// Placement options, and the objects to drag onto the terrain.
+pub fn palette_ui(ui: &mut egui::Ui, placement: &mut Placement) {
+    ui.checkbox(&mut placement.snap_to_ground, "Snap to ground");
+    ui.add_enabled(
+        placement.snap_to_ground,
+        egui::Checkbox::new(&mut placement.align_to_normal, "Align to slope"),
+    );
+    ui.separator();
+
+    ui.label("Drag onto the terrain");
+    let items = [PaletteItem::Sphere, PaletteItem::Cube, PaletteItem::Fox];
+    for item in items {
+        palette_item(ui, item);
+    }
+
+    ui.horizontal(|ui| {
+        palette_item(ui, PaletteItem::Gltf(placement.gltf_path.clone()));
+        ui.text_edit_singleline(&mut placement.gltf_path);
+    });
+}
+
+fn palette_item(ui: &mut egui::Ui, item: PaletteItem) {
+    let id = egui::Id::new("palette").with(item.label());
+    let label = match &item {
+        PaletteItem::Gltf(_) => "glTF".to_string(),
+        item => item.label(),
+    };
+    ui.dnd_drag_source(id, item, |ui| ui.label(label));
+}
+
+/// Spawns `item` on whatever is under `cursor`, returning it to be selected.
+pub fn drop_item(
+    world: &mut World,
+    item: &PaletteItem,
+    placement: &Placement,
+    viewport: egui::Rect,
+    cursor: egui::Pos2,
+) -> Option<Entity> {
+    let hit = pick(world, viewport, cursor)?;
+    let up = if placement.align_to_normal {
+        hit.normal
+    } else {
+        Vec3::Y
+    };
+
+    let point = world.resource::<WorldOrigin>().to_local(hit.position);
+    let transform = Transform::from_translation(point + up * item.base_height())
+        .with_rotation(Quat::from_rotation_arc(Vec3::Y, up));
+    Some(item.spawn(world, transform))
+}
+
+/// Terrain surface, and its normal, under `position`. Far from the camera,
+/// where chunks have no colliders, it is sampled from the noise.
+pub fn ground_under(world: &World, position: Vec3) -> (Vec3, Vec3) {
+    let is_terrain = |entity: Entity| {
+        world
+            .get_entity(entity)
+            .is_some_and(|entity| entity.contains::<Chunk>() || entity.contains::<QuadtreeNode>())
+    };
+
+    let ray_origin = Vec3::new(position.x, GROUND_RAY_HEIGHT, position.z);
+    let hit = world.resource::<RapierContext>().cast_ray_and_get_normal(
+        ray_origin,
+        Vec3::NEG_Y,
+        2.0 * GROUND_RAY_HEIGHT,
+        true,
+        QueryFilter::only_fixed().predicate(&is_terrain),
+    );
+    if let Some((_, hit)) = hit {
+        return (hit.point, hit.normal);
+    }
+
+    let absolute = world.resource::<WorldOrigin>().to_world_xz(position);
+    let height = surface_height(
+        absolute,
+        world.resource::<NoiseSettings>(),
+        world.resource::<TerrainEdits>(),
+    );
+    (Vec3::new(position.x, height, position.z), Vec3::Y)
+}
+
+/// Moves a world space `transform` of `entity` down or up to the ground,
+/// with its bottom touching it.
+pub fn snap_to_ground(
+    world: &World,
+    entity: Entity,
+    transform: Transform,
+    placement: &Placement,
+) -> Transform {
+    let (ground, normal) = ground_under(world, transform.translation);
+
+    // mesh entities know how far their bottom is, scenes start at their feet
+    let base = world
+        .get::<Aabb>(entity)
+        .map_or(0.0, |aabb| -aabb.min().y * transform.scale.y);
+
+    if !placement.align_to_normal {
+        return transform.with_translation(ground + Vec3::Y * base);
+    }
+
+    let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+    Transform {
+        translation: ground + normal * base,
+        rotation: Quat::from_rotation_arc(Vec3::Y, normal) * Quat::from_rotation_y(yaw),
+        scale: transform.scale,
+    }
+}
@@ -0,0 +1,490 @@
+use bevy::math::I64Vec2;
+use bevy::prelude::*;
+use bevy::render::mesh::MeshVertexAttribute;
+use bevy::render::render_resource::VertexFormat;
+use bevy_rapier3d::geometry::Collider;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use super::chunk::{Chunk, CHUNK_WORLD_SCALE, HEIGHT_INTENSITY};
+use super::noise::SEA_LEVEL;
+
+/// Biome ID of every vertex, stored on the chunk meshes next to the colors.
+pub const ATTRIBUTE_BIOME: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_Biome", 988540917, VertexFormat::Uint32);
+
+const TEMPERATURE_SEED: u32 = 4211;
+const MOISTURE_SEED: u32 = 8779;
+
+/// Frequency of the temperature and moisture noise. Lower values produce
+/// larger biomes, one noise unit covers a bit more than two chunks.
+const CLIMATE_FREQUENCY: f64 = 0.35;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub enum Biome {
+    Ocean,
+    Beach,
+    Desert,
+    #[default]
+    Grassland,
+    Forest,
+    Taiga,
+    Tundra,
+    Snow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum GroundMaterial {
+    Grass,
+    Rock,
+    Sand,
+    Snow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum PropKind {
+    Tree,
+    Pine,
+    Cactus,
+    Shrub,
+    Rock,
+}
+
+/// Describes how often a prop appears on the vertices of a biome.
+#[derive(Clone, Copy, Debug, Reflect)]
+pub struct PropRule {
+    pub kind: PropKind,
+    /// Probability for a vertex to hold this prop.
+    pub density: f32,
+    /// Props are not placed on faces steeper than this, in radians.
+    pub max_slope: f32,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 8] = [
+        Biome::Ocean,
+        Biome::Beach,
+        Biome::Desert,
+        Biome::Grassland,
+        Biome::Forest,
+        Biome::Taiga,
+        Biome::Tundra,
+        Biome::Snow,
+    ];
+
+    pub fn id(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_id(id: u32) -> Option<Biome> {
+        Biome::ALL.get(id as usize).copied()
+    }
+
+    pub fn color(self) -> [f32; 4] {
+        match self {
+            Biome::Ocean => [0.1, 0.3, 0.9, 1.0],
+            Biome::Beach => [0.93, 0.85, 0.6, 1.0],
+            Biome::Desert => [0.87, 0.72, 0.42, 1.0],
+            Biome::Grassland => [0.2, 0.9, 0.1, 1.0],
+            Biome::Forest => [0.1, 0.55, 0.12, 1.0],
+            Biome::Taiga => [0.2, 0.45, 0.35, 1.0],
+            Biome::Tundra => [0.55, 0.6, 0.5, 1.0],
+            Biome::Snow => [0.8, 1.0, 0.9, 1.0],
+        }
+    }
+
+    pub fn ground(self) -> GroundMaterial {
+        match self {
+            Biome::Ocean | Biome::Beach | Biome::Desert => GroundMaterial::Sand,
+            Biome::Grassland | Biome::Forest | Biome::Taiga => GroundMaterial::Grass,
+            Biome::Tundra => GroundMaterial::Rock,
+            Biome::Snow => GroundMaterial::Snow,
+        }
+    }
+
+    pub fn props(self) -> &'static [PropRule] {
+        const MAX_SLOPE: f32 = 0.6;
+
+        match self {
+            Biome::Ocean | Biome::Snow => &[],
+            Biome::Beach => &[PropRule {
+                kind: PropKind::Rock,
+                density: 0.004,
+                max_slope: MAX_SLOPE,
+            }],
+            Biome::Desert => &[
+                PropRule {
+                    kind: PropKind::Cactus,
+                    density: 0.01,
+                    max_slope: MAX_SLOPE,
+                },
+                PropRule {
+                    kind: PropKind::Rock,
+                    density: 0.005,
+                    max_slope: 1.2,
+                },
+            ],
+            Biome::Grassland => &[
+                PropRule {
+                    kind: PropKind::Shrub,
+                    density: 0.02,
+                    max_slope: MAX_SLOPE,
+                },
+                PropRule {
+                    kind: PropKind::Tree,
+                    density: 0.005,
+                    max_slope: MAX_SLOPE,
+                },
+            ],
+            Biome::Forest => &[PropRule {
+                kind: PropKind::Tree,
+                density: 0.06,
+                max_slope: MAX_SLOPE,
+            }],
+            Biome::Taiga => &[PropRule {
+                kind: PropKind::Pine,
+                density: 0.05,
+                max_slope: MAX_SLOPE,
+            }],
+            Biome::Tundra => &[PropRule {
+                kind: PropKind::Rock,
+                density: 0.01,
+                max_slope: 1.2,
+            }],
+        }
+    }
+}
+
+/// Whittaker-style classification of the land biomes.
+///
+/// `temperature_bands` and `moisture_bands` hold the upper bounds (in 0..1)
+/// of every row and column of `grid`, from cold to hot and from dry to wet.
+/// Values above the last bound fall in the last row or column.
+#[derive(Resource, Clone, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct BiomeTable {
+    pub temperature_bands: Vec<f32>,
+    pub moisture_bands: Vec<f32>,
+    pub grid: Vec<Vec<Biome>>,
+
+    /// Terrain height under which vertices are ocean, in mesh units. The water
+    /// surface is placed at the same height.
+    pub sea_level: f32,
+    /// Height band above `sea_level` covered by beaches.
+    pub beach_height: f32,
+    /// Terrain height above which everything is snow.
+    pub snow_height: f32,
+
+    /// Temperature lost per mesh unit above sea level.
+    pub lapse_rate: f32,
+    /// Direction the wind blows to, on the chunk grid. Mountains cast a rain
+    /// shadow on their downwind side.
+    pub wind_direction: Vec2,
+    /// Number of grid samples looked at upwind of every vertex.
+    pub rain_shadow_distance: usize,
+    /// Moisture lost per mesh unit of terrain standing upwind.
+    pub rain_shadow_strength: f32,
+}
+
+impl Default for BiomeTable {
+    fn default() -> Self {
+        use Biome::*;
+
+        BiomeTable {
+            temperature_bands: vec![0.2, 0.4, 0.7, 1.0],
+            moisture_bands: vec![0.33, 0.66, 1.0],
+            grid: vec![
+                // dry, average, wet
+                vec![Tundra, Tundra, Snow],
+                vec![Tundra, Taiga, Taiga],
+                vec![Grassland, Grassland, Forest],
+                vec![Desert, Grassland, Forest],
+            ],
+            sea_level: SEA_LEVEL as f32 * HEIGHT_INTENSITY,
+            beach_height: 0.01,
+            snow_height: 0.06,
+            lapse_rate: 4.0,
+            wind_direction: Vec2::X,
+            rain_shadow_distance: 8,
+            rain_shadow_strength: 6.0,
+        }
+    }
+}
+
+impl BiomeTable {
+    pub fn classify(&self, height: f32, temperature: f32, moisture: f32) -> Biome {
+        if height < self.sea_level {
+            return Biome::Ocean;
+        }
+        if height > self.snow_height {
+            return Biome::Snow;
+        }
+        if height < self.sea_level + self.beach_height {
+            return Biome::Beach;
+        }
+
+        let row = band(&self.temperature_bands, temperature);
+        let column = band(&self.moisture_bands, moisture);
+
+        self.grid
+            .get(row)
+            .and_then(|row| row.get(column))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+fn band(bounds: &[f32], value: f32) -> usize {
+    bounds
+        .iter()
+        .position(|bound| value <= *bound)
+        .unwrap_or(bounds.len().saturating_sub(1))
+}
+
+/// Biome of every vertex of a chunk, in the same order as the mesh vertices.
+pub struct BiomeMap {
+    pub width: usize,
+    pub depth: usize,
+    pub biomes: Vec<Biome>,
+}
+
+impl BiomeMap {
+    /// Computes the biome of a `(width + 1) * (depth + 1)` grid of heights.
+    ///
+    /// `origin` and `step` give the position of the grid in noise space, so
+    /// that temperature and moisture line up between chunks.
+    pub fn generate(
+        table: &BiomeTable,
+        heights: &[f32],
+        width: usize,
+        depth: usize,
+        origin: [f64; 2],
+        step: f64,
+    ) -> BiomeMap {
+        let temperature_noise = Fbm::<Perlin>::new(TEMPERATURE_SEED)
+            .set_frequency(CLIMATE_FREQUENCY)
+            .set_octaves(4);
+        let moisture_noise = Fbm::<Perlin>::new(MOISTURE_SEED)
+            .set_frequency(CLIMATE_FREQUENCY * 1.5)
+            .set_octaves(4);
+
+        let wind = table.wind_direction.normalize_or_zero();
+        let mut biomes = Vec::with_capacity(heights.len());
+
+        for d in 0..=depth {
+            for w in 0..=width {
+                let height = heights[d * (width + 1) + w];
+                let point = [origin[0] + w as f64 * step, origin[1] + d as f64 * step];
+
+                let temperature = (temperature_noise.get(point) as f32 + 1.0) / 2.0
+                    - (height - table.sea_level).max(0.0) * table.lapse_rate;
+
+                // terrain standing between the wind and this vertex holds the rain back
+                let mut upwind_height = height;
+                for i in 1..=table.rain_shadow_distance {
+                    let x = w as f32 - wind.x * i as f32;
+                    let z = d as f32 - wind.y * i as f32;
+                    if x < 0.0 || z < 0.0 || x > width as f32 || z > depth as f32 {
+                        break;
+                    }
+                    let index = z.round() as usize * (width + 1) + x.round() as usize;
+                    upwind_height = upwind_height.max(heights[index]);
+                }
+                let moisture = (moisture_noise.get(point) as f32 + 1.0) / 2.0
+                    - (upwind_height - height) * table.rain_shadow_strength;
+
+                biomes.push(table.classify(height, temperature, moisture));
+            }
+        }
+
+        BiomeMap {
+            width,
+            depth,
+            biomes,
+        }
+    }
+
+    pub fn get(&self, w: usize, d: usize) -> Biome {
+        self.biomes[d * (self.width + 1) + w]
+    }
+
+    /// Most common biome of the map.
+    pub fn dominant(&self) -> Biome {
+        let mut counts = [0usize; Biome::ALL.len()];
+        for biome in &self.biomes {
+            counts[biome.id() as usize] += 1;
+        }
+
+        let (id, _) = counts
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, count)| **count)
+            .unwrap();
+
+        Biome::ALL[id]
+    }
+}
+
+/// Prop placed on a chunk, in chunk mesh space.
+#[derive(Clone, Debug)]
+pub struct PropInstance {
+    pub kind: PropKind,
+    pub position: Vec3,
+}
+
+/// Applies the prop rules of every vertex biome. Placement is hashed from
+/// the chunk coordinates so a chunk always gets the same props back.
+pub fn scatter_props(
+    biomes: &BiomeMap,
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    coords: I64Vec2,
+) -> Vec<PropInstance> {
+    let mut props = Vec::new();
+
+    // the last row and column are not part of the rendered surface
+    for d in 0..biomes.depth {
+        for w in 0..biomes.width {
+            let index = d * (biomes.width + 1) + w;
+            let slope = Vec3::from(normals[index]).angle_between(Vec3::Y);
+
+            for (rule_index, rule) in biomes.get(w, d).props().iter().enumerate() {
+                if slope > rule.max_slope {
+                    continue;
+                }
+                if hash(coords, index, rule_index) < rule.density {
+                    props.push(PropInstance {
+                        kind: rule.kind,
+                        position: Vec3::from(positions[index]),
+                    });
+                    break;
+                }
+            }
+        }
+    }
+
+    props
+}
+
+fn hash(coords: I64Vec2, index: usize, salt: usize) -> f32 {
+    let mut h = (coords.x as u32).wrapping_mul(0x9E37_79B9)
+        ^ (coords.y as u32).wrapping_mul(0x85EB_CA6B)
+        ^ (index as u32).wrapping_mul(0xC2B2_AE35)
+        ^ (salt as u32).wrapping_mul(0x27D4_EB2F);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297A_2D39);
+    h ^= h >> 15;
+
+    h as f32 / u32::MAX as f32
+}
+
+#[derive(Component)]
+pub struct Prop {
+    pub kind: PropKind,
+}
+
+#[derive(Resource)]
+pub struct PropAssets {
+    meshes: Vec<(PropKind, Handle<Mesh>, Handle<StandardMaterial>)>,
+}
+
+impl PropAssets {
+    fn get(&self, kind: PropKind) -> (Handle<Mesh>, Handle<StandardMaterial>) {
+        let (_, mesh, material) = self.meshes.iter().find(|(k, ..)| *k == kind).unwrap();
+        (mesh.clone(), material.clone())
+    }
+}
+
+impl PropKind {
+    /// Height of the prop in world units, used to stand it on the ground.
+    fn height(self) -> f32 {
+        match self {
+            PropKind::Tree => 6.0,
+            PropKind::Pine => 8.0,
+            PropKind::Cactus => 3.0,
+            PropKind::Shrub => 1.0,
+            PropKind::Rock => 1.0,
+        }
+    }
+
+    fn collider(self) -> Collider {
+        match self {
+            PropKind::Tree => Collider::capsule_y(2.0, 1.0),
+            PropKind::Pine => Collider::cylinder(4.0, 0.8),
+            PropKind::Cactus => Collider::capsule_y(1.1, 0.4),
+            PropKind::Shrub => Collider::ball(0.5),
+            PropKind::Rock => Collider::cuboid(0.8, 0.5, 0.6),
+        }
+    }
+}
+
+pub fn setup_props(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut prop =
+        |kind: PropKind, mesh: Mesh, color: Color| (kind, meshes.add(mesh), materials.add(color));
+
+    commands.insert_resource(PropAssets {
+        meshes: vec![
+            prop(
+                PropKind::Tree,
+                Capsule3d::new(1.0, 4.0).into(),
+                Color::rgb(0.15, 0.45, 0.1),
+            ),
+            prop(
+                PropKind::Pine,
+                Cylinder::new(0.8, 8.0).into(),
+                Color::rgb(0.1, 0.3, 0.2),
+            ),
+            prop(
+                PropKind::Cactus,
+                Capsule3d::new(0.4, 2.2).into(),
+                Color::rgb(0.4, 0.6, 0.2),
+            ),
+            prop(
+                PropKind::Shrub,
+                Sphere::new(0.5).into(),
+                Color::rgb(0.3, 0.5, 0.15),
+            ),
+            prop(
+                PropKind::Rock,
+                Cuboid::new(1.6, 1.0, 1.2).into(),
+                Color::rgb(0.45, 0.43, 0.4),
+            ),
+        ],
+    });
+}
+
+/// Spawns the props of new chunks as children, so they go away with them.
+pub fn spawn_chunk_props(
+    mut commands: Commands,
+    chunks: Query<(Entity, &Chunk), Added<Chunk>>,
+    prop_assets: Res<PropAssets>,
+) {
+    for (entity, chunk) in chunks.iter() {
+        commands.entity(entity).with_children(|parent| {
+            for prop in &chunk.props {
+                let (mesh, material) = prop_assets.get(prop.kind);
+
+                // chunks are scaled up, undo it so props keep their world size
+                let mut transform = Transform::from_translation(prop.position)
+                    .with_scale(Vec3::splat(1.0 / CHUNK_WORLD_SCALE));
+                transform.translation.y += prop.kind.height() / 2.0 / CHUNK_WORLD_SCALE;
+
+                parent.spawn((
+                    PbrBundle {
+                        mesh,
+                        material,
+                        transform,
+                        ..default()
+                    },
+                    prop.kind.collider(),
+                    Prop { kind: prop.kind },
+                ));
+            }
+        });
+    }
+}
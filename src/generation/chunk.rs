@@ -1,37 +1,75 @@
+use bevy::diagnostic::Diagnostics;
+use bevy::ecs::system::SystemParam;
+use bevy::math::{DVec2, I64Vec2};
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
 use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
-use bevy::{pbr::wireframe::Wireframe, prelude::*};
-use bevy_flycam::FlyCam;
+use bevy::utils::Instant;
 use bevy_rapier3d::{
     dynamics::RigidBody,
     geometry::{Collider, ComputedColliderShape},
 };
 use futures_lite::future;
 
-use super::mesh::create_mesh;
+use crate::origin::WorldOrigin;
 
-const CHUNK_WORLD_SCALE: f32 = 512.0;
-const CHUNK_WORLD_SIZE: f32 = 112.0;
+use super::biome::{Biome, BiomeTable, PropInstance, ATTRIBUTE_BIOME};
+use super::color_ramp::{TerrainColorRamp, TerrainColoring};
+use super::diagnostics::record_chunk_timings;
+use super::edits::TerrainEdits;
+use super::horizon::{Horizon, HorizonTask};
+use super::material::TerrainMaterialHandle;
+use super::mesh::{create_mesh, MeshTimings, TerrainMeshing};
+use super::noise::{generate_noise_map, NoiseSettings};
+use super::quadtree::{QuadtreeNode, QuadtreeNodeTask, QuadtreeSettings};
 
-const FAR_LOD: usize = 16;
-const NORMAL_LOD: usize = 32;
+pub const CHUNK_WORLD_SCALE: f32 = 512.0;
+pub const CHUNK_WORLD_SIZE: f32 = 112.0;
 
-const TERRAIN_ALPHA: f32 = 1.0;
+pub const FAR_LOD: usize = 16;
+pub const NORMAL_LOD: usize = 32;
 
-const RENDER_DISTANCE: i32 = 6;
+pub const RENDER_DISTANCE: i32 = 6;
 
-const HEIGHT_INTENSITY: f32 = 0.2;
-const MAP_SIZE: f64 = 0.25;
+pub const HEIGHT_INTENSITY: f32 = 0.2;
+/// Extent of a chunk mesh, in mesh units. Chunks mesh exactly their
+/// footprint on the chunk grid, so neighbors share their border vertices.
+pub const MAP_SIZE: f64 = CHUNK_WORLD_SIZE as f64 / CHUNK_WORLD_SCALE as f64;
+
+/// Chunks are loaded around the entity carrying this, and the world origin
+/// follows it.
+#[derive(Component)]
+pub struct ChunkLoader;
+
+/// Radius, in chunks, of the square of chunks loaded around the `ChunkLoader`.
+#[derive(Resource, Clone, Copy, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct RenderDistance(pub i32);
+
+impl Default for RenderDistance {
+    fn default() -> Self {
+        RenderDistance(RENDER_DISTANCE)
+    }
+}
 
 pub struct ChunkDescriptor {
     pub lod: usize,
-    pub coords: Vec2,
+    pub coords: I64Vec2,
 }
 
 #[derive(Component)]
 pub struct Chunk {
     pub mesh: Mesh,
     pub lod: usize,
-    pub coords: Vec2,
+    /// Absolute coordinates, independent of the world origin.
+    pub coords: I64Vec2,
+    /// Heights of the `(lod + 1) * (lod + 1)` grid, in mesh units.
+    pub heights: Vec<f32>,
+    pub biome: Biome,
+    pub props: Vec<PropInstance>,
+    pub timings: MeshTimings,
+    /// Sculpted offsets the heights include.
+    pub edits: TerrainEdits,
 }
 
 #[derive(Component)]
@@ -46,41 +84,206 @@ pub struct ReplaceTask {
     pub descriptor: ChunkDescriptor,
 }
 
+/// Copy of the generation settings handed to the chunk tasks.
+#[derive(Clone)]
+pub struct ChunkSettings {
+    pub biome_table: BiomeTable,
+    pub color_ramp: Option<TerrainColorRamp>,
+    pub meshing: TerrainMeshing,
+    pub noise: NoiseSettings,
+    pub edits: TerrainEdits,
+}
+
+#[derive(SystemParam)]
+pub struct ChunkSettingsParam<'w> {
+    biome_table: Res<'w, BiomeTable>,
+    coloring: Res<'w, TerrainColoring>,
+    color_ramps: Res<'w, Assets<TerrainColorRamp>>,
+    meshing: Res<'w, TerrainMeshing>,
+    noise: Res<'w, NoiseSettings>,
+    edits: Res<'w, TerrainEdits>,
+}
+
+impl ChunkSettingsParam<'_> {
+    pub fn get(&self) -> ChunkSettings {
+        ChunkSettings {
+            biome_table: self.biome_table.clone(),
+            color_ramp: self.coloring.active_ramp(&self.color_ramps).cloned(),
+            meshing: self.meshing.clone(),
+            noise: self.noise.clone(),
+            edits: self.edits.clone(),
+        }
+    }
+
+    /// Height of the terrain surface, in world units, as the chunks built
+    /// from now on will have it.
+    pub fn surface_height(&self, position: DVec2) -> f32 {
+        surface_height(position, &self.noise, &self.edits)
+    }
+
+    /// Whether the terrain settings changed since the system last ran. Edits
+    /// to the color ramp assets are not included, nor is sculpting, which only
+    /// rebuilds the chunks it touched.
+    pub fn is_changed(&self) -> bool {
+        self.biome_table.is_changed()
+            || self.coloring.is_changed()
+            || self.meshing.is_changed()
+            || self.noise.is_changed()
+    }
+}
+
 impl Chunk {
-    fn new(coords: Vec2, lod: usize) -> Chunk {
+    pub fn new(coords: I64Vec2, lod: usize, settings: &ChunkSettings) -> Chunk {
+        let terrain = create_mesh(MAP_SIZE, HEIGHT_INTENSITY, lod, lod, coords, settings);
+
         Chunk {
-            mesh: create_mesh(MAP_SIZE, HEIGHT_INTENSITY, lod, lod, coords),
+            mesh: terrain.mesh,
             lod,
             coords,
+            heights: terrain.heights,
+            biome: terrain.biomes.dominant(),
+            // far chunks are too coarse to stand props on
+            props: if lod == NORMAL_LOD {
+                terrain.props
+            } else {
+                Vec::new()
+            },
+            timings: terrain.timings,
+            edits: settings.edits.clone(),
+        }
+    }
+
+    /// Terrain height under a position local to the chunk mesh, in mesh
+    /// units, interpolated between the grid vertices.
+    pub fn height_at(&self, local: Vec2) -> f32 {
+        let lod = self.lod as f32;
+        let grid = ((local / MAP_SIZE as f32 + 0.5) * lod).clamp(Vec2::ZERO, Vec2::splat(lod));
+        let (w, d) = (
+            grid.x.floor().min(lod - 1.0) as usize,
+            grid.y.floor().min(lod - 1.0) as usize,
+        );
+        let (tx, tz) = (grid.x - w as f32, grid.y - d as f32);
+
+        let height = |w: usize, d: usize| self.heights[d * (self.lod + 1) + w];
+        let back = height(w, d) + (height(w + 1, d) - height(w, d)) * tx;
+        let front = height(w, d + 1) + (height(w + 1, d + 1) - height(w, d + 1)) * tx;
+
+        back + (front - back) * tz
+    }
+
+    /// Height, in world units, the noise alone gives under an absolute
+    /// horizontal position, from the heights and the edits they include.
+    pub fn unsculpted_height(&self, position: DVec2) -> f32 {
+        let center = self.coords.as_dvec2() * CHUNK_WORLD_SIZE as f64;
+        let local = ((position - center) / CHUNK_WORLD_SCALE as f64).as_vec2();
+        self.height_at(local) * CHUNK_WORLD_SCALE - self.edits.height_offset(position)
+    }
+
+    /// Biome of the grid vertex nearest to a position local to the chunk mesh.
+    pub fn biome_at(&self, local: Vec2) -> Biome {
+        let lod = self.lod as f32;
+        let grid = ((local / MAP_SIZE as f32 + 0.5) * lod)
+            .clamp(Vec2::ZERO, Vec2::splat(lod))
+            .round();
+        let index = grid.y as usize * (self.lod + 1) + grid.x as usize;
+
+        match self.mesh.attribute(ATTRIBUTE_BIOME) {
+            Some(VertexAttributeValues::Uint32(biomes)) => biomes
+                .get(index)
+                .and_then(|id| Biome::from_id(*id))
+                .unwrap_or(self.biome),
+            _ => self.biome,
         }
     }
 }
 
-pub fn setup_chunks(mut commands: Commands) {
-    let thread_pool = AsyncComputeTaskPool::get();
+/// Height of the terrain surface, in world units, at an absolute horizontal
+/// position, sculpting included.
+pub fn surface_height(position: DVec2, noise: &NoiseSettings, edits: &TerrainEdits) -> f32 {
+    let scale = CHUNK_WORLD_SCALE as f64;
+    let origin = [position.x * 2.0 / scale, position.y * 2.0 / scale];
+    let noise_map = generate_noise_map(1.0 / scale, 1, 1, origin, noise);
 
-    let task = thread_pool.spawn(async move { Chunk::new(Vec2::new(0.0, 0.0), NORMAL_LOD) });
+    noise_map.get_value(0, 0) as f32 * HEIGHT_INTENSITY * CHUNK_WORLD_SCALE
+        + edits.height_offset(position)
+}
+
+pub fn setup_chunks(mut commands: Commands, settings: ChunkSettingsParam) {
+    spawn_chunk_task(&mut commands, I64Vec2::ZERO, settings.get());
+}
+
+/// Generates the chunk at `coords` at full detail. The chunk systems only load
+/// the neighbors of the current chunk, so this starts them off.
+pub fn spawn_chunk_task(commands: &mut Commands, coords: I64Vec2, settings: ChunkSettings) {
+    let task =
+        AsyncComputeTaskPool::get().spawn(async move { Chunk::new(coords, NORMAL_LOD, &settings) });
 
     commands.spawn(ChunkTask {
         task,
         descriptor: ChunkDescriptor {
             lod: NORMAL_LOD,
-            coords: Vec2::new(0.0, 0.0),
+            coords,
         },
     });
 }
 
+/// Every entity holding terrain generated from the noise.
+type GeneratedTerrain = Or<(
+    With<Chunk>,
+    With<ChunkTask>,
+    With<QuadtreeNode>,
+    With<QuadtreeNodeTask>,
+    With<Horizon>,
+    With<HorizonTask>,
+)>;
+
+/// Entities with a terrain collider, props left out.
+pub type TerrainCollider = Or<(With<Chunk>, With<QuadtreeNode>)>;
+
+/// Every entity showing a terrain mesh.
+pub type TerrainMeshes = Or<(With<Chunk>, With<QuadtreeNode>, With<Horizon>)>;
+
+/// Throws away the whole terrain when the planet noise changes, so it streams
+/// back in with the new noise.
+pub fn regenerate_terrain(
+    mut commands: Commands,
+    noise: Res<NoiseSettings>,
+    quadtree: Res<QuadtreeSettings>,
+    chunk_settings: ChunkSettingsParam,
+    player_query: Query<&Transform, With<ChunkLoader>>,
+    origin: Res<WorldOrigin>,
+    terrain: Query<Entity, GeneratedTerrain>,
+) {
+    if !noise.is_changed() || noise.is_added() {
+        return;
+    }
+
+    for entity in terrain.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    if !quadtree.enabled {
+        if let Ok(player_transform) = player_query.get_single() {
+            let coords = get_player_chunk(player_transform.translation, &origin);
+            spawn_chunk_task(&mut commands, coords, chunk_settings.get());
+        }
+    }
+}
+
 pub fn handle_new_chunks(
     mut commands: Commands,
     chunks: Query<&Chunk>,
     tasks: Query<&ChunkTask>,
-    player_query: Query<&Transform, With<FlyCam>>,
+    player_query: Query<&Transform, With<ChunkLoader>>,
+    origin: Res<WorldOrigin>,
+    render_distance: Res<RenderDistance>,
+    settings: ChunkSettingsParam,
 ) {
     if let Ok(player_transform) = player_query.get_single() {
         let thread_pool = AsyncComputeTaskPool::get();
 
-        let current_chunk = get_player_chunk(player_transform.translation);
-        let neighbors = get_neighbors(current_chunk, RENDER_DISTANCE);
+        let current_chunk = get_player_chunk(player_transform.translation, &origin);
+        let neighbors = get_neighbors(current_chunk, render_distance.0);
 
         for (neighbor, lod) in &neighbors {
             let mut already_tasked = false;
@@ -100,18 +303,15 @@ pub fn handle_new_chunks(
             }
 
             if !already_generated && !already_tasked {
-                let x = neighbor.x;
-                let y = neighbor.y;
+                let coords = *neighbor;
                 let lod = *lod;
+                let settings = settings.get();
 
-                let task = thread_pool.spawn(async move { Chunk::new(Vec2::new(x, y), lod) });
+                let task = thread_pool.spawn(async move { Chunk::new(coords, lod, &settings) });
 
                 commands.spawn(ChunkTask {
                     task,
-                    descriptor: ChunkDescriptor {
-                        lod,
-                        coords: Vec2::new(x, y),
-                    },
+                    descriptor: ChunkDescriptor { lod, coords },
                 });
             }
         }
@@ -121,33 +321,32 @@ pub fn handle_new_chunks(
 pub fn handle_chunk_tasks(
     mut commands: Commands,
     mut chunk_tasks: Query<(Entity, &mut ChunkTask)>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<TerrainMaterialHandle>,
+    origin: Res<WorldOrigin>,
+    mut diagnostics: Diagnostics,
 ) {
     for (entity, mut task) in &mut chunk_tasks {
         if let Some(new_chunk) = block_on(future::poll_once(&mut task.task)) {
-            let x = new_chunk.coords.x;
-            let y = new_chunk.coords.y;
+            let start = Instant::now();
+            let collider =
+                Collider::from_bevy_mesh(&new_chunk.mesh, &ComputedColliderShape::TriMesh).unwrap();
+            record_chunk_timings(&mut diagnostics, new_chunk.timings, start.elapsed());
 
-            // Add our new PbrBundle of components to our tagged entity
+            // Add our new MaterialMeshBundle of components to our tagged entity
             commands.entity(entity).insert((
-                PbrBundle {
+                MaterialMeshBundle {
                     mesh: meshes.add(new_chunk.mesh.clone()),
-                    material: materials.add(Color::rgba(1.0, 1.0, 1.0, TERRAIN_ALPHA)),
+                    material: material.0.clone(),
                     transform: Transform {
-                        translation: Vec3::new(
-                            x as f32 * CHUNK_WORLD_SIZE,
-                            0.0,
-                            y as f32 * CHUNK_WORLD_SIZE,
-                        ),
+                        translation: origin.chunk_translation(new_chunk.coords),
                         scale: Vec3::new(CHUNK_WORLD_SCALE, CHUNK_WORLD_SCALE, CHUNK_WORLD_SCALE),
                         ..default()
                     },
                     ..default()
                 },
                 RigidBody::Fixed,
-                Collider::from_bevy_mesh(&new_chunk.mesh, &ComputedColliderShape::TriMesh).unwrap(),
-                Wireframe,
+                collider,
                 new_chunk,
             ));
 
@@ -160,11 +359,13 @@ pub fn handle_chunk_tasks(
 pub fn remove_chunks(
     mut commands: Commands,
     chunks: Query<(Entity, &Chunk)>,
-    player_query: Query<&Transform, With<FlyCam>>,
+    player_query: Query<&Transform, With<ChunkLoader>>,
+    origin: Res<WorldOrigin>,
+    render_distance: Res<RenderDistance>,
 ) {
     if let Ok(player_transform) = player_query.get_single() {
-        let current_chunk = get_player_chunk(player_transform.translation);
-        let neighbors = get_neighbors(current_chunk, RENDER_DISTANCE);
+        let current_chunk = get_player_chunk(player_transform.translation, &origin);
+        let neighbors = get_neighbors(current_chunk, render_distance.0);
 
         for (entity, chunk) in chunks.iter() {
             let mut should_remove = true;
@@ -175,7 +376,7 @@ pub fn remove_chunks(
                 }
             }
             if should_remove {
-                commands.entity(entity).despawn();
+                commands.entity(entity).despawn_recursive();
             }
         }
     }
@@ -184,31 +385,31 @@ pub fn remove_chunks(
 pub fn spawn_replace_task(
     mut commands: Commands,
     chunks: Query<(Entity, &Chunk, Option<&ReplaceTask>)>,
-    player_query: Query<&Transform, With<FlyCam>>,
+    player_query: Query<&Transform, With<ChunkLoader>>,
+    origin: Res<WorldOrigin>,
+    render_distance: Res<RenderDistance>,
+    settings: ChunkSettingsParam,
 ) {
     if let Ok(player_transform) = player_query.get_single() {
         let thread_pool = AsyncComputeTaskPool::get();
 
-        let current_chunk = get_player_chunk(player_transform.translation);
-        let neighbors = get_neighbors(current_chunk, RENDER_DISTANCE);
+        let current_chunk = get_player_chunk(player_transform.translation, &origin);
+        let neighbors = get_neighbors(current_chunk, render_distance.0);
 
         for (entity, chunk, task) in chunks.iter() {
             if task.is_none() {
                 for (neighbor, lod) in &neighbors {
                     if neighbor == &chunk.coords && lod != &chunk.lod {
-                        let x = neighbor.x;
-                        let y = neighbor.y;
+                        let coords = *neighbor;
                         let lod = *lod;
+                        let settings = settings.get();
 
                         let task =
-                            thread_pool.spawn(async move { Chunk::new(Vec2::new(x, y), lod) });
+                            thread_pool.spawn(async move { Chunk::new(coords, lod, &settings) });
 
                         commands.entity(entity).insert(ReplaceTask {
                             task,
-                            descriptor: ChunkDescriptor {
-                                lod,
-                                coords: Vec2::new(x, y),
-                            },
+                            descriptor: ChunkDescriptor { lod, coords },
                         });
                     }
                 }
@@ -220,43 +421,42 @@ pub fn spawn_replace_task(
 pub fn handle_replace_tasks(
     mut commands: Commands,
     mut replace_tasks: Query<(Entity, &mut ReplaceTask)>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<TerrainMaterialHandle>,
+    origin: Res<WorldOrigin>,
+    mut diagnostics: Diagnostics,
 ) {
     for (entity, mut task) in &mut replace_tasks {
         if let Some(replacing_chunk) = block_on(future::poll_once(&mut task.task)) {
-            let x = replacing_chunk.coords.x;
-            let y = replacing_chunk.coords.y;
+            let start = Instant::now();
+            let collider =
+                Collider::from_bevy_mesh(&replacing_chunk.mesh, &ComputedColliderShape::TriMesh)
+                    .unwrap();
+            record_chunk_timings(&mut diagnostics, replacing_chunk.timings, start.elapsed());
 
-            commands.entity(entity).despawn();
+            commands.entity(entity).despawn_recursive();
 
             commands.spawn((
-                PbrBundle {
+                MaterialMeshBundle {
                     mesh: meshes.add(replacing_chunk.mesh.clone()),
-                    material: materials.add(Color::rgba(1.0, 1.0, 1.0, TERRAIN_ALPHA)),
+                    material: material.0.clone(),
                     transform: Transform {
-                        translation: Vec3::new(
-                            x as f32 * CHUNK_WORLD_SIZE,
-                            0.0,
-                            y as f32 * CHUNK_WORLD_SIZE,
-                        ),
+                        translation: origin.chunk_translation(replacing_chunk.coords),
                         scale: Vec3::new(CHUNK_WORLD_SCALE, CHUNK_WORLD_SCALE, CHUNK_WORLD_SCALE),
                         ..default()
                     },
                     ..default()
                 },
                 RigidBody::Fixed,
-                Collider::from_bevy_mesh(&replacing_chunk.mesh, &ComputedColliderShape::TriMesh)
-                    .unwrap(),
-                Wireframe,
+                collider,
                 replacing_chunk,
             ));
         }
     }
 }
 
-fn get_neighbors(coords: Vec2, mut radius: i32) -> Vec<(Vec2, usize)> {
-    let mut neighbors = Vec::<(Vec2, usize)>::new();
+fn get_neighbors(coords: I64Vec2, mut radius: i32) -> Vec<(I64Vec2, usize)> {
+    let mut neighbors = Vec::<(I64Vec2, usize)>::new();
     if radius <= 0 {
         radius = 1
     };
@@ -269,13 +469,11 @@ fn get_neighbors(coords: Vec2, mut radius: i32) -> Vec<(Vec2, usize)> {
             // current chunk isn't a neighbor
             if x != 0 || y != 0 {
                 // closest chunk have higher lod
+                let neighbor = coords + I64Vec2::new(x as i64, y as i64);
                 if (x >= -1 && x <= 1) && (y >= -1 && y <= 1) {
-                    neighbors.push((
-                        Vec2::new(coords.x + x as f32, coords.y + y as f32),
-                        NORMAL_LOD,
-                    ));
+                    neighbors.push((neighbor, NORMAL_LOD));
                 } else {
-                    neighbors.push((Vec2::new(coords.x + x as f32, coords.y + y as f32), FAR_LOD));
+                    neighbors.push((neighbor, FAR_LOD));
                 }
             }
         }
@@ -284,9 +482,6 @@ fn get_neighbors(coords: Vec2, mut radius: i32) -> Vec<(Vec2, usize)> {
     neighbors
 }
 
-fn get_player_chunk(player_translation: Vec3) -> Vec2 {
-    Vec2::new(
-        (player_translation.x / CHUNK_WORLD_SIZE).round(),
-        (player_translation.z / CHUNK_WORLD_SIZE).round(),
-    )
+pub fn get_player_chunk(player_translation: Vec3, origin: &WorldOrigin) -> I64Vec2 {
+    origin.chunk_at(player_translation)
 }
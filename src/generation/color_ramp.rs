@@ -0,0 +1,281 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::utils::BoxedFuture;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::biome::{Biome, ATTRIBUTE_BIOME};
+use super::chunk::{TerrainMeshes, HEIGHT_INTENSITY};
+
+pub const EARTH_RAMP: &str = "color_ramps/earth.ramp.ron";
+pub const DESERT_RAMP: &str = "color_ramps/desert.ramp.ron";
+pub const ALIEN_RAMP: &str = "color_ramps/alien.ramp.ron";
+pub const CONTOUR_RAMP: &str = "color_ramps/contour.ramp.ron";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct ColorStop {
+    pub position: f32,
+    pub color: Color,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub enum RampInterpolation {
+    Linear,
+    #[default]
+    Smooth,
+}
+
+/// Thin lines drawn every `interval` planetary elevation units.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Reflect)]
+pub struct Contours {
+    pub interval: f32,
+    pub width: f32,
+    pub color: Color,
+}
+
+/// Gradients used to color the terrain vertices.
+///
+/// `elevation` stops are placed in planetary elevation units (-1.0 for the
+/// deepest trenches, +1.0 for the highest peaks). `slope` stops are placed in
+/// degrees and are painted over the elevation color using their alpha, so
+/// cliffs can turn to rock whatever their height.
+#[derive(Asset, Clone, Debug, Serialize, Deserialize, Reflect)]
+pub struct TerrainColorRamp {
+    pub elevation: Vec<ColorStop>,
+    #[serde(default)]
+    pub slope: Vec<ColorStop>,
+    #[serde(default)]
+    pub interpolation: RampInterpolation,
+    #[serde(default)]
+    pub contours: Option<Contours>,
+}
+
+impl TerrainColorRamp {
+    pub fn color(&self, elevation: f32, slope: f32) -> Color {
+        let mut color = sample(&self.elevation, elevation, self.interpolation);
+
+        if !self.slope.is_empty() {
+            let rock = sample(&self.slope, slope, self.interpolation);
+            color = mix(color, rock.with_a(1.0), rock.a());
+        }
+
+        if let Some(contours) = self.contours {
+            if contours.interval > 0.0 {
+                let distance = (elevation / contours.interval).fract().abs();
+                if distance.min(1.0 - distance) * contours.interval < contours.width / 2.0 {
+                    color = contours.color;
+                }
+            }
+        }
+
+        color
+    }
+}
+
+fn sample(stops: &[ColorStop], position: f32, interpolation: RampInterpolation) -> Color {
+    let Some(first) = stops.first() else {
+        return Color::WHITE;
+    };
+    if position <= first.position {
+        return first.color;
+    }
+
+    for pair in stops.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        if position <= to.position {
+            let mut t =
+                (position - from.position) / (to.position - from.position).max(f32::EPSILON);
+            if interpolation == RampInterpolation::Smooth {
+                t = t * t * (3.0 - 2.0 * t);
+            }
+            return mix(from.color, to.color, t);
+        }
+    }
+
+    stops.last().unwrap().color
+}
+
+fn mix(from: Color, to: Color, t: f32) -> Color {
+    let (from, to) = (from.as_rgba_f32(), to.as_rgba_f32());
+    Color::rgba(
+        from[0] + (to[0] - from[0]) * t,
+        from[1] + (to[1] - from[1]) * t,
+        from[2] + (to[2] - from[2]) * t,
+        from[3] + (to[3] - from[3]) * t,
+    )
+}
+
+/// Colors of the terrain vertices, from the ramp when there is one or from
+/// the vertex biomes otherwise.
+pub fn terrain_colors(
+    ramp: Option<&TerrainColorRamp>,
+    biomes: &[Biome],
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+) -> Vec<[f32; 4]> {
+    match ramp {
+        Some(ramp) => positions
+            .iter()
+            .zip(normals)
+            .map(|([_, y, _], normal)| {
+                let slope = Vec3::from(*normal).angle_between(Vec3::Y).to_degrees();
+                ramp.color(y / HEIGHT_INTENSITY, slope).as_rgba_f32()
+            })
+            .collect(),
+        None => biomes.iter().map(|biome| biome.color()).collect(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum VertexColoring {
+    #[default]
+    Biome,
+    Ramp,
+}
+
+/// Selects how chunks are colored. Changing it, or editing the active ramp
+/// asset, recolors the loaded chunks.
+#[derive(Resource, Default, Reflect)]
+#[reflect(Resource)]
+pub struct TerrainColoring {
+    pub mode: VertexColoring,
+    pub ramp: Handle<TerrainColorRamp>,
+}
+
+impl TerrainColoring {
+    pub fn active_ramp<'a>(
+        &self,
+        ramps: &'a Assets<TerrainColorRamp>,
+    ) -> Option<&'a TerrainColorRamp> {
+        match self.mode {
+            VertexColoring::Biome => None,
+            VertexColoring::Ramp => ramps.get(&self.ramp),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TerrainColorRampLoader;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum TerrainColorRampLoaderError {
+    #[error("Could not read color ramp: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse color ramp: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for TerrainColorRampLoader {
+    type Asset = TerrainColorRamp;
+    type Settings = ();
+    type Error = TerrainColorRampLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes::<TerrainColorRamp>(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ramp.ron"]
+    }
+}
+
+pub fn setup_coloring(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(TerrainColoring {
+        mode: VertexColoring::default(),
+        ramp: asset_server.load(EARTH_RAMP),
+    });
+}
+
+pub fn recolor_chunks(
+    coloring: Res<TerrainColoring>,
+    ramps: Res<Assets<TerrainColorRamp>>,
+    mut ramp_events: EventReader<AssetEvent<TerrainColorRamp>>,
+    chunks: Query<&Handle<Mesh>, TerrainMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let ramp_changed = ramp_events.read().any(|event| match event {
+        AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => {
+            *id == coloring.ramp.id()
+        }
+        _ => false,
+    });
+    if !coloring.is_changed() && !ramp_changed {
+        return;
+    }
+
+    let ramp = coloring.active_ramp(&ramps);
+    for handle in chunks.iter() {
+        let Some(mesh) = meshes.get_mut(handle) else {
+            continue;
+        };
+
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Uint32(biome_ids)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            mesh.attribute(ATTRIBUTE_BIOME),
+        )
+        else {
+            continue;
+        };
+
+        let biomes: Vec<Biome> = biome_ids
+            .iter()
+            .map(|id| Biome::from_id(*id).unwrap_or_default())
+            .collect();
+        let colors = terrain_colors(ramp, &biomes, positions, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generation::noise::SEA_LEVEL;
+
+    fn preset(text: &str) -> TerrainColorRamp {
+        ron::de::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn presets_have_sorted_stops() {
+        for text in [
+            include_str!("../../assets/color_ramps/earth.ramp.ron"),
+            include_str!("../../assets/color_ramps/desert.ramp.ron"),
+            include_str!("../../assets/color_ramps/alien.ramp.ron"),
+            include_str!("../../assets/color_ramps/contour.ramp.ron"),
+        ] {
+            let ramp = preset(text);
+            assert!(ramp
+                .elevation
+                .windows(2)
+                .all(|pair| pair[0].position < pair[1].position));
+        }
+    }
+
+    #[test]
+    fn earth_shore_is_at_sea_level() {
+        let ramp = preset(include_str!("../../assets/color_ramps/earth.ramp.ron"));
+        let sea_level = SEA_LEVEL as f32;
+
+        let water = ramp.color(sea_level - 0.01, 0.0);
+        let beach = ramp.color(sea_level + 0.01, 0.0);
+        assert!(water.b() > water.r(), "{water:?} is not water");
+        assert!(beach.r() > beach.b(), "{beach:?} is not sand");
+    }
+}
@@ -0,0 +1,330 @@
+use std::path::{Path, PathBuf};
+
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::console::{
+    parse_arg, parse_switch, switch_label, AddConsoleCommand, CommandResult, ConsoleCommand,
+    ConsoleError,
+};
+use crate::origin::WorldOrigin;
+
+use super::chunk::{ChunkLoader, RenderDistance, CHUNK_WORLD_SCALE, CHUNK_WORLD_SIZE};
+use super::debug::{DebugColoring, TerrainDebugMode};
+use super::edits::TerrainEdits;
+use super::mesh::TerrainMeshing;
+use super::noise::{generate_noise_map, NoiseSettings};
+use super::quadtree::QuadtreeSettings;
+
+/// Pixels along each side of an exported heightmap, unless given.
+const HEIGHTMAP_RESOLUTION: usize = 512;
+/// Largest exported heightmap, 16M samples of the full planet noise.
+const MAX_HEIGHTMAP_RESOLUTION: usize = 4096;
+
+pub fn add_terrain_commands(app: &mut App) {
+    app.add_console_command(
+        "seed",
+        ConsoleCommand::new("seed [n]", "Shows or changes the planet seed", seed_command),
+    )
+    .add_console_command(
+        "regen",
+        ConsoleCommand::new("regen", "Regenerates the whole terrain", |world, args| {
+            if !args.is_empty() {
+                return Err(ConsoleError::Usage);
+            }
+            world.resource_mut::<NoiseSettings>().set_changed();
+            Ok("regenerating the terrain".to_string())
+        }),
+    )
+    .add_console_command(
+        "renderdist",
+        ConsoleCommand::new(
+            "renderdist [n]",
+            "Shows or changes the radius, in chunks, of the loaded terrain",
+            render_distance_command,
+        ),
+    )
+    .add_console_command(
+        "lod",
+        ConsoleCommand::new(
+            "lod [setting value]",
+            "Shows or changes the quadtree and meshing settings",
+            lod_command,
+        )
+        .with_keywords(&[
+            "quadtree", "levels", "split", "morph", "adaptive", "error", "on", "off",
+        ]),
+    )
+    .add_console_command(
+        "debug",
+        ConsoleCommand::new(
+            "debug [view on|off]",
+            "Shows or toggles the terrain debug views",
+            debug_command,
+        )
+        .with_keywords(&[
+            "wireframe",
+            "borders",
+            "normals",
+            "tasks",
+            "coloring",
+            "on",
+            "off",
+            "lod",
+            "slope",
+            "biome",
+        ]),
+    )
+    .add_console_command(
+        "export",
+        ConsoleCommand::new(
+            "export heightmap <path> [resolution]",
+            "Saves the heights of the loaded terrain as a grayscale image",
+            export_command,
+        )
+        .with_keywords(&["heightmap"]),
+    );
+}
+
+fn seed_command(world: &mut World, args: &[&str]) -> CommandResult {
+    match args {
+        [] => Ok(format!("seed {}", world.resource::<NoiseSettings>().seed)),
+        [seed] => {
+            let seed = parse_arg(seed)?;
+            // the terrain regenerates by itself when the noise changes
+            world.resource_mut::<NoiseSettings>().seed = seed;
+            Ok(format!("seed set to {seed}"))
+        }
+        _ => Err(ConsoleError::Usage),
+    }
+}
+
+fn render_distance_command(world: &mut World, args: &[&str]) -> CommandResult {
+    match args {
+        [] => Ok(format!(
+            "render distance {}",
+            world.resource::<RenderDistance>().0
+        )),
+        [distance] => {
+            let distance: i32 = parse_arg(distance)?;
+            // same bounds as `Settings::validate`
+            if !(1..=32).contains(&distance) {
+                return Err(ConsoleError::Failed(
+                    "the render distance is between 1 and 32 chunks".to_string(),
+                ));
+            }
+            world.resource_mut::<RenderDistance>().0 = distance;
+            Ok(format!("render distance set to {distance}"))
+        }
+        _ => Err(ConsoleError::Usage),
+    }
+}
+
+fn lod_command(world: &mut World, args: &[&str]) -> CommandResult {
+    let [setting, value] = args else {
+        if !args.is_empty() {
+            return Err(ConsoleError::Usage);
+        }
+        let quadtree = world.resource::<QuadtreeSettings>();
+        let meshing = world.resource::<TerrainMeshing>();
+        return Ok(format!(
+            "quadtree {}, levels {}, split {}, morph {}\nadaptive {}, error {}",
+            switch_label(quadtree.enabled),
+            quadtree.levels,
+            quadtree.split_distance,
+            quadtree.morph_ratio,
+            switch_label(meshing.adaptive),
+            meshing.max_error,
+        ));
+    };
+
+    let invalid = |reason: &str| Err(ConsoleError::Failed(format!("{setting} {reason}")));
+
+    match *setting {
+        "quadtree" => world.resource_mut::<QuadtreeSettings>().enabled = parse_switch(value)?,
+        "levels" => {
+            // nodes double in size at every level
+            let levels: u32 = parse_arg(value)?;
+            if !(1..=16).contains(&levels) {
+                return invalid("is between 1 and 16");
+            }
+            world.resource_mut::<QuadtreeSettings>().levels = levels;
+        }
+        "split" => {
+            let split: f32 = parse_arg(value)?;
+            if !(split > 0.0 && split.is_finite()) {
+                return invalid("must be positive");
+            }
+            world.resource_mut::<QuadtreeSettings>().split_distance = split;
+        }
+        "morph" => {
+            let morph: f32 = parse_arg(value)?;
+            if !(0.0..=1.0).contains(&morph) {
+                return invalid("is between 0 and 1");
+            }
+            world.resource_mut::<QuadtreeSettings>().morph_ratio = morph;
+        }
+        // meshing only applies to the chunks generated from now on
+        "adaptive" => world.resource_mut::<TerrainMeshing>().adaptive = parse_switch(value)?,
+        "error" => {
+            let error: f32 = parse_arg(value)?;
+            if !(error >= 0.0 && error.is_finite()) {
+                return invalid("must be at least 0");
+            }
+            world.resource_mut::<TerrainMeshing>().max_error = error;
+        }
+        _ => return Err(ConsoleError::Usage),
+    }
+    Ok(format!("{setting} set to {value}"))
+}
+
+fn debug_command(world: &mut World, args: &[&str]) -> CommandResult {
+    let mut mode = world.resource_mut::<TerrainDebugMode>();
+
+    let (view, value) = match args {
+        [] => {
+            return Ok(format!(
+                "wireframe {}, borders {}, normals {}, tasks {}, coloring {}",
+                switch_label(mode.wireframe),
+                switch_label(mode.chunk_borders),
+                switch_label(mode.normals),
+                switch_label(mode.task_states),
+                match mode.coloring {
+                    Some(coloring) => format!("{coloring:?}").to_lowercase(),
+                    None => "off".to_string(),
+                },
+            ))
+        }
+        ["coloring", coloring] => {
+            mode.coloring = match *coloring {
+                "lod" => Some(DebugColoring::Lod),
+                "slope" => Some(DebugColoring::Slope),
+                "biome" => Some(DebugColoring::Biome),
+                "off" => None,
+                _ => {
+                    return Err(ConsoleError::InvalidArgument(
+                        coloring.to_string(),
+                        "coloring",
+                    ))
+                }
+            };
+            return Ok(format!("coloring set to {coloring}"));
+        }
+        [view] => (*view, None),
+        [view, value] => (*view, Some(parse_switch(value)?)),
+        _ => return Err(ConsoleError::Usage),
+    };
+
+    let flag = match view {
+        "wireframe" => &mut mode.wireframe,
+        "borders" => &mut mode.chunk_borders,
+        "normals" => &mut mode.normals,
+        "tasks" => &mut mode.task_states,
+        _ => {
+            return Err(ConsoleError::InvalidArgument(
+                view.to_string(),
+                "debug view",
+            ))
+        }
+    };
+    // toggled when no value is given
+    *flag = value.unwrap_or(!*flag);
+    Ok(format!("{view} {}", switch_label(*flag)))
+}
+
+fn export_command(world: &mut World, args: &[&str]) -> CommandResult {
+    let (path, resolution) = match args {
+        ["heightmap", path] => (*path, HEIGHTMAP_RESOLUTION),
+        ["heightmap", path, resolution] => (*path, parse_arg(resolution)?),
+        _ => return Err(ConsoleError::Usage),
+    };
+    if !(1..=MAX_HEIGHTMAP_RESOLUTION).contains(&resolution) {
+        return Err(ConsoleError::Failed(format!(
+            "the resolution is between 1 and {MAX_HEIGHTMAP_RESOLUTION} pixels"
+        )));
+    }
+
+    let Ok(loader) = world
+        .query_filtered::<&Transform, With<ChunkLoader>>()
+        .get_single(world)
+    else {
+        return Err(ConsoleError::Failed(
+            "nothing loads the terrain".to_string(),
+        ));
+    };
+    let center = world
+        .resource::<WorldOrigin>()
+        .to_world_xz(loader.translation);
+    let size = (world.resource::<RenderDistance>().0 * 2 + 1) as f64 * CHUNK_WORLD_SIZE as f64;
+    let noise = world.resource::<NoiseSettings>().clone();
+    let edits = world.resource::<TerrainEdits>().clone();
+
+    // the full planet noise is slow to sample, keep the game running
+    let path = PathBuf::from(path);
+    let message = format!(
+        "exporting the {size} world units around {:.0}, {:.0} to {}",
+        center.x,
+        center.y,
+        path.display()
+    );
+    AsyncComputeTaskPool::get()
+        .spawn(async move {
+            match export_heightmap(&path, center, size, resolution, &noise, &edits) {
+                Ok(()) => info!("exported the heightmap to {}", path.display()),
+                Err(error) => error!("could not export the heightmap: {error}"),
+            }
+        })
+        .detach();
+
+    Ok(message)
+}
+
+/// Writes the heights of the `size` world units square around `center`,
+/// sculpting included, the lowest planet elevation being black and the
+/// highest white.
+fn export_heightmap(
+    path: &Path,
+    center: DVec2,
+    size: f64,
+    resolution: usize,
+    noise: &NoiseSettings,
+    edits: &TerrainEdits,
+) -> Result<(), String> {
+    let scale = CHUNK_WORLD_SCALE as f64;
+    let min = center - size / 2.0;
+    let origin = [min.x * 2.0 / scale, min.y * 2.0 / scale];
+    let mut noise_map = generate_noise_map(size / scale, resolution, resolution, origin, noise);
+    edits.apply(&mut noise_map, size / scale, origin);
+
+    let mut data = Vec::with_capacity(resolution * resolution);
+    for d in 0..resolution {
+        for w in 0..resolution {
+            let height = noise_map.get_value(w, d) * 0.5 + 0.5;
+            data.push((height.clamp(0.0, 1.0) * 255.0) as u8);
+        }
+    }
+
+    let image = Image::new(
+        Extent3d {
+            width: resolution as u32,
+            height: resolution as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::R8Unorm,
+        RenderAssetUsages::MAIN_WORLD,
+    );
+
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder).map_err(|error| error.to_string())?;
+    }
+    image
+        .try_into_dynamic()
+        .map_err(|error| error.to_string())?
+        .save(path)
+        .map_err(|error| error.to_string())
+}
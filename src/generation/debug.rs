@@ -0,0 +1,501 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::math::I64Vec2;
+use bevy::pbr::wireframe::Wireframe;
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::utils::HashMap;
+
+use crate::camera::MainCamera;
+use crate::input::{Action, Actions, InputMap};
+use crate::origin::WorldOrigin;
+
+use super::biome::{Biome, ATTRIBUTE_BIOME};
+use super::chunk::{Chunk, ChunkTask, ReplaceTask, CHUNK_WORLD_SIZE, NORMAL_LOD};
+use super::color_ramp::TerrainColoring;
+use super::material::TerrainMaterialConfig;
+
+/// Length of the normal gizmos, in world units.
+const NORMAL_LENGTH: f32 = 2.0;
+
+/// Debug colors painted over the terrain instead of the biome or ramp colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub enum DebugColoring {
+    Lod,
+    Slope,
+    Biome,
+}
+
+/// Terrain debug visualizations. Everything is off by default.
+#[derive(Resource, Clone, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct TerrainDebugMode {
+    pub wireframe: bool,
+    pub chunk_borders: bool,
+    pub normals: bool,
+    pub task_states: bool,
+    pub coloring: Option<DebugColoring>,
+    pub show_panel: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DebugToggle {
+    Wireframe,
+    ChunkBorders,
+    LodColoring,
+    Normals,
+    SlopeHeatmap,
+    BiomeColoring,
+    TaskStates,
+}
+
+impl DebugToggle {
+    const ALL: [DebugToggle; 7] = [
+        DebugToggle::Wireframe,
+        DebugToggle::ChunkBorders,
+        DebugToggle::LodColoring,
+        DebugToggle::Normals,
+        DebugToggle::SlopeHeatmap,
+        DebugToggle::BiomeColoring,
+        DebugToggle::TaskStates,
+    ];
+
+    fn action(self) -> Action {
+        match self {
+            DebugToggle::Wireframe => Action::ToggleWireframe,
+            DebugToggle::ChunkBorders => Action::ToggleChunkBorders,
+            DebugToggle::LodColoring => Action::ToggleLodColoring,
+            DebugToggle::Normals => Action::ToggleNormals,
+            DebugToggle::SlopeHeatmap => Action::ToggleSlopeHeatmap,
+            DebugToggle::BiomeColoring => Action::ToggleBiomeColoring,
+            DebugToggle::TaskStates => Action::ToggleTaskStates,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            DebugToggle::Wireframe => "Wireframe",
+            DebugToggle::ChunkBorders => "Chunk borders",
+            DebugToggle::LodColoring => "LOD colors",
+            DebugToggle::Normals => "Normals",
+            DebugToggle::SlopeHeatmap => "Slope heatmap",
+            DebugToggle::BiomeColoring => "Biome colors",
+            DebugToggle::TaskStates => "Task states",
+        }
+    }
+
+    fn coloring(self) -> Option<DebugColoring> {
+        match self {
+            DebugToggle::LodColoring => Some(DebugColoring::Lod),
+            DebugToggle::SlopeHeatmap => Some(DebugColoring::Slope),
+            DebugToggle::BiomeColoring => Some(DebugColoring::Biome),
+            _ => None,
+        }
+    }
+
+    fn is_on(self, mode: &TerrainDebugMode) -> bool {
+        match self {
+            DebugToggle::Wireframe => mode.wireframe,
+            DebugToggle::ChunkBorders => mode.chunk_borders,
+            DebugToggle::Normals => mode.normals,
+            DebugToggle::TaskStates => mode.task_states,
+            _ => mode.coloring == self.coloring(),
+        }
+    }
+
+    fn toggle(self, mode: &mut TerrainDebugMode) {
+        match self {
+            DebugToggle::Wireframe => mode.wireframe = !mode.wireframe,
+            DebugToggle::ChunkBorders => mode.chunk_borders = !mode.chunk_borders,
+            DebugToggle::Normals => mode.normals = !mode.normals,
+            DebugToggle::TaskStates => mode.task_states = !mode.task_states,
+            // debug colorings replace each other
+            _ => {
+                mode.coloring = if mode.coloring == self.coloring() {
+                    None
+                } else {
+                    self.coloring()
+                }
+            }
+        }
+    }
+}
+
+/// Marker of the debug panel root node
+#[derive(Component)]
+pub struct DebugPanelRoot;
+
+#[derive(Component)]
+pub struct DebugButton(DebugToggle);
+
+/// UI text showing the coordinates of a chunk
+#[derive(Component)]
+pub struct ChunkLabel;
+
+pub fn setup_debug_panel(mut commands: Commands) {
+    let root = commands
+        .spawn((
+            DebugPanelRoot,
+            NodeBundle {
+                background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+                z_index: ZIndex::Global(i32::MAX),
+                visibility: Visibility::Hidden,
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    // bottom-left corner, out of the way of the counters
+                    left: Val::Percent(1.),
+                    bottom: Val::Percent(1.),
+                    top: Val::Auto,
+                    right: Val::Auto,
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(4.0)),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .id();
+
+    for toggle in DebugToggle::ALL {
+        let button = commands
+            .spawn((
+                DebugButton(toggle),
+                ButtonBundle {
+                    background_color: BackgroundColor(Color::NONE),
+                    style: Style {
+                        padding: UiRect::all(Val::Px(2.0)),
+                        ..default()
+                    },
+                    ..default()
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ));
+            })
+            .id();
+        commands.entity(root).push_children(&[button]);
+    }
+}
+
+/// Toggle the debug modes with their actions, F1 to F7 by default, F8 shows
+/// the panel
+pub fn debug_mode_hotkeys(actions: Actions, mut mode: ResMut<TerrainDebugMode>) {
+    for toggle in DebugToggle::ALL {
+        if actions.just_pressed(toggle.action()) {
+            toggle.toggle(&mut mode);
+        }
+    }
+
+    if actions.just_pressed(Action::ToggleDebugPanel) {
+        mode.show_panel = !mode.show_panel;
+    }
+}
+
+pub fn debug_panel_buttons(
+    mut mode: ResMut<TerrainDebugMode>,
+    buttons: Query<(&Interaction, &DebugButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction == Interaction::Pressed {
+            button.0.toggle(&mut mode);
+        }
+    }
+}
+
+pub fn update_debug_panel(
+    mode: Res<TerrainDebugMode>,
+    input_map: Res<InputMap>,
+    mut root: Query<&mut Visibility, With<DebugPanelRoot>>,
+    buttons: Query<(&DebugButton, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    if !mode.is_changed() && !input_map.is_changed() {
+        return;
+    }
+
+    for mut visibility in root.iter_mut() {
+        *visibility = if mode.show_panel {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+
+    for (button, children) in buttons.iter() {
+        let on = button.0.is_on(&mode);
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                let key = input_map
+                    .key(button.0.action())
+                    .map_or("-".to_string(), |key| format!("{key:?}"));
+                text.sections[0].value = format!(
+                    "[{key}] {}: {}",
+                    button.0.label(),
+                    if on { "on" } else { "off" }
+                );
+                text.sections[0].style.color = if on { Color::GREEN } else { Color::WHITE };
+            }
+        }
+    }
+}
+
+pub fn apply_debug_wireframe(
+    mut commands: Commands,
+    mode: Res<TerrainDebugMode>,
+    chunks: Query<(Entity, Ref<Chunk>, Has<Wireframe>)>,
+) {
+    for (entity, chunk, has_wireframe) in chunks.iter() {
+        if !mode.is_changed() && !chunk.is_added() {
+            continue;
+        }
+
+        if mode.wireframe && !has_wireframe {
+            commands.entity(entity).insert(Wireframe);
+        } else if !mode.wireframe && has_wireframe {
+            commands.entity(entity).remove::<Wireframe>();
+        }
+    }
+}
+
+/// Paints the debug colors over the chunks. Runs after the regular
+/// recoloring, and hands the chunks back to it once turned off.
+pub fn apply_debug_coloring(
+    mode: Res<TerrainDebugMode>,
+    mut previous: Local<Option<DebugColoring>>,
+    mut coloring: ResMut<TerrainColoring>,
+    mut material_config: ResMut<TerrainMaterialConfig>,
+    chunks: Query<(Ref<Chunk>, &Handle<Mesh>)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let changed = *previous != mode.coloring;
+    *previous = mode.coloring;
+
+    if changed {
+        material_config.splat.debug_vertex_colors = match mode.coloring {
+            Some(_) => 1.0,
+            None => 0.0,
+        };
+        if mode.coloring.is_none() {
+            coloring.set_changed();
+            return;
+        }
+    }
+
+    let Some(debug_coloring) = mode.coloring else {
+        return;
+    };
+
+    for (chunk, handle) in chunks.iter() {
+        if !changed && !chunk.is_added() && !coloring.is_changed() {
+            continue;
+        }
+        let Some(mesh) = meshes.get_mut(handle) else {
+            continue;
+        };
+
+        let colors: Vec<[f32; 4]> = match debug_coloring {
+            DebugColoring::Lod => {
+                let color = if chunk.lod == NORMAL_LOD {
+                    Color::GREEN
+                } else {
+                    Color::ORANGE_RED
+                };
+                vec![color.as_rgba_f32(); mesh.count_vertices()]
+            }
+            DebugColoring::Slope => {
+                let Some(VertexAttributeValues::Float32x3(normals)) =
+                    mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+                else {
+                    continue;
+                };
+                normals
+                    .iter()
+                    .map(|normal| {
+                        // flat is blue, 60 degrees and steeper is red
+                        let slope = Vec3::from(*normal).angle_between(Vec3::Y).to_degrees();
+                        let t = (slope / 60.0).clamp(0.0, 1.0);
+                        Color::hsl(240.0 * (1.0 - t), 1.0, 0.5).as_rgba_f32()
+                    })
+                    .collect()
+            }
+            DebugColoring::Biome => {
+                let Some(VertexAttributeValues::Uint32(biome_ids)) =
+                    mesh.attribute(ATTRIBUTE_BIOME)
+                else {
+                    continue;
+                };
+                biome_ids
+                    .iter()
+                    .map(|id| {
+                        let hue = 360.0 * *id as f32 / Biome::ALL.len() as f32;
+                        Color::hsl(hue, 0.8, 0.5).as_rgba_f32()
+                    })
+                    .collect()
+            }
+        };
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    }
+}
+
+pub fn draw_chunk_borders(
+    mode: Res<TerrainDebugMode>,
+    mut gizmos: Gizmos,
+    chunks: Query<&Chunk>,
+    origin: Res<WorldOrigin>,
+) {
+    if !mode.chunk_borders {
+        return;
+    }
+
+    for chunk in chunks.iter() {
+        gizmos.rect(
+            origin.chunk_translation(chunk.coords),
+            Quat::from_rotation_x(FRAC_PI_2),
+            Vec2::splat(CHUNK_WORLD_SIZE),
+            Color::YELLOW,
+        );
+    }
+}
+
+/// Labels the chunks with their coordinates, along with their borders.
+pub fn update_chunk_labels(
+    mode: Res<TerrainDebugMode>,
+    chunks: Query<(Entity, &Chunk)>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut labels: Local<HashMap<Entity, Entity>>,
+    mut label_nodes: Query<(&mut Style, &mut Visibility), With<ChunkLabel>>,
+    origin: Res<WorldOrigin>,
+    mut commands: Commands,
+) {
+    // forget the labels of removed chunks, or all of them once turned off
+    labels.retain(|chunk, label| {
+        let keep = mode.chunk_borders && chunks.contains(*chunk);
+        if !keep {
+            commands.entity(*label).despawn_recursive();
+        }
+        keep
+    });
+    if !mode.chunk_borders {
+        return;
+    }
+
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+
+    for (entity, chunk) in chunks.iter() {
+        let center = origin.chunk_translation(chunk.coords);
+
+        let label = *labels.entry(entity).or_insert_with(|| {
+            commands
+                .spawn((
+                    ChunkLabel,
+                    TextBundle::from_section(
+                        format!("{}, {}", chunk.coords.x, chunk.coords.y),
+                        TextStyle {
+                            font_size: 14.0,
+                            color: Color::YELLOW,
+                            ..default()
+                        },
+                    )
+                    .with_style(Style {
+                        position_type: PositionType::Absolute,
+                        ..default()
+                    }),
+                ))
+                .id()
+        });
+
+        let Ok((mut style, mut visibility)) = label_nodes.get_mut(label) else {
+            continue;
+        };
+        match camera.world_to_viewport(camera_transform, center) {
+            Some(position) => {
+                style.left = Val::Px(position.x);
+                style.top = Val::Px(position.y);
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+pub fn draw_normals(
+    mode: Res<TerrainDebugMode>,
+    mut gizmos: Gizmos,
+    chunks: Query<(&Chunk, &Handle<Mesh>, &GlobalTransform)>,
+    meshes: Res<Assets<Mesh>>,
+    camera: Query<&GlobalTransform, With<MainCamera>>,
+) {
+    if !mode.normals {
+        return;
+    }
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+
+    for (chunk, handle, transform) in chunks.iter() {
+        // only the chunks around the camera, the lines get too dense further
+        let center = transform.translation();
+        if center.xz().distance(camera.translation().xz()) > CHUNK_WORLD_SIZE * 1.5 {
+            continue;
+        }
+        if chunk.lod != NORMAL_LOD {
+            continue;
+        }
+
+        let Some(mesh) = meshes.get(handle) else {
+            continue;
+        };
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+        )
+        else {
+            continue;
+        };
+
+        for (position, normal) in positions.iter().zip(normals) {
+            let start = transform.transform_point(Vec3::from(*position));
+            gizmos.ray(start, Vec3::from(*normal) * NORMAL_LENGTH, Color::CYAN);
+        }
+    }
+}
+
+pub fn draw_task_states(
+    mode: Res<TerrainDebugMode>,
+    mut gizmos: Gizmos,
+    pending: Query<&ChunkTask>,
+    replacing: Query<&ReplaceTask>,
+    origin: Res<WorldOrigin>,
+) {
+    if !mode.task_states {
+        return;
+    }
+
+    let mut outline = |coords: I64Vec2, color: Color| {
+        gizmos.rect(
+            origin.chunk_translation(coords) + Vec3::Y,
+            Quat::from_rotation_x(FRAC_PI_2),
+            Vec2::splat(CHUNK_WORLD_SIZE * 0.9),
+            color,
+        );
+    };
+
+    for task in pending.iter() {
+        outline(task.descriptor.coords, Color::ORANGE);
+    }
+    for task in replacing.iter() {
+        outline(task.descriptor.coords, Color::FUCHSIA);
+    }
+}
@@ -0,0 +1,94 @@
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::utils::Duration;
+
+use super::chunk::{Chunk, ChunkTask, ReplaceTask, TerrainMeshes};
+use super::mesh::MeshTimings;
+
+/// Time spent sampling the noise of each generated chunk.
+pub const CHUNK_NOISE_TIME: DiagnosticPath = DiagnosticPath::const_new("chunk/noise_time");
+/// Time spent building the mesh of each generated chunk, after the noise.
+pub const CHUNK_MESH_TIME: DiagnosticPath = DiagnosticPath::const_new("chunk/mesh_time");
+/// Time spent building the collider of each generated chunk, on the main
+/// thread.
+pub const CHUNK_COLLIDER_TIME: DiagnosticPath = DiagnosticPath::const_new("chunk/collider_time");
+pub const PENDING_CHUNK_TASKS: DiagnosticPath = DiagnosticPath::const_new("chunk/pending_chunks");
+pub const PENDING_REPLACE_TASKS: DiagnosticPath =
+    DiagnosticPath::const_new("chunk/pending_replacements");
+pub const LOADED_CHUNKS: DiagnosticPath = DiagnosticPath::const_new("chunk/loaded");
+/// Vertices of every terrain mesh: chunks, quadtree nodes and horizon.
+pub const TERRAIN_VERTICES: DiagnosticPath = DiagnosticPath::const_new("terrain/vertices");
+/// Vertex and index buffer size of every terrain mesh.
+pub const TERRAIN_MESH_MEMORY: DiagnosticPath = DiagnosticPath::const_new("terrain/mesh_memory");
+
+/// Measurements kept per diagnostic, enough to compare a few minutes of
+/// streaming.
+const HISTORY_LENGTH: usize = 4096;
+
+pub struct ChunkDiagnosticsPlugin;
+
+impl Plugin for ChunkDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        for (path, suffix) in [
+            (CHUNK_NOISE_TIME, "ms"),
+            (CHUNK_MESH_TIME, "ms"),
+            (CHUNK_COLLIDER_TIME, "ms"),
+            (PENDING_CHUNK_TASKS, ""),
+            (PENDING_REPLACE_TASKS, ""),
+            (LOADED_CHUNKS, ""),
+            (TERRAIN_VERTICES, ""),
+            (TERRAIN_MESH_MEMORY, "KiB"),
+        ] {
+            app.register_diagnostic(
+                Diagnostic::new(path)
+                    .with_suffix(suffix)
+                    .with_max_history_length(HISTORY_LENGTH),
+            );
+        }
+
+        app.add_systems(Update, terrain_diagnostics);
+    }
+}
+
+/// Records how long the chunk took to generate, once it is ready.
+pub fn record_chunk_timings(
+    diagnostics: &mut Diagnostics,
+    timings: MeshTimings,
+    collider: Duration,
+) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+    diagnostics.add_measurement(&CHUNK_NOISE_TIME, || ms(timings.noise));
+    diagnostics.add_measurement(&CHUNK_MESH_TIME, || ms(timings.mesh));
+    diagnostics.add_measurement(&CHUNK_COLLIDER_TIME, || ms(collider));
+}
+
+fn terrain_diagnostics(
+    mut diagnostics: Diagnostics,
+    chunk_tasks: Query<(), With<ChunkTask>>,
+    replace_tasks: Query<(), With<ReplaceTask>>,
+    chunks: Query<(), With<Chunk>>,
+    terrain: Query<&Handle<Mesh>, TerrainMeshes>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    diagnostics.add_measurement(&PENDING_CHUNK_TASKS, || chunk_tasks.iter().len() as f64);
+    diagnostics.add_measurement(&PENDING_REPLACE_TASKS, || replace_tasks.iter().len() as f64);
+    diagnostics.add_measurement(&LOADED_CHUNKS, || chunks.iter().len() as f64);
+
+    let (mut vertices, mut bytes) = (0, 0);
+    for mesh in terrain.iter().filter_map(|handle| meshes.get(handle)) {
+        vertices += mesh.count_vertices();
+        bytes += mesh
+            .attributes()
+            .map(|(_, values)| values.get_bytes().len())
+            .sum::<usize>();
+        bytes += match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.len() * 2,
+            Some(Indices::U32(indices)) => indices.len() * 4,
+            None => 0,
+        };
+    }
+    diagnostics.add_measurement(&TERRAIN_VERTICES, || vertices as f64);
+    diagnostics.add_measurement(&TERRAIN_MESH_MEMORY, || bytes as f64 / 1024.0);
+}
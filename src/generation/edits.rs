@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use bevy::math::{DVec2, I64Vec2};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use noise::utils::NoiseMap;
+
+use super::chunk::{CHUNK_WORLD_SCALE, CHUNK_WORLD_SIZE, HEIGHT_INTENSITY};
+
+/// Edit samples along each side of a chunk.
+pub const EDIT_RESOLUTION: i64 = 64;
+/// World units between two edit samples.
+pub const EDIT_SPACING: f64 = CHUNK_WORLD_SIZE as f64 / EDIT_RESOLUTION as f64;
+
+/// Height offsets sculpted over the area of one chunk, in world units.
+#[derive(Clone)]
+struct ChunkEdits {
+    offsets: Vec<f32>,
+}
+
+impl Default for ChunkEdits {
+    fn default() -> Self {
+        ChunkEdits {
+            offsets: vec![0.0; (EDIT_RESOLUTION * EDIT_RESOLUTION) as usize],
+        }
+    }
+}
+
+/// Sculpted heights, added to the noise of every terrain mesh built from now
+/// on, whatever its level of detail.
+///
+/// Offsets are sampled on a grid of absolute positions, `EDIT_SPACING` apart,
+/// and only the chunks that were sculpted hold any. Chunks share them with the
+/// tasks and with the edits they were built from until they are sculpted
+/// again, so cloning is cheap.
+#[derive(Resource, Clone, Default)]
+pub struct TerrainEdits {
+    chunks: HashMap<I64Vec2, Arc<ChunkEdits>>,
+}
+
+impl TerrainEdits {
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Coordinates of the chunks holding offsets.
+    pub fn edited_chunks(&self) -> impl Iterator<Item = I64Vec2> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// Chunk holding the sample at `index`, and where in it. Chunk `c` holds
+    /// the samples within half a chunk of its center.
+    fn locate(index: I64Vec2) -> (I64Vec2, usize) {
+        let shifted = index + EDIT_RESOLUTION / 2;
+        let chunk = shifted.div_euclid(I64Vec2::splat(EDIT_RESOLUTION));
+        let local = shifted.rem_euclid(I64Vec2::splat(EDIT_RESOLUTION));
+        (chunk, (local.y * EDIT_RESOLUTION + local.x) as usize)
+    }
+
+    /// Offset of the sample at `index` of the grid.
+    pub fn offset(&self, index: I64Vec2) -> f32 {
+        let (chunk, sample) = TerrainEdits::locate(index);
+        self.chunks
+            .get(&chunk)
+            .map_or(0.0, |edits| edits.offsets[sample])
+    }
+
+    /// Offset of the sample at `index`, copied out of the chunks sharing it.
+    pub fn offset_mut(&mut self, index: I64Vec2) -> &mut f32 {
+        let (chunk, sample) = TerrainEdits::locate(index);
+        let edits = self.chunks.entry(chunk).or_default();
+        &mut Arc::make_mut(edits).offsets[sample]
+    }
+
+    /// Position of the sample at `index`.
+    pub fn sample_position(index: I64Vec2) -> DVec2 {
+        index.as_dvec2() * EDIT_SPACING
+    }
+
+    /// Sample at or before `position` on both axes.
+    pub fn sample_index(position: DVec2) -> I64Vec2 {
+        (position / EDIT_SPACING).floor().as_i64vec2()
+    }
+
+    /// Height offset at an absolute horizontal position, interpolated between
+    /// the samples around it.
+    pub fn height_offset(&self, position: DVec2) -> f32 {
+        if self.chunks.is_empty() {
+            return 0.0;
+        }
+
+        let index = TerrainEdits::sample_index(position);
+        let t = (position / EDIT_SPACING - index.as_dvec2()).as_vec2();
+
+        let offset = |x: i64, y: i64| self.offset(index + I64Vec2::new(x, y));
+        let back = offset(0, 0) + (offset(1, 0) - offset(0, 0)) * t.x;
+        let front = offset(0, 1) + (offset(1, 1) - offset(0, 1)) * t.x;
+
+        back + (front - back) * t.y
+    }
+
+    /// Whether any sculpted chunk overlaps the area between `min` and `max`.
+    fn overlaps(&self, min: DVec2, max: DVec2) -> bool {
+        // an offset sample spreads one spacing around it
+        let half = CHUNK_WORLD_SIZE as f64 / 2.0 + EDIT_SPACING;
+        self.chunks.keys().any(|chunk| {
+            let center = chunk.as_dvec2() * CHUNK_WORLD_SIZE as f64;
+            center.cmpge(min - half).all() && center.cmple(max + half).all()
+        })
+    }
+
+    /// Adds the offsets to a noise map covering `2 * extent` noise units from
+    /// `origin`, as built by `generate_noise_map`.
+    pub fn apply(&self, noise_map: &mut NoiseMap, extent: f64, origin: [f64; 2]) {
+        let (width, depth) = noise_map.size();
+        if width < 2 || depth < 2 {
+            return;
+        }
+
+        // noise units are half a world unit per CHUNK_WORLD_SCALE
+        let to_world = CHUNK_WORLD_SCALE as f64 / 2.0;
+        let min = DVec2::from(origin) * to_world;
+        let max = min + 2.0 * extent * to_world;
+        if !self.overlaps(min, max) {
+            return;
+        }
+
+        let step = DVec2::new(
+            (max.x - min.x) / (width - 1) as f64,
+            (max.y - min.y) / (depth - 1) as f64,
+        );
+        // offsets are in world units, the noise is scaled up to them
+        let to_noise = 1.0 / (HEIGHT_INTENSITY * CHUNK_WORLD_SCALE) as f64;
+
+        for d in 0..depth {
+            for w in 0..width {
+                let position = min + DVec2::new(w as f64, d as f64) * step;
+                let offset = self.height_offset(position);
+                if offset != 0.0 {
+                    let value = noise_map.get_value(w, d) + offset as f64 * to_noise;
+                    noise_map.set_value(w, d, value);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,600 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use noise::utils::{NoiseMapBuilder, PlaneMapBuilder};
+use noise::{
+    Billow, Blend, Cache, Clamp, Constant, Curve, Exponent, Fbm, Max, Min, MultiFractal, Multiply,
+    NoiseFn, Perlin, RidgedMulti, ScaleBias, ScalePoint, Seedable, Select, Terrace, Turbulence,
+    Worley,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Graph opened by the node editor. Only the continent definition of
+/// `generate_noise_map` is written out as a graph, the rest of the planet is
+/// still built in code.
+pub const CONTINENTS_GRAPH: &str = "terrain_graphs/continents.graph.ron";
+
+/// Noise module built from a graph node.
+pub type GraphNoise = Box<dyn NoiseFn<f64, 3>>;
+
+/// Noise modules wired together, the way `generate_noise_map` builds the
+/// planet, but as data that can be edited and saved.
+#[derive(Asset, TypePath, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TerrainGraph {
+    pub nodes: Vec<GraphNode>,
+    /// Node whose value is the terrain height.
+    pub output: Option<NodeId>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NodeId(pub u32);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub id: NodeId,
+    pub kind: NodeKind,
+    /// Sources, one per `NodeKind::inputs`, `None` when unplugged.
+    pub inputs: Vec<Option<NodeId>>,
+    /// Where the node sits in the editor.
+    pub position: [f32; 2],
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum NodeKind {
+    Perlin {
+        seed: u32,
+        frequency: f64,
+    },
+    Fbm {
+        seed: u32,
+        frequency: f64,
+        lacunarity: f64,
+        persistence: f64,
+        octaves: usize,
+    },
+    Billow {
+        seed: u32,
+        frequency: f64,
+        lacunarity: f64,
+        persistence: f64,
+        octaves: usize,
+    },
+    RidgedMulti {
+        seed: u32,
+        frequency: f64,
+        lacunarity: f64,
+        octaves: usize,
+    },
+    Worley {
+        seed: u32,
+        frequency: f64,
+    },
+    Constant {
+        value: f64,
+    },
+    Turbulence {
+        seed: u32,
+        frequency: f64,
+        power: f64,
+        roughness: usize,
+    },
+    ScaleBias {
+        scale: f64,
+        bias: f64,
+    },
+    Clamp {
+        lower: f64,
+        upper: f64,
+    },
+    Exponent {
+        exponent: f64,
+    },
+    /// Maps the input through a spline, each point being `[input, output]`.
+    Curve {
+        control_points: Vec<[f64; 2]>,
+    },
+    Terrace {
+        control_points: Vec<f64>,
+        inverted: bool,
+    },
+    Add,
+    Multiply,
+    Min,
+    Max,
+    Blend,
+    Select {
+        lower: f64,
+        upper: f64,
+        falloff: f64,
+    },
+    Cache,
+}
+
+impl NodeKind {
+    /// One of every kind, with sensible parameters, for the "add node" menu.
+    pub fn templates() -> Vec<NodeKind> {
+        vec![
+            NodeKind::Perlin {
+                seed: 0,
+                frequency: 1.0,
+            },
+            NodeKind::Fbm {
+                seed: 0,
+                frequency: 1.0,
+                lacunarity: 2.0,
+                persistence: 0.5,
+                octaves: 6,
+            },
+            NodeKind::Billow {
+                seed: 0,
+                frequency: 1.0,
+                lacunarity: 2.0,
+                persistence: 0.5,
+                octaves: 6,
+            },
+            NodeKind::RidgedMulti {
+                seed: 0,
+                frequency: 1.0,
+                lacunarity: 2.0,
+                octaves: 6,
+            },
+            NodeKind::Worley {
+                seed: 0,
+                frequency: 1.0,
+            },
+            NodeKind::Constant { value: 0.0 },
+            NodeKind::Turbulence {
+                seed: 0,
+                frequency: 1.0,
+                power: 0.1,
+                roughness: 3,
+            },
+            NodeKind::ScaleBias {
+                scale: 1.0,
+                bias: 0.0,
+            },
+            NodeKind::Clamp {
+                lower: -1.0,
+                upper: 1.0,
+            },
+            NodeKind::Exponent { exponent: 1.0 },
+            NodeKind::Curve {
+                control_points: vec![[-1.0, -1.0], [-0.5, -0.5], [0.5, 0.5], [1.0, 1.0]],
+            },
+            NodeKind::Terrace {
+                control_points: vec![-1.0, 0.0, 1.0],
+                inverted: false,
+            },
+            NodeKind::Add,
+            NodeKind::Multiply,
+            NodeKind::Min,
+            NodeKind::Max,
+            NodeKind::Blend,
+            NodeKind::Select {
+                lower: 0.0,
+                upper: 1.0,
+                falloff: 0.0,
+            },
+            NodeKind::Cache,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeKind::Perlin { .. } => "Perlin",
+            NodeKind::Fbm { .. } => "Fbm",
+            NodeKind::Billow { .. } => "Billow",
+            NodeKind::RidgedMulti { .. } => "RidgedMulti",
+            NodeKind::Worley { .. } => "Worley",
+            NodeKind::Constant { .. } => "Constant",
+            NodeKind::Turbulence { .. } => "Turbulence",
+            NodeKind::ScaleBias { .. } => "ScaleBias",
+            NodeKind::Clamp { .. } => "Clamp",
+            NodeKind::Exponent { .. } => "Exponent",
+            NodeKind::Curve { .. } => "Curve",
+            NodeKind::Terrace { .. } => "Terrace",
+            NodeKind::Add => "Add",
+            NodeKind::Multiply => "Multiply",
+            NodeKind::Min => "Min",
+            NodeKind::Max => "Max",
+            NodeKind::Blend => "Blend",
+            NodeKind::Select { .. } => "Select",
+            NodeKind::Cache => "Cache",
+        }
+    }
+
+    /// Names of the sources the node reads from.
+    pub fn inputs(&self) -> &'static [&'static str] {
+        match self {
+            NodeKind::Perlin { .. }
+            | NodeKind::Fbm { .. }
+            | NodeKind::Billow { .. }
+            | NodeKind::RidgedMulti { .. }
+            | NodeKind::Worley { .. }
+            | NodeKind::Constant { .. } => &[],
+            NodeKind::Turbulence { .. }
+            | NodeKind::ScaleBias { .. }
+            | NodeKind::Clamp { .. }
+            | NodeKind::Exponent { .. }
+            | NodeKind::Curve { .. }
+            | NodeKind::Terrace { .. }
+            | NodeKind::Cache => &["source"],
+            NodeKind::Add | NodeKind::Multiply | NodeKind::Min | NodeKind::Max => &["a", "b"],
+            NodeKind::Blend | NodeKind::Select { .. } => &["a", "b", "control"],
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TerrainGraphError {
+    #[error("node {0:?} does not exist")]
+    MissingNode(NodeId),
+    #[error("input `{input}` of node {node:?} is not connected")]
+    Unconnected { node: NodeId, input: &'static str },
+    #[error("node {0:?} is part of a cycle")]
+    Cycle(NodeId),
+    #[error("node {node:?} has {found} inputs instead of {expected}")]
+    InputCount {
+        node: NodeId,
+        expected: usize,
+        found: usize,
+    },
+    #[error("{0:?} needs at least {1} control points with distinct inputs")]
+    ControlPoints(NodeId, usize),
+    #[error("the graph has no output node")]
+    NoOutput,
+}
+
+impl GraphNode {
+    /// Whether the node has one input per `NodeKind::inputs`.
+    fn check_inputs(&self) -> Result<(), TerrainGraphError> {
+        let expected = self.kind.inputs().len();
+        if self.inputs.len() != expected {
+            return Err(TerrainGraphError::InputCount {
+                node: self.id,
+                expected,
+                found: self.inputs.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Whether a control point of `Curve` or `Terrace` already sits at `input`.
+pub fn input_taken(mut inputs: impl Iterator<Item = f64>, input: f64) -> bool {
+    inputs.any(|other| (other - input).abs() <= f64::EPSILON)
+}
+
+/// Control points `Curve` and `Terrace` keep: they drop the ones whose input
+/// is already taken.
+pub fn distinct_inputs(inputs: impl Iterator<Item = f64>) -> usize {
+    let mut kept: Vec<f64> = Vec::new();
+    for input in inputs {
+        if !input_taken(kept.iter().copied(), input) {
+            kept.push(input);
+        }
+    }
+    kept.len()
+}
+
+impl TerrainGraph {
+    pub fn node(&self, id: NodeId) -> Option<&GraphNode> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut GraphNode> {
+        self.nodes.iter_mut().find(|node| node.id == id)
+    }
+
+    pub fn add_node(&mut self, kind: NodeKind, position: [f32; 2]) -> NodeId {
+        let id = NodeId(
+            self.nodes
+                .iter()
+                .map(|node| node.id.0 + 1)
+                .max()
+                .unwrap_or(0),
+        );
+        self.nodes.push(GraphNode {
+            id,
+            inputs: vec![None; kind.inputs().len()],
+            kind,
+            position,
+        });
+        id
+    }
+
+    /// Removes the node and unplugs everything it fed.
+    pub fn remove_node(&mut self, id: NodeId) {
+        self.nodes.retain(|node| node.id != id);
+        for node in &mut self.nodes {
+            for input in &mut node.inputs {
+                if *input == Some(id) {
+                    *input = None;
+                }
+            }
+        }
+        if self.output == Some(id) {
+            self.output = None;
+        }
+    }
+
+    /// Plugs `source` into the `input`th input of `node`, unless that would
+    /// loop back on itself.
+    pub fn connect(&mut self, source: NodeId, node: NodeId, input: usize) -> bool {
+        if source == node || self.depends_on(source, node) {
+            return false;
+        }
+
+        match self
+            .node_mut(node)
+            .and_then(|node| node.inputs.get_mut(input))
+        {
+            Some(slot) => {
+                *slot = Some(source);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether `node` reads from `source`, directly or not.
+    pub fn depends_on(&self, node: NodeId, source: NodeId) -> bool {
+        let mut stack = vec![node];
+        let mut visited = Vec::new();
+
+        while let Some(current) = stack.pop() {
+            if current == source {
+                return true;
+            }
+            if visited.contains(&current) {
+                continue;
+            }
+            visited.push(current);
+            if let Some(node) = self.node(current) {
+                stack.extend(node.inputs.iter().flatten());
+            }
+        }
+
+        false
+    }
+
+    pub fn build_output(&self) -> Result<GraphNoise, TerrainGraphError> {
+        self.build(self.output.ok_or(TerrainGraphError::NoOutput)?)
+    }
+
+    /// Noise module computing the value of `id`.
+    pub fn build(&self, id: NodeId) -> Result<GraphNoise, TerrainGraphError> {
+        self.build_node(id, &mut Vec::new())
+    }
+
+    fn build_node(
+        &self,
+        id: NodeId,
+        path: &mut Vec<NodeId>,
+    ) -> Result<GraphNoise, TerrainGraphError> {
+        if path.contains(&id) {
+            return Err(TerrainGraphError::Cycle(id));
+        }
+        let node = self.node(id).ok_or(TerrainGraphError::MissingNode(id))?;
+        node.check_inputs()?;
+
+        path.push(id);
+        let mut sources = Vec::with_capacity(node.inputs.len());
+        for (input, name) in node.inputs.iter().zip(node.kind.inputs()) {
+            let source = input.ok_or(TerrainGraphError::Unconnected {
+                node: id,
+                input: name,
+            })?;
+            sources.push(self.build_node(source, path)?);
+        }
+        path.pop();
+
+        let mut sources = sources.into_iter();
+        let mut source = || sources.next().unwrap();
+
+        let noise: GraphNoise = match &node.kind {
+            NodeKind::Perlin { seed, frequency } => {
+                Box::new(ScalePoint::new(Perlin::new(*seed)).set_scale(*frequency))
+            }
+            NodeKind::Fbm {
+                seed,
+                frequency,
+                lacunarity,
+                persistence,
+                octaves,
+            } => Box::new(
+                Fbm::<Perlin>::new(*seed)
+                    .set_frequency(*frequency)
+                    .set_lacunarity(*lacunarity)
+                    .set_persistence(*persistence)
+                    .set_octaves(*octaves),
+            ),
+            NodeKind::Billow {
+                seed,
+                frequency,
+                lacunarity,
+                persistence,
+                octaves,
+            } => Box::new(
+                Billow::<Perlin>::new(*seed)
+                    .set_frequency(*frequency)
+                    .set_lacunarity(*lacunarity)
+                    .set_persistence(*persistence)
+                    .set_octaves(*octaves),
+            ),
+            NodeKind::RidgedMulti {
+                seed,
+                frequency,
+                lacunarity,
+                octaves,
+            } => Box::new(
+                RidgedMulti::<Perlin>::new(*seed)
+                    .set_frequency(*frequency)
+                    .set_lacunarity(*lacunarity)
+                    .set_octaves(*octaves),
+            ),
+            NodeKind::Worley { seed, frequency } => {
+                Box::new(Worley::new(*seed).set_frequency(*frequency))
+            }
+            NodeKind::Constant { value } => Box::new(Constant::new(*value)),
+            NodeKind::Turbulence {
+                seed,
+                frequency,
+                power,
+                roughness,
+            } => Box::new(
+                Turbulence::<_, Perlin>::new(source())
+                    .set_seed(*seed)
+                    .set_frequency(*frequency)
+                    .set_power(*power)
+                    .set_roughness(*roughness),
+            ),
+            NodeKind::ScaleBias { scale, bias } => {
+                Box::new(ScaleBias::new(source()).set_scale(*scale).set_bias(*bias))
+            }
+            NodeKind::Clamp { lower, upper } => {
+                Box::new(Clamp::new(source()).set_bounds(*lower, *upper))
+            }
+            NodeKind::Exponent { exponent } => {
+                Box::new(Exponent::new(source()).set_exponent(*exponent))
+            }
+            NodeKind::Curve { control_points } => {
+                if distinct_inputs(control_points.iter().map(|[input, _]| *input)) < 4 {
+                    return Err(TerrainGraphError::ControlPoints(id, 4));
+                }
+                let mut curve = Curve::new(source());
+                for [input, output] in control_points {
+                    curve = curve.add_control_point(*input, *output);
+                }
+                Box::new(curve)
+            }
+            NodeKind::Terrace {
+                control_points,
+                inverted,
+            } => {
+                if distinct_inputs(control_points.iter().copied()) < 2 {
+                    return Err(TerrainGraphError::ControlPoints(id, 2));
+                }
+                let mut terrace = Terrace::new(source()).invert_terraces(*inverted);
+                for point in control_points {
+                    terrace = terrace.add_control_point(*point);
+                }
+                Box::new(terrace)
+            }
+            NodeKind::Add => Box::new(noise::Add::new(source(), source())),
+            NodeKind::Multiply => Box::new(Multiply::new(source(), source())),
+            NodeKind::Min => Box::new(Min::new(source(), source())),
+            NodeKind::Max => Box::new(Max::new(source(), source())),
+            NodeKind::Blend => Box::new(Blend::new(source(), source(), source())),
+            NodeKind::Select {
+                lower,
+                upper,
+                falloff,
+            } => Box::new(
+                Select::new(source(), source(), source())
+                    .set_bounds(*lower, *upper)
+                    .set_falloff(*falloff),
+            ),
+            NodeKind::Cache => Box::new(Cache::new(source())),
+        };
+
+        Ok(noise)
+    }
+
+    /// Values of `id` over `resolution * resolution` points, covering
+    /// `2 * extent` noise units from `origin`, row by row.
+    pub fn sample(
+        &self,
+        id: NodeId,
+        extent: f64,
+        resolution: usize,
+        origin: [f64; 2],
+    ) -> Result<Vec<f64>, TerrainGraphError> {
+        let noise = self.build(id)?;
+        let map = PlaneMapBuilder::new(&noise)
+            .set_size(resolution, resolution)
+            .set_x_bounds(origin[0], origin[0] + 2.0 * extent)
+            .set_y_bounds(origin[1], origin[1] + 2.0 * extent)
+            .build();
+
+        let mut values = Vec::with_capacity(resolution * resolution);
+        for y in 0..resolution {
+            for x in 0..resolution {
+                values.push(map.get_value(x, y));
+            }
+        }
+        Ok(values)
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+}
+
+#[derive(Default)]
+pub struct TerrainGraphLoader;
+
+#[derive(Debug, Error)]
+pub enum TerrainGraphLoaderError {
+    #[error("Could not load terrain graph: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse terrain graph: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("Invalid terrain graph: {0}")]
+    Graph(#[from] TerrainGraphError),
+}
+
+impl AssetLoader for TerrainGraphLoader {
+    type Asset = TerrainGraph;
+    type Settings = ();
+    type Error = TerrainGraphLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let graph = ron::de::from_bytes::<TerrainGraph>(&bytes)?;
+            for node in &graph.nodes {
+                node.check_inputs()?;
+            }
+            Ok(graph)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["graph.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::generation::noise::{generate_noise_map, NoiseSettings};
+
+    #[test]
+    fn applied_graph_drives_the_noise_map() {
+        let graph: TerrainGraph = ron::de::from_str(include_str!(
+            "../../assets/terrain_graphs/continents.graph.ron"
+        ))
+        .unwrap();
+        let output = graph.output.unwrap();
+        let expected = graph.sample(output, 0.5, 2, [1.0, -2.0]).unwrap();
+
+        let settings = NoiseSettings {
+            graph: Some(Arc::new(graph)),
+            ..Default::default()
+        };
+        // one more sample on each axis, the corners land on the graph samples
+        let map = generate_noise_map(0.5, 1, 1, [1.0, -2.0], &settings);
+        assert_eq!(map.get_value(0, 0), expected[0]);
+    }
+}
@@ -0,0 +1,200 @@
+use bevy::math::{DVec2, DVec3};
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use futures_lite::future;
+
+use crate::camera::{fog_falloff, FOG_VISIBILITY};
+use crate::origin::WorldOrigin;
+
+use super::chunk::{
+    ChunkLoader, ChunkSettingsParam, RenderDistance, CHUNK_WORLD_SCALE, CHUNK_WORLD_SIZE,
+    HEIGHT_INTENSITY,
+};
+use super::material::TerrainMaterialHandle;
+use super::mesh::create_horizon_mesh;
+use super::quadtree::QuadtreeSettings;
+
+/// Coarse terrain drawn past the render distance, so mountains stay visible
+/// on the horizon from high up instead of the world ending in fog.
+#[derive(Resource, Clone, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct HorizonSettings {
+    pub enabled: bool,
+    /// World units from the center of the horizon to its edge.
+    pub radius: f32,
+    /// Quads along each side of the horizon mesh.
+    pub resolution: usize,
+    /// The horizon follows the camera in steps of this many world units.
+    pub recenter_step: f32,
+    /// World units the horizon is lowered by, so the chunks hide it where they
+    /// overlap.
+    pub sink: f32,
+}
+
+impl Default for HorizonSettings {
+    fn default() -> Self {
+        HorizonSettings {
+            enabled: true,
+            radius: 8192.0,
+            resolution: 128,
+            recenter_step: 256.0,
+            sink: 8.0,
+        }
+    }
+}
+
+impl HorizonSettings {
+    /// Quads left out around the center, which the chunks always cover
+    /// wherever the camera is within a recenter step.
+    fn hole(&self, render_distance: i32) -> usize {
+        let quad_size = 2.0 * self.radius / self.resolution as f32;
+        let covered = (render_distance - 1) as f32 * CHUNK_WORLD_SIZE - self.recenter_step / 2.0;
+
+        (covered / quad_size).max(0.0) as usize
+    }
+}
+
+/// Absolute world position of the center of the horizon.
+#[derive(Component)]
+pub struct Horizon {
+    pub center: DVec2,
+}
+
+#[derive(Component)]
+pub struct HorizonTask {
+    pub task: Task<Mesh>,
+    pub center: DVec2,
+}
+
+/// Whether the horizon is drawn. The quadtree terrain already reaches the
+/// horizon, so none is drawn along with it.
+pub fn horizon_shown(settings: Res<HorizonSettings>, quadtree: Res<QuadtreeSettings>) -> bool {
+    settings.enabled && !quadtree.enabled
+}
+
+/// Throws the horizon and its pending tasks away once it is not drawn.
+pub fn clear_horizon(
+    mut commands: Commands,
+    horizons: Query<(Entity, AnyOf<(&Horizon, &HorizonTask)>)>,
+) {
+    for (entity, _) in horizons.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Regenerates the horizon when the camera moves a recenter step away from
+/// its center, or when the settings change.
+pub fn update_horizon(
+    mut commands: Commands,
+    settings: Res<HorizonSettings>,
+    render_distance: Res<RenderDistance>,
+    chunk_settings: ChunkSettingsParam,
+    player_query: Query<&Transform, With<ChunkLoader>>,
+    origin: Res<WorldOrigin>,
+    horizons: Query<(Entity, AnyOf<(&Horizon, &HorizonTask)>)>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let step = settings.recenter_step as f64;
+    let center = (origin.to_world_xz(player_transform.translation) / step).round() * step;
+
+    let up_to_date = horizons
+        .iter()
+        .any(|(_, (horizon, _))| horizon.is_some_and(|horizon| horizon.center == center));
+    let pending = horizons
+        .iter()
+        .any(|(_, (_, task))| task.is_some_and(|task| task.center == center));
+    let changed = settings.is_changed() || render_distance.is_changed();
+    if !changed && (up_to_date || pending) {
+        return;
+    }
+
+    for (entity, (_, task)) in horizons.iter() {
+        if task.is_some() {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    let horizon = settings.clone();
+    let hole = horizon.hole(render_distance.0);
+    let chunk_settings = chunk_settings.get();
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        // same noise scale as the chunks, see `QuadtreeNode::new`
+        let scale = CHUNK_WORLD_SCALE as f64;
+        let min = center - horizon.radius as f64;
+        create_horizon_mesh(
+            2.0 * horizon.radius as f64 / scale,
+            HEIGHT_INTENSITY,
+            horizon.resolution,
+            hole,
+            [min.x * 2.0 / scale, min.y * 2.0 / scale],
+            &chunk_settings,
+        )
+        .mesh
+    });
+
+    commands.spawn(HorizonTask { task, center });
+}
+
+pub fn handle_horizon_tasks(
+    mut commands: Commands,
+    mut horizon_tasks: Query<(Entity, &mut HorizonTask)>,
+    horizons: Query<Entity, With<Horizon>>,
+    settings: Res<HorizonSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<TerrainMaterialHandle>,
+    origin: Res<WorldOrigin>,
+) {
+    for (entity, mut task) in &mut horizon_tasks {
+        if let Some(mesh) = block_on(future::poll_once(&mut task.task)) {
+            for horizon in horizons.iter() {
+                commands.entity(horizon).despawn_recursive();
+            }
+
+            let center = task.center;
+            commands
+                .entity(entity)
+                .insert((
+                    MaterialMeshBundle {
+                        mesh: meshes.add(mesh),
+                        material: material.0.clone(),
+                        transform: Transform {
+                            translation: origin.to_local(DVec3::new(
+                                center.x,
+                                -settings.sink as f64,
+                                center.y,
+                            )),
+                            scale: Vec3::splat(CHUNK_WORLD_SCALE),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    NotShadowCaster,
+                    Horizon { center },
+                ))
+                .remove::<HorizonTask>();
+        }
+    }
+}
+
+/// Pushes the fog back to the edge of the horizon while it is drawn.
+pub fn update_horizon_fog(
+    settings: Res<HorizonSettings>,
+    quadtree: Res<QuadtreeSettings>,
+    mut fogs: Query<&mut FogSettings>,
+) {
+    if !settings.is_changed() && !quadtree.is_changed() {
+        return;
+    }
+
+    let visibility = if settings.enabled && !quadtree.enabled {
+        settings.radius
+    } else {
+        FOG_VISIBILITY
+    };
+    for mut fog in &mut fogs {
+        fog.falloff = fog_falloff(visibility);
+    }
+}
@@ -0,0 +1,333 @@
+use bevy::pbr::{
+    ExtendedMaterial, MaterialExtension, MaterialExtensionKey, MaterialExtensionPipeline,
+};
+use bevy::prelude::*;
+use bevy::render::mesh::{MeshVertexAttribute, MeshVertexBufferLayout};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{
+    AsBindGroup, Extent3d, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+    TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension, VertexFormat,
+};
+use bevy::render::texture::{
+    ImageAddressMode, ImageFilterMode, ImageSampler, ImageSamplerDescriptor,
+};
+use noise::utils::{NoiseMapBuilder, PlaneMapBuilder};
+use noise::{Fbm, MultiFractal, Perlin};
+
+use super::biome::{Biome, GroundMaterial};
+
+/// Weights of the grass, rock, sand and snow textures of every vertex.
+pub const ATTRIBUTE_SPLAT: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_Splat", 988540918, VertexFormat::Float32x4);
+
+/// Height offset to the coarser quadtree level, in mesh units, followed by the
+/// camera distances at which the vertex starts and ends morphing to it.
+pub const ATTRIBUTE_MORPH: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_Morph", 988540919, VertexFormat::Float32x4);
+
+const TERRAIN_SHADER: &str = "shaders/terrain.wgsl";
+
+const TEXTURE_SIZE: u32 = 256;
+
+const TERRAIN_ALPHA: f32 = 1.0;
+
+/// Slope, in degrees, at which the ground starts turning to rock and at which
+/// it is only rock.
+const ROCK_SLOPE: (f32, f32) = (30.0, 45.0);
+
+pub type TerrainMaterial = ExtendedMaterial<StandardMaterial, TerrainExtension>;
+
+pub use self::uniform::TerrainMaterialSettings;
+
+// `ShaderType` derives a size check per field that is never called, a false
+// positive of the dead code lint
+#[allow(dead_code)]
+mod uniform {
+    use bevy::prelude::*;
+    use bevy::render::render_resource::ShaderType;
+
+    #[derive(Clone, Copy, Debug, ShaderType, Reflect)]
+    pub struct TerrainMaterialSettings {
+        /// World units covered by one repetition of the textures.
+        pub texture_scale: f32,
+        /// Higher values narrow the blend between the triplanar projections.
+        pub triplanar_sharpness: f32,
+        /// How much of the vertex colors tints the textures.
+        pub vertex_color_tint: f32,
+        pub normal_strength: f32,
+        /// Shows the vertex colors alone, set by the debug colorings.
+        pub debug_vertex_colors: f32,
+    }
+}
+
+impl Default for TerrainMaterialSettings {
+    fn default() -> Self {
+        TerrainMaterialSettings {
+            texture_scale: 8.0,
+            triplanar_sharpness: 4.0,
+            vertex_color_tint: 0.35,
+            normal_strength: 1.0,
+            debug_vertex_colors: 0.0,
+        }
+    }
+}
+
+/// Blends tiling albedo and normal textures over the terrain, following the
+/// splat weights of the mesh vertices.
+#[derive(Asset, AsBindGroup, Clone, Debug, Reflect)]
+pub struct TerrainExtension {
+    #[uniform(100)]
+    pub settings: TerrainMaterialSettings,
+    #[texture(101, dimension = "2d_array")]
+    #[sampler(102)]
+    pub albedo: Handle<Image>,
+    #[texture(103, dimension = "2d_array")]
+    #[sampler(104)]
+    pub normal: Handle<Image>,
+}
+
+impl MaterialExtension for TerrainExtension {
+    fn vertex_shader() -> ShaderRef {
+        TERRAIN_SHADER.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        TERRAIN_SHADER.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // shadows and prepasses keep the standard vertex shader
+        if descriptor.label.as_deref() == Some("prepass_pipeline") {
+            return Ok(());
+        }
+
+        let mut attributes = vec![
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(5),
+            ATTRIBUTE_SPLAT.at_shader_location(8),
+        ];
+        // only quadtree nodes geomorph
+        if layout.contains(ATTRIBUTE_MORPH) {
+            attributes.push(ATTRIBUTE_MORPH.at_shader_location(9));
+            descriptor.vertex.shader_defs.push("TERRAIN_MORPH".into());
+        }
+        descriptor.vertex.buffers = vec![layout.get_layout(&attributes)?];
+
+        Ok(())
+    }
+}
+
+/// Texture arrays of the terrain material, one layer per [`GroundMaterial`].
+#[derive(Resource)]
+pub struct TerrainTextures {
+    pub albedo: Handle<Image>,
+    pub normal: Handle<Image>,
+}
+
+/// Material shared by every chunk, so they can be batched together.
+#[derive(Resource)]
+pub struct TerrainMaterialHandle(pub Handle<TerrainMaterial>);
+
+/// Central settings of the terrain material. Changes are copied to the shared
+/// material asset.
+#[derive(Resource, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct TerrainMaterialConfig {
+    pub roughness: f32,
+    pub alpha: f32,
+    /// Multiplied with the terrain color, handy to tell the terrain apart
+    /// while debugging.
+    pub debug_tint: Color,
+    pub splat: TerrainMaterialSettings,
+}
+
+impl Default for TerrainMaterialConfig {
+    fn default() -> Self {
+        TerrainMaterialConfig {
+            roughness: 0.9,
+            alpha: TERRAIN_ALPHA,
+            debug_tint: Color::WHITE,
+            splat: TerrainMaterialSettings::default(),
+        }
+    }
+}
+
+impl TerrainMaterialConfig {
+    fn apply(&self, material: &mut TerrainMaterial) {
+        material.base.base_color = self.debug_tint.with_a(self.alpha);
+        material.base.alpha_mode = if self.alpha < 1.0 {
+            AlphaMode::Blend
+        } else {
+            AlphaMode::Opaque
+        };
+        material.base.perceptual_roughness = self.roughness;
+        material.extension.settings = self.splat;
+    }
+}
+
+impl GroundMaterial {
+    /// Layer of the ground in the terrain texture arrays and splat weights.
+    pub fn layer(self) -> usize {
+        match self {
+            GroundMaterial::Grass => 0,
+            GroundMaterial::Rock => 1,
+            GroundMaterial::Sand => 2,
+            GroundMaterial::Snow => 3,
+        }
+    }
+
+    fn texture(self) -> ([f32; 3], [f32; 3], f64) {
+        // base color, variation color and noise frequency
+        match self {
+            GroundMaterial::Grass => ([0.2, 0.42, 0.1], [0.35, 0.55, 0.15], 8.0),
+            GroundMaterial::Rock => ([0.38, 0.36, 0.33], [0.55, 0.52, 0.48], 5.0),
+            GroundMaterial::Sand => ([0.85, 0.75, 0.52], [0.75, 0.63, 0.42], 12.0),
+            GroundMaterial::Snow => ([0.92, 0.95, 0.98], [0.8, 0.85, 0.92], 4.0),
+        }
+    }
+}
+
+pub fn splat_weights(biome: Biome, slope: f32) -> [f32; 4] {
+    let mut weights = [0.0; 4];
+    weights[biome.ground().layer()] = 1.0;
+
+    // steep faces show rock whatever the biome
+    let t = ((slope - ROCK_SLOPE.0) / (ROCK_SLOPE.1 - ROCK_SLOPE.0)).clamp(0.0, 1.0);
+    let rock = t * t * (3.0 - 2.0 * t);
+    for weight in weights.iter_mut() {
+        *weight *= 1.0 - rock;
+    }
+    weights[GroundMaterial::Rock.layer()] += rock;
+
+    weights
+}
+
+/// Creates the shared terrain material. Called when building the plugin so the
+/// material exists before any chunk.
+pub fn init_terrain_material(world: &mut World) {
+    let textures = build_terrain_textures(&mut world.resource_mut::<Assets<Image>>());
+    let config = TerrainMaterialConfig::default();
+
+    let mut material = TerrainMaterial {
+        base: StandardMaterial::default(),
+        extension: TerrainExtension {
+            settings: config.splat,
+            albedo: textures.albedo.clone(),
+            normal: textures.normal.clone(),
+        },
+    };
+    config.apply(&mut material);
+    let handle = world
+        .resource_mut::<Assets<TerrainMaterial>>()
+        .add(material);
+
+    world.insert_resource(textures);
+    world.insert_resource(config);
+    world.insert_resource(TerrainMaterialHandle(handle));
+}
+
+pub fn update_terrain_material(
+    config: Res<TerrainMaterialConfig>,
+    handle: Res<TerrainMaterialHandle>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+
+    if let Some(material) = materials.get_mut(&handle.0) {
+        config.apply(material);
+    }
+}
+
+/// Builds the tiling texture arrays from noise, so the terrain does not
+/// depend on image files.
+fn build_terrain_textures(images: &mut Assets<Image>) -> TerrainTextures {
+    let grounds = [
+        GroundMaterial::Grass,
+        GroundMaterial::Rock,
+        GroundMaterial::Sand,
+        GroundMaterial::Snow,
+    ];
+
+    let size = TEXTURE_SIZE as usize;
+    let mut albedo = Vec::with_capacity(size * size * 4 * grounds.len());
+    let mut normal = Vec::with_capacity(size * size * 4 * grounds.len());
+
+    for (seed, ground) in grounds.iter().enumerate() {
+        let (base, variation, frequency) = ground.texture();
+        let fbm = Fbm::<Perlin>::new(seed as u32)
+            .set_frequency(frequency)
+            .set_octaves(5);
+        let map = PlaneMapBuilder::new(fbm)
+            .set_size(size, size)
+            .set_x_bounds(0.0, 1.0)
+            .set_y_bounds(0.0, 1.0)
+            .set_is_seamless(true)
+            .build();
+        let height = |x: usize, y: usize| map.get_value(x % size, y % size) as f32;
+
+        for y in 0..size {
+            for x in 0..size {
+                let t = (height(x, y) * 0.5 + 0.5).clamp(0.0, 1.0);
+                for channel in 0..3 {
+                    let value = base[channel] + (variation[channel] - base[channel]) * t;
+                    albedo.push((value * 255.0) as u8);
+                }
+                albedo.push(255);
+
+                let dx = height(x + 1, y) - height(x + size - 1, y);
+                let dy = height(x, y + 1) - height(x, y + size - 1);
+                let n = Vec3::new(-dx * 2.0, -dy * 2.0, 1.0).normalize() * 0.5 + 0.5;
+                normal.extend([
+                    (n.x * 255.0) as u8,
+                    (n.y * 255.0) as u8,
+                    (n.z * 255.0) as u8,
+                    255,
+                ]);
+            }
+        }
+    }
+
+    let albedo = texture_array(albedo, grounds.len() as u32, TextureFormat::Rgba8UnormSrgb);
+    let normal = texture_array(normal, grounds.len() as u32, TextureFormat::Rgba8Unorm);
+
+    TerrainTextures {
+        albedo: images.add(albedo),
+        normal: images.add(normal),
+    }
+}
+
+fn texture_array(data: Vec<u8>, layers: u32, format: TextureFormat) -> Image {
+    let mut image = Image::new(
+        Extent3d {
+            width: TEXTURE_SIZE,
+            height: TEXTURE_SIZE,
+            depth_or_array_layers: layers,
+        },
+        TextureDimension::D2,
+        data,
+        format,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..default()
+    });
+    image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::Repeat,
+        address_mode_v: ImageAddressMode::Repeat,
+        mag_filter: ImageFilterMode::Linear,
+        min_filter: ImageFilterMode::Linear,
+        ..default()
+    });
+
+    image
+}
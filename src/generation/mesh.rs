@@ -1,33 +1,162 @@
+use bevy::math::I64Vec2;
 use bevy::prelude::*;
 use bevy::render::render_asset::RenderAssetUsages;
 use bevy::render::render_resource::PrimitiveTopology;
+use bevy::utils::{Duration, Instant};
 
+use super::biome::{scatter_props, BiomeMap, PropInstance, ATTRIBUTE_BIOME};
+use super::chunk::ChunkSettings;
+use super::color_ramp::terrain_colors;
+use super::material::{splat_weights, ATTRIBUTE_MORPH, ATTRIBUTE_SPLAT};
 use super::noise::generate_noise_map;
+use super::rtin;
 
-const SNOW_HEIGHT: f32 = 0.06;
-const OCEAN_HEIGHT: f32 = -0.14;
+/// How chunk heightmaps are turned into triangles.
+#[derive(Resource, Clone, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct TerrainMeshing {
+    /// Simplifies flat areas with a right-triangulated irregular network
+    /// instead of meshing the full grid.
+    pub adaptive: bool,
+    /// Largest vertical distance, in mesh units, between the adaptive mesh and
+    /// the heightmap. Chunks are scaled up by `CHUNK_WORLD_SCALE` in the world.
+    pub max_error: f32,
+}
+
+impl Default for TerrainMeshing {
+    fn default() -> Self {
+        TerrainMeshing {
+            adaptive: true,
+            max_error: 0.001,
+        }
+    }
+}
+
+/// What a terrain mesh is built for, which decides how it is triangulated.
+#[derive(Clone, Copy)]
+enum MeshKind {
+    Chunk(I64Vec2),
+    Node {
+        morph_range: [f32; 2],
+    },
+    /// Full grid, minus the squares closer than `hole` quads to its center.
+    Horizon {
+        hole: usize,
+    },
+}
+
+pub struct TerrainMesh {
+    pub mesh: Mesh,
+    /// Heights of the full `(width + 1) * (depth + 1)` grid, in mesh units,
+    /// whatever vertices the mesh kept.
+    pub heights: Vec<f32>,
+    pub biomes: BiomeMap,
+    pub props: Vec<PropInstance>,
+    pub timings: MeshTimings,
+}
+
+/// Time spent building a terrain mesh.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeshTimings {
+    /// Sampling the heightmap from the noise.
+    pub noise: Duration,
+    /// Everything after: normals, biomes, triangles and attributes.
+    pub mesh: Duration,
+}
 
 // create_mesh function taken from : https://gitlab.lejondahl.com/bevy/bevy_holo
+/// Mesh of the chunk at `chunk`, covering `2 * size` noise units around its
+/// center. Chunks are `2 * size` noise units apart, so they tile exactly.
 pub fn create_mesh(
     // seed: u32,
     size: f64,
     intensity: f32,
     width: usize,
     depth: usize,
-    chunk: Vec2,
-) -> Mesh {
+    chunk: I64Vec2,
+    settings: &ChunkSettings,
+) -> TerrainMesh {
+    let origin = [
+        -size + chunk.x as f64 * size * 2.0,
+        -size + chunk.y as f64 * size * 2.0,
+    ];
+
+    build_mesh(
+        size,
+        intensity,
+        width,
+        depth,
+        origin,
+        MeshKind::Chunk(chunk),
+        settings,
+    )
+}
+
+/// Mesh of a quadtree node, covering `2 * size` noise units from `origin`
+/// with a full `resolution * resolution` grid.
+///
+/// Every vertex also gets the height of the twice coarser parent grid, which
+/// the terrain shader morphs to between `morph_range` camera distances.
+pub fn create_node_mesh(
+    size: f64,
+    intensity: f32,
+    resolution: usize,
+    origin: [f64; 2],
+    morph_range: [f32; 2],
+    settings: &ChunkSettings,
+) -> TerrainMesh {
+    build_mesh(
+        size,
+        intensity,
+        resolution,
+        resolution,
+        origin,
+        MeshKind::Node { morph_range },
+        settings,
+    )
+}
+
+/// Coarse mesh of the distant terrain, covering `2 * size` noise units from
+/// `origin`. The squares closer than `hole` quads to its center are left out,
+/// as the chunks cover them.
+pub fn create_horizon_mesh(
+    size: f64,
+    intensity: f32,
+    resolution: usize,
+    hole: usize,
+    origin: [f64; 2],
+    settings: &ChunkSettings,
+) -> TerrainMesh {
+    build_mesh(
+        size,
+        intensity,
+        resolution,
+        resolution,
+        origin,
+        MeshKind::Horizon { hole },
+        settings,
+    )
+}
+
+fn build_mesh(
+    size: f64,
+    intensity: f32,
+    width: usize,
+    depth: usize,
+    origin: [f64; 2],
+    kind: MeshKind,
+    settings: &ChunkSettings,
+) -> TerrainMesh {
     let extent: f64 = size;
     let intensity = intensity;
     let width: usize = width;
     let depth: usize = depth;
 
-    let mut offset = 0;
-    if depth == 16 {
-        offset = 1;
-    }
-
-    // Create noisemap
-    let noisemap = generate_noise_map(extent, width, depth, chunk);
+    // Create noisemap, with what was sculpted on top
+    let start = Instant::now();
+    let mut noisemap = generate_noise_map(extent, width, depth, origin, &settings.noise);
+    settings.edits.apply(&mut noisemap, extent, origin);
+    let noise_time = start.elapsed();
 
     let vertices_count: usize = (width + 1) * (depth + 1);
     let triangle_count: usize = width * depth * 2 * 3;
@@ -39,7 +168,6 @@ pub fn create_mesh(
 
     // Defining vertices
     let mut positions: Vec<[f32; 3]> = Vec::with_capacity(vertices_count);
-    let mut normals: Vec<[f32; 3]> = Vec::with_capacity(vertices_count);
     let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(vertices_count);
 
     for d in 0..=width {
@@ -52,24 +180,67 @@ pub fn create_mesh(
                 (d_f32 - depth_f32 / 2.) * extent_f32 / depth_f32,
             ];
             positions.push(pos);
-            normals.push([0.0, 1.0, 0.0]);
             uvs.push([w_f32 / width_f32, d_f32 / depth_f32]);
         }
     }
 
+    let heights: Vec<f32> = positions.iter().map(|[_, y, _]| *y).collect();
+    let mut normals = compute_normals(&heights, width, depth, extent_f32);
+
+    // Biomes are sampled at the same noise coordinates as the heights
+    let step = 2.0 * extent / width as f64;
+    let biomes = BiomeMap::generate(&settings.biome_table, &heights, width, depth, origin, step);
+    let props = match kind {
+        MeshKind::Chunk(chunk) => scatter_props(&biomes, &positions, &normals, chunk),
+        _ => Vec::new(),
+    };
+
     // Defining triangles
     let mut triangles: Vec<u32> = Vec::with_capacity(triangle_count);
+    let adaptive = settings.meshing.adaptive
+        && matches!(kind, MeshKind::Chunk(_))
+        && width == depth
+        && width.is_power_of_two();
+
+    if let MeshKind::Node { .. } | MeshKind::Horizon { .. } = kind {
+        let hole = match kind {
+            MeshKind::Horizon { hole } => hole as u32,
+            _ => 0,
+        };
+        let (center_w, center_d) = (width_u32 / 2, depth_u32 / 2);
 
-    for d in 0..depth_u32 - 1 - offset {
-        for w in 0..width_u32 - 1 - offset {
-            // First tringle
-            triangles.push((d * (width_u32 + 1)) + w);
-            triangles.push(((d + 1) * (width_u32 + 1)) + w);
-            triangles.push(((d + 1) * (width_u32 + 1)) + w + 1);
-            // Second triangle
-            triangles.push((d * (width_u32 + 1)) + w);
-            triangles.push(((d + 1) * (width_u32 + 1)) + w + 1);
-            triangles.push((d * (width_u32 + 1)) + w + 1);
+        // quadtree nodes keep their last row and column to meet their neighbors
+        for d in 0..depth_u32 {
+            for w in 0..width_u32 {
+                let inside_w = w + hole >= center_w && w < center_w + hole;
+                let inside_d = d + hole >= center_d && d < center_d + hole;
+                if inside_w && inside_d {
+                    continue;
+                }
+
+                triangles.push((d * (width_u32 + 1)) + w);
+                triangles.push(((d + 1) * (width_u32 + 1)) + w);
+                triangles.push(((d + 1) * (width_u32 + 1)) + w + 1);
+                triangles.push((d * (width_u32 + 1)) + w);
+                triangles.push(((d + 1) * (width_u32 + 1)) + w + 1);
+                triangles.push((d * (width_u32 + 1)) + w + 1);
+            }
+        }
+    } else if adaptive {
+        triangles = rtin::triangulate(&heights, width + 1, settings.meshing.max_error);
+    } else {
+        // the full grid, like the adaptive triangulation
+        for d in 0..depth_u32 {
+            for w in 0..width_u32 {
+                // First tringle
+                triangles.push((d * (width_u32 + 1)) + w);
+                triangles.push(((d + 1) * (width_u32 + 1)) + w);
+                triangles.push(((d + 1) * (width_u32 + 1)) + w + 1);
+                // Second triangle
+                triangles.push((d * (width_u32 + 1)) + w);
+                triangles.push(((d + 1) * (width_u32 + 1)) + w + 1);
+                triangles.push((d * (width_u32 + 1)) + w + 1);
+            }
         }
     }
 
@@ -78,39 +249,190 @@ pub fn create_mesh(
         RenderAssetUsages::default(),
     );
 
-    mesh.insert_indices(bevy::render::mesh::Indices::U32(triangles));
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions.clone());
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-    let colors: Vec<[f32; 4]> = positions
+    let mut colors = terrain_colors(
+        settings.color_ramp.as_ref(),
+        &biomes.biomes,
+        &positions,
+        &normals,
+    );
+    let mut splats: Vec<[f32; 4]> = biomes
+        .biomes
         .iter()
-        .map(|[_, y, _]| {
-            let r: f32;
-            let g: f32;
-            let b: f32;
-
-            match *y {
-                y if y > SNOW_HEIGHT => {
-                    r = 0.8;
-                    g = 1.0;
-                    b = 0.9;
-                } // white: snow
-                y if y < OCEAN_HEIGHT => {
-                    r = 0.1;
-                    g = 0.3;
-                    b = 0.9;
-                } // blue: ocean
-                _ => {
-                    r = 0.2;
-                    g = 0.9;
-                    b = 0.1;
-                } // green: land
-            }
-
-            [r, g, b, 1.0]
+        .zip(&normals)
+        .map(|(biome, normal)| {
+            splat_weights(
+                *biome,
+                Vec3::from(*normal).angle_between(Vec3::Y).to_degrees(),
+            )
         })
         .collect();
+    let mut biome_ids: Vec<u32> = biomes.biomes.iter().map(|biome| biome.id()).collect();
+
+    // Drop the vertices the adaptive triangulation or the horizon hole do not use
+    if adaptive || matches!(kind, MeshKind::Horizon { .. }) {
+        let kept = compact_vertices(&mut triangles, vertices_count);
+        positions = retain_vertices(&positions, &kept);
+        normals = retain_vertices(&normals, &kept);
+        uvs = retain_vertices(&uvs, &kept);
+        colors = retain_vertices(&colors, &kept);
+        splats = retain_vertices(&splats, &kept);
+        biome_ids = retain_vertices(&biome_ids, &kept);
+    }
+
+    mesh.insert_indices(bevy::render::mesh::Indices::U32(triangles));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
     mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.insert_attribute(ATTRIBUTE_BIOME, biome_ids);
+    mesh.insert_attribute(ATTRIBUTE_SPLAT, splats);
+
+    if let MeshKind::Node {
+        morph_range: [start, end],
+    } = kind
+    {
+        let morphs: Vec<[f32; 4]> = parent_heights(&heights, width, depth)
+            .into_iter()
+            .zip(&heights)
+            .map(|(parent, height)| [parent - height, start, end, 0.0])
+            .collect();
+        mesh.insert_attribute(ATTRIBUTE_MORPH, morphs);
+    }
+
+    TerrainMesh {
+        mesh,
+        heights,
+        biomes,
+        props,
+        timings: MeshTimings {
+            noise: noise_time,
+            mesh: start.elapsed() - noise_time,
+        },
+    }
+}
+
+/// Heights of the grid with half the vertices, interpolated along the
+/// triangle edges so a fully morphed node matches its coarser neighbors.
+fn parent_heights(heights: &[f32], width: usize, depth: usize) -> Vec<f32> {
+    let height = |w: usize, d: usize| heights[d * (width + 1) + w];
+
+    let mut parents = Vec::with_capacity(heights.len());
+    for d in 0..=depth {
+        for w in 0..=width {
+            let parent = match (w % 2, d % 2) {
+                (0, 0) => height(w, d),
+                (1, 0) => (height(w - 1, d) + height(w + 1, d)) / 2.0,
+                (0, _) => (height(w, d - 1) + height(w, d + 1)) / 2.0,
+                // on the diagonal the triangles are split along
+                _ => (height(w - 1, d - 1) + height(w + 1, d + 1)) / 2.0,
+            };
+            parents.push(parent);
+        }
+    }
+
+    parents
+}
+
+/// Renumbers the triangle indices to only count the vertices in use, and
+/// returns the original index of each vertex kept.
+fn compact_vertices(triangles: &mut [u32], vertices_count: usize) -> Vec<usize> {
+    let mut remap = vec![u32::MAX; vertices_count];
+    let mut kept = Vec::new();
 
-    mesh
+    for index in triangles.iter_mut() {
+        let old = *index as usize;
+        if remap[old] == u32::MAX {
+            remap[old] = kept.len() as u32;
+            kept.push(old);
+        }
+        *index = remap[old];
+    }
+
+    kept
+}
+
+fn retain_vertices<T: Copy>(values: &[T], kept: &[usize]) -> Vec<T> {
+    kept.iter().map(|&index| values[index]).collect()
+}
+
+/// Smooth normals of a `(width + 1) * (depth + 1)` heightfield, from the
+/// height differences between neighboring vertices.
+pub fn compute_normals(heights: &[f32], width: usize, depth: usize, extent: f32) -> Vec<[f32; 3]> {
+    let height = |w: usize, d: usize| heights[d * (width + 1) + w];
+    let (step_x, step_z) = (extent / width as f32, extent / depth as f32);
+
+    let mut normals = Vec::with_capacity(heights.len());
+    for d in 0..=depth {
+        for w in 0..=width {
+            let (left, right) = (w.saturating_sub(1), (w + 1).min(width));
+            let (back, front) = (d.saturating_sub(1), (d + 1).min(depth));
+
+            let dx = (height(right, d) - height(left, d)) / ((right - left) as f32 * step_x);
+            let dz = (height(w, front) - height(w, back)) / ((front - back) as f32 * step_z);
+
+            normals.push(Vec3::new(-dx, 1.0, -dz).normalize().to_array());
+        }
+    }
+
+    normals
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::mesh::VertexAttributeValues;
+
+    use super::*;
+    use crate::generation::chunk::{HEIGHT_INTENSITY, MAP_SIZE, NORMAL_LOD};
+
+    fn settings(adaptive: bool) -> ChunkSettings {
+        ChunkSettings {
+            biome_table: Default::default(),
+            color_ramp: None,
+            meshing: TerrainMeshing {
+                adaptive,
+                ..Default::default()
+            },
+            noise: Default::default(),
+            edits: Default::default(),
+        }
+    }
+
+    /// Depths and heights of the vertices of a chunk mesh lying at `x`.
+    fn column(chunk: I64Vec2, x: f32, settings: &ChunkSettings) -> Vec<[f32; 2]> {
+        let terrain = create_mesh(
+            MAP_SIZE,
+            HEIGHT_INTENSITY,
+            NORMAL_LOD,
+            NORMAL_LOD,
+            chunk,
+            settings,
+        );
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            terrain.mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("chunk mesh without positions");
+        };
+
+        let mut column: Vec<[f32; 2]> = positions
+            .iter()
+            .filter(|[px, _, _]| *px == x)
+            .map(|[_, y, z]| [*z, *y])
+            .collect();
+        column.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        column
+    }
+
+    #[test]
+    fn neighbors_share_their_border_vertices() {
+        let half = (MAP_SIZE / 2.0) as f32;
+
+        for adaptive in [false, true] {
+            let settings = settings(adaptive);
+            let left = column(I64Vec2::new(3, -2), half, &settings);
+            let right = column(I64Vec2::new(4, -2), -half, &settings);
+
+            assert_eq!(left.len(), NORMAL_LOD + 1);
+            assert_eq!(left, right);
+        }
+    }
 }
@@ -1,16 +1,100 @@
 use bevy::prelude::*;
 
+pub mod biome;
 pub mod chunk;
+pub mod color_ramp;
+pub mod commands;
+pub mod debug;
+pub mod diagnostics;
+pub mod edits;
+pub mod graph;
+pub mod horizon;
+pub mod material;
 pub mod mesh;
 pub mod noise;
+pub mod quadtree;
+pub mod rtin;
 
+use self::biome::*;
 use self::chunk::*;
+use self::color_ramp::*;
+use self::commands::*;
+use self::debug::*;
+use self::diagnostics::*;
+use self::edits::TerrainEdits;
+use self::graph::*;
+use self::horizon::*;
+use self::material::*;
+use self::mesh::TerrainMeshing;
+use self::noise::NoiseSettings;
+use self::quadtree::*;
 
 pub struct GenerationPlugin;
 
 impl Plugin for GenerationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_chunks);
+        app.add_plugins((
+            MaterialPlugin::<TerrainMaterial>::default(),
+            ChunkDiagnosticsPlugin,
+        ));
+        init_terrain_material(&mut app.world);
+        add_terrain_commands(app);
+        app.init_resource::<BiomeTable>()
+            .register_type::<BiomeTable>()
+            .init_resource::<RenderDistance>()
+            .register_type::<RenderDistance>()
+            .init_resource::<NoiseSettings>()
+            .register_type::<NoiseSettings>()
+            .init_resource::<TerrainEdits>()
+            .init_resource::<TerrainMeshing>()
+            .register_type::<TerrainMeshing>()
+            .init_resource::<QuadtreeSettings>()
+            .register_type::<QuadtreeSettings>()
+            .init_resource::<HorizonSettings>()
+            .register_type::<HorizonSettings>()
+            .init_asset::<TerrainColorRamp>()
+            .init_asset_loader::<TerrainColorRampLoader>()
+            .register_asset_reflect::<TerrainColorRamp>()
+            .init_asset::<TerrainGraph>()
+            .init_asset_loader::<TerrainGraphLoader>()
+            .register_type::<TerrainColoring>()
+            .register_type::<TerrainMaterialConfig>()
+            .init_resource::<TerrainDebugMode>()
+            .register_type::<TerrainDebugMode>();
+        app.add_systems(
+            Startup,
+            (
+                setup_coloring,
+                setup_chunks
+                    .after(setup_coloring)
+                    .run_if(not(quadtree_enabled)),
+                setup_props,
+                setup_debug_panel,
+            ),
+        );
+        app.add_systems(
+            Update,
+            (
+                spawn_chunk_props,
+                recolor_chunks,
+                apply_debug_coloring.after(recolor_chunks),
+                update_terrain_material.after(apply_debug_coloring),
+                (debug_mode_hotkeys, debug_panel_buttons, update_debug_panel).chain(),
+                apply_debug_wireframe,
+                draw_chunk_borders,
+                update_chunk_labels,
+                draw_normals,
+                draw_task_states,
+                (
+                    clear_horizon.run_if(not(horizon_shown)),
+                    update_horizon.run_if(horizon_shown),
+                    handle_horizon_tasks,
+                )
+                    .chain(),
+                update_horizon_fog,
+                regenerate_terrain,
+            ),
+        );
         app.add_systems(
             FixedUpdate,
             (
@@ -19,6 +103,17 @@ impl Plugin for GenerationPlugin {
                 handle_replace_tasks,
                 handle_chunk_tasks,
                 remove_chunks,
+            )
+                .chain()
+                .run_if(not(quadtree_enabled)),
+        );
+        app.add_systems(
+            FixedUpdate,
+            (
+                switch_terrain_mode,
+                (stream_quadtree, handle_quadtree_tasks)
+                    .chain()
+                    .run_if(quadtree_enabled),
             )
                 .chain(),
         );
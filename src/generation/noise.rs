@@ -1,104 +1,158 @@
 extern crate noise;
 
-use bevy::math::Vec2;
+use std::sync::Arc;
+
+use bevy::prelude::{ReflectResource, Resource};
+use bevy::reflect::Reflect;
 use noise::utils::{NoiseMap, NoiseMapBuilder, PlaneMapBuilder};
 use noise::{core::worley::ReturnType, *};
 
-// example from : https://github.com/Razaekel/noise-rs/blob/develop/examples/complexplanet.rs
-#[allow(non_snake_case)]
-pub fn generate_noise_map(
-    extent: f64,
-    width: usize,
-    depth: usize,
-    chunk_location: Vec2,
-) -> NoiseMap {
-    /// Planet seed. Change this to generate a different planet.
-    const CURRENT_SEED: u32 = 9823247;
+use super::graph::TerrainGraph;
+
+/// Specifies the planet's sea level. This value must be between -1.0
+/// (minimum planet elevation) and +1.0 (maximum planet elevation).
+pub const SEA_LEVEL: f64 = 0.0;
 
+/// Parameters of the planet noise, copied into every generation task.
+#[derive(Resource, Clone, Debug, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct NoiseSettings {
+    /// Planet seed. Change this to generate a different planet.
+    pub seed: u32,
     /// Frequency of the planet's continents. Higher frequency produces
     /// smaller, more numerous continents. This value is measured in radians.
-    const CONTINENT_FREQUENCY: f64 = 1.0;
-
+    pub continent_frequency: f64,
     /// Lacunarity of the planet's continents. Changing this value produces
     /// slightly different continents. For the best results, this value should
     /// be random, but close to 2.0.
-    const CONTINENT_LACUNARITY: f64 = 2.208984375;
-
+    pub continent_lacunarity: f64,
     /// Lacunarity of the planet's mountains. Changing the value produces
     /// slightly different mountains. For the best results, this value should
     /// be random, but close to 2.0.
-    const MOUNTAIN_LACUNARITY: f64 = 2.142578125;
-
+    pub mountain_lacunarity: f64,
     /// Lacunarity of the planet's hills. Changing this value produces
     /// slightly different hills. For the best results, this value should be
     /// random, but close to 2.0.
-    const HILLS_LACUNARITY: f64 = 2.162109375;
-
+    pub hills_lacunarity: f64,
     /// Lacunarity of the planet's plains. Changing this value produces
     /// slightly different plains. For the best results, this value should be
     /// random, but close to 2.0.
-    const PLAINS_LACUNARITY: f64 = 2.314453125;
-
+    pub plains_lacunarity: f64,
     /// Lacunarity of the planet's badlands. Changing this value produces
     /// slightly different badlands. For the best results, this value should
     /// be random, but close to 2.0.
-    const BADLANDS_LACUNARITY: f64 = 2.212890625;
-
+    pub badlands_lacunarity: f64,
     /// Specifies the "twistiness" of the mountains.
-    const MOUNTAINS_TWIST: f64 = 1.0;
-
+    pub mountains_twist: f64,
     /// Specifies the "twistiness" of the hills.
-    const HILLS_TWIST: f64 = 1.0;
-
+    pub hills_twist: f64,
     /// Specifies the "twistiness" of the badlands.
-    const BADLANDS_TWIST: f64 = 1.0;
-
-    /// Specifies the planet's sea level. This value must be between -1.0
-    /// (minimum planet elevation) and +1.0 (maximum planet elevation).
-    const SEA_LEVEL: f64 = 0.0;
-
+    pub badlands_twist: f64,
     /// Specifies the level on the planet in which continental shelves appear.
     /// This value must be between -1.0 (minimum planet elevation) and +1.0
     /// (maximum planet elevation), and must be less than `SEA_LEVEL`.
-    const SHELF_LEVEL: f64 = -0.375;
-
+    pub shelf_level: f64,
     /// Determines the amount of mountainous terrain that appears on the
     /// planet. Values range from 0.0 (no mountains) to 1.0 (all terrain is
     /// covered in mountains). Mountains terrain will overlap hilly terrain.
     /// Because the badlands terrain may overlap parts of the mountainous
-    /// terrain, setting `MOUNTAINS_AMOUNT` to 1.0 may not completely cover the
+    /// terrain, setting `mountains_amount` to 1.0 may not completely cover the
     /// terrain in mountains.
-    const MOUNTAINS_AMOUNT: f64 = 0.5;
-
+    pub mountains_amount: f64,
     /// Determines the amount of hilly terrain that appears on the planet.
     /// Values range from 0.0 (no hills) to 1.0 (all terrain is covered in
-    /// hills). This value must be less than `MOUNTAINS_AMOUNT`. Because the
+    /// hills). This value must be less than `mountains_amount`. Because the
     /// mountains terrain will overlap parts of the hilly terrain, and the
     /// badlands terrain may overlap parts of the hilly terrain, setting
-    /// `HILLS_AMOUNT` to 1.0 may not completely cover the terrain in hills.
-    const HILLS_AMOUNT: f64 = (1.0 + MOUNTAINS_AMOUNT) / 2.0;
-
+    /// `hills_amount` to 1.0 may not completely cover the terrain in hills.
+    pub hills_amount: f64,
     /// Determines the amount of badlands terrain that covers the planet.
     /// Values range from 0.0 (no badlands) to 1.0 (all terrain is covered in
     /// badlands). Badlands terrain will overlap any other type of terrain.
-    const BADLANDS_AMOUNT: f64 = 0.3125;
-
+    pub badlands_amount: f64,
     /// Offset to apply to the terrain type definition. Low values (< 1.0)
     /// cause the rough areas to appear only at high elevations. High values
     /// (> 2.0) cause the rough areas to appear at any elevation. The
     /// percentage of rough areas on the planet are independent of this value.
-    const TERRAIN_OFFSET: f64 = 1.0;
-
+    pub terrain_offset: f64,
     /// Specifies the amount of "glaciation" on the mountains. This value
     /// should be close to 1.0 and greater than 1.0.
-    const MOUNTAIN_GLACIATION: f64 = 1.375;
-
+    pub mountain_glaciation: f64,
     /// Scaling to apply to the base continent elevations, in planetary
     /// elevation units.
-    const CONTINENT_HEIGHT_SCALE: f64 = (1.0 - SEA_LEVEL) / 4.0;
-
+    pub continent_height_scale: f64,
     /// Maximum depth of the rivers, in planetary elevation units.
-    const RIVER_DEPTH: f64 = 0.0234375;
+    pub river_depth: f64,
+    /// Graph applied from the node editor, whose output replaces the planet
+    /// above when it can be built.
+    #[reflect(ignore)]
+    pub graph: Option<Arc<TerrainGraph>>,
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        NoiseSettings {
+            seed: 9823247,
+            continent_frequency: 1.0,
+            continent_lacunarity: 2.208984375,
+            mountain_lacunarity: 2.142578125,
+            hills_lacunarity: 2.162109375,
+            plains_lacunarity: 2.314453125,
+            badlands_lacunarity: 2.212890625,
+            mountains_twist: 1.0,
+            hills_twist: 1.0,
+            badlands_twist: 1.0,
+            shelf_level: -0.375,
+            mountains_amount: 0.5,
+            hills_amount: 0.75,
+            badlands_amount: 0.3125,
+            terrain_offset: 1.0,
+            mountain_glaciation: 1.375,
+            continent_height_scale: (1.0 - SEA_LEVEL) / 4.0,
+            river_depth: 0.0234375,
+            graph: None,
+        }
+    }
+}
+
+// example from : https://github.com/Razaekel/noise-rs/blob/develop/examples/complexplanet.rs
+#[allow(non_snake_case)]
+pub fn generate_noise_map(
+    extent: f64,
+    width: usize,
+    depth: usize,
+    origin: [f64; 2],
+    settings: &NoiseSettings,
+) -> NoiseMap {
+    if let Some(noise) = settings
+        .graph
+        .as_ref()
+        .and_then(|graph| graph.build_output().ok())
+    {
+        return build_noise_map(&noise, extent, width, depth, origin);
+    }
+
+    // every noise module derives its own seed from the planet seed
+    let CURRENT_SEED: u32 = settings.seed;
+
+    // planet parameters, documented on `NoiseSettings`
+    let CONTINENT_FREQUENCY: f64 = settings.continent_frequency;
+    let CONTINENT_LACUNARITY: f64 = settings.continent_lacunarity;
+    let MOUNTAIN_LACUNARITY: f64 = settings.mountain_lacunarity;
+    let HILLS_LACUNARITY: f64 = settings.hills_lacunarity;
+    let PLAINS_LACUNARITY: f64 = settings.plains_lacunarity;
+    let BADLANDS_LACUNARITY: f64 = settings.badlands_lacunarity;
+    let MOUNTAINS_TWIST: f64 = settings.mountains_twist;
+    let HILLS_TWIST: f64 = settings.hills_twist;
+    let BADLANDS_TWIST: f64 = settings.badlands_twist;
+    let SHELF_LEVEL: f64 = settings.shelf_level;
+    let MOUNTAINS_AMOUNT: f64 = settings.mountains_amount;
+    let HILLS_AMOUNT: f64 = settings.hills_amount;
+    let BADLANDS_AMOUNT: f64 = settings.badlands_amount;
+    let TERRAIN_OFFSET: f64 = settings.terrain_offset;
+    let MOUNTAIN_GLACIATION: f64 = settings.mountain_glaciation;
+    let CONTINENT_HEIGHT_SCALE: f64 = settings.continent_height_scale;
+    let RIVER_DEPTH: f64 = settings.river_depth;
 
     // ////////////////////////////////////////////////////////////////////////
     // Function group: continent definition
@@ -116,7 +170,8 @@ pub fn generate_noise_map(
     // -1.0 represents the lowest elevations and +1.0 represents the highest
     // elevations.
     //
-    fn baseContinentDef() -> impl NoiseFn<f64, 3> {
+    // a closure rather than a nested function, as it reads the seed
+    let baseContinentDef = || {
         // 1: [Continent module]: This FBM module generates the continents. This
         // noise function has a high number of octaves so that detail is visible at
         // high zoom levels.
@@ -159,7 +214,7 @@ pub fn generate_noise_map(
         // used by subsequent noise functions to carve out chunks from the
         // mountain ranges within the continent-with-ranges module so that the
         // mountain ranges will not be completely impassible.
-        let baseContinentDef_fb1 = Fbm::<Perlin>::new(CURRENT_SEED + 1)
+        let baseContinentDef_fb1 = Fbm::<Perlin>::new(CURRENT_SEED.wrapping_add(1))
             .set_frequency(CONTINENT_FREQUENCY * 4.34375)
             .set_persistence(0.5)
             .set_lacunarity(CONTINENT_LACUNARITY)
@@ -215,7 +270,7 @@ pub fn generate_noise_map(
         let baseContinentDef = Cache::new(baseContinentDef_cl);
 
         baseContinentDef
-    }
+    };
 
     //    debug::render_noise_module("complexplanet_images/00_5_baseContinentDef.png",
     //                               &baseContinentDef,
@@ -240,7 +295,7 @@ pub fn generate_noise_map(
     // value from the base-continent-definition subgroup, adding some coarse
     // detail to it.
     let continentDef_tu0 = Turbulence::<_, Perlin>::new(baseContinentDef())
-        .set_seed(CURRENT_SEED + 10)
+        .set_seed(CURRENT_SEED.wrapping_add(10))
         .set_frequency(CONTINENT_FREQUENCY * 15.25)
         .set_power(CONTINENT_FREQUENCY / 113.75)
         .set_roughness(13);
@@ -256,7 +311,7 @@ pub fn generate_noise_map(
     // higher frequency, but lower power, than the coarse-turbulence module,
     // adding some intermediate detail to it.
     let continentDef_tu1 = Turbulence::<_, Perlin>::new(continentDef_tu0)
-        .set_seed(CURRENT_SEED + 11)
+        .set_seed(CURRENT_SEED.wrapping_add(11))
         .set_frequency(CONTINENT_FREQUENCY * 47.25)
         .set_power(CONTINENT_FREQUENCY / 433.75)
         .set_roughness(12);
@@ -272,7 +327,7 @@ pub fn generate_noise_map(
     // turbulence has a higher frequency, but lower power, than the
     // intermediate-turbulence module, adding some fine detail to it.
     let continentDef_tu2 = Turbulence::<_, Perlin>::new(continentDef_tu1)
-        .set_seed(CURRENT_SEED + 12)
+        .set_seed(CURRENT_SEED.wrapping_add(12))
         .set_frequency(CONTINENT_FREQUENCY * 95.25)
         .set_power(CONTINENT_FREQUENCY / 1019.75)
         .set_roughness(11);
@@ -340,7 +395,7 @@ pub fn generate_noise_map(
     // areas may now appear in the the ocean, creating rocky islands and
     // fjords.
     let terrainTypeDef_tu = Turbulence::<_, Perlin>::new(&continentDef)
-        .set_seed(CURRENT_SEED + 20)
+        .set_seed(CURRENT_SEED.wrapping_add(20))
         .set_frequency(CONTINENT_FREQUENCY * 18.125)
         .set_power(CONTINENT_FREQUENCY / 20.59375 * TERRAIN_OFFSET)
         .set_roughness(3);
@@ -376,7 +431,7 @@ pub fn generate_noise_map(
 
     // 1: [Mountain-ridge module]: This ridged-multifractal-noise function
     // generates the mountain ridges.
-    let mountainBaseDef_rm0 = RidgedMulti::<Perlin>::new(CURRENT_SEED + 30)
+    let mountainBaseDef_rm0 = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(30))
         .set_frequency(1723.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
         .set_octaves(4);
@@ -395,7 +450,7 @@ pub fn generate_noise_map(
     // of the valleys. Note that this noise function generates ridged-multifractal
     // noise using only one octave; this information will be important in the
     // next step.
-    let mountainBaseDef_rm1 = RidgedMulti::<Perlin>::new(CURRENT_SEED + 31)
+    let mountainBaseDef_rm1 = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(31))
         .set_frequency(367.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
         .set_octaves(1);
@@ -431,7 +486,7 @@ pub fn generate_noise_map(
     // value from the mountain-and-valleys module, adding some coarse detail to
     // it.
     let mountainBaseDef_tu0 = Turbulence::<_, Perlin>::new(mountainBaseDef_bl)
-        .set_seed(CURRENT_SEED + 32)
+        .set_seed(CURRENT_SEED.wrapping_add(32))
         .set_frequency(1337.0)
         .set_power(1.0 / 6730.0 * MOUNTAINS_TWIST)
         .set_roughness(4);
@@ -441,7 +496,7 @@ pub fn generate_noise_map(
     // a higher frequency, but lower power, than the coarse-turbulence module,
     // adding some fine detail to it.
     let mountainBaseDef_tu1 = Turbulence::<_, Perlin>::new(mountainBaseDef_tu0)
-        .set_seed(CURRENT_SEED + 33)
+        .set_seed(CURRENT_SEED.wrapping_add(33))
         .set_frequency(21221.0)
         .set_power(1.0 / 120157.0 * MOUNTAINS_TWIST)
         .set_roughness(6);
@@ -463,7 +518,7 @@ pub fn generate_noise_map(
     // 1: [Mountain-basis-0 module]: This ridged-multifractal-noise function,
     // along with the mountain-basis-1 module, generates the individual
     // mountains.
-    let mountainousHigh_rm0 = RidgedMulti::<Perlin>::new(CURRENT_SEED + 40)
+    let mountainousHigh_rm0 = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(40))
         .set_frequency(2371.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
         .set_octaves(3);
@@ -471,7 +526,7 @@ pub fn generate_noise_map(
     // 2: [Mountain-basis-1 module]: This ridged-multifractal-noise function,
     // along with the mountain-basis-0 module, generates the individual
     // mountains.
-    let mountainousHigh_rm1 = RidgedMulti::<Perlin>::new(CURRENT_SEED + 41)
+    let mountainousHigh_rm1 = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(41))
         .set_frequency(2341.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
         .set_octaves(3);
@@ -486,7 +541,7 @@ pub fn generate_noise_map(
     // 4: [Warped-high-mountains module]: This turbulence module warps the
     // output value from the high-mountains module, adding some detail to it.
     let mountainousHigh_tu = Turbulence::<_, Perlin>::new(mountainousHigh_ma)
-        .set_seed(CURRENT_SEED + 42)
+        .set_seed(CURRENT_SEED.wrapping_add(42))
         .set_frequency(31511.0)
         .set_power(1.0 / 180371.0 * MOUNTAINS_TWIST)
         .set_roughness(4);
@@ -508,7 +563,7 @@ pub fn generate_noise_map(
     // 1: [Lowland-basis-0 module]: This ridged-multifractal-noise function,
     // along with the lowland-basis-1 module, produces the low mountainous
     // terrain.
-    let mountainousLow_rm0 = RidgedMulti::<Perlin>::new(CURRENT_SEED + 50)
+    let mountainousLow_rm0 = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(50))
         .set_frequency(1381.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
         .set_octaves(8);
@@ -516,7 +571,7 @@ pub fn generate_noise_map(
     // 1: [Lowland-basis-1 module]: This ridged-multifractal-noise function,
     // along with the lowland-basis-0 module, produces the low mountainous
     // terrain.
-    let mountainousLow_rm1 = RidgedMulti::<Perlin>::new(CURRENT_SEED + 51)
+    let mountainousLow_rm1 = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(51))
         .set_frequency(1427.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
         .set_octaves(8);
@@ -620,7 +675,7 @@ pub fn generate_noise_map(
     //
 
     // 1: [Hills module]: This billow-noise function generates the hills.
-    let hillyTerrain_bi = Billow::<Perlin>::new(CURRENT_SEED + 60)
+    let hillyTerrain_bi = Billow::<Perlin>::new(CURRENT_SEED.wrapping_add(60))
         .set_frequency(1663.0)
         .set_persistence(0.5)
         .set_lacunarity(HILLS_LACUNARITY)
@@ -637,7 +692,7 @@ pub fn generate_noise_map(
     // appear in between the valleys. Note that this noise function generates
     // ridged-multifractal noise using only one octave; this information will be
     // important in the next step.
-    let hillyTerrain_rm = RidgedMulti::<Perlin>::new(CURRENT_SEED + 61)
+    let hillyTerrain_rm = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(61))
         .set_frequency(367.5)
         .set_lacunarity(HILLS_LACUNARITY)
         .set_octaves(1);
@@ -683,7 +738,7 @@ pub fn generate_noise_map(
     // value from the increased-slope-hilly-terrain module, adding some
     // coarse detail to it.
     let hillyTerrain_tu0 = Turbulence::<_, Perlin>::new(hillyTerrain_ex)
-        .set_seed(CURRENT_SEED + 62)
+        .set_seed(CURRENT_SEED.wrapping_add(62))
         .set_frequency(1531.0)
         .set_power(1.0 / 16921.0 * HILLS_TWIST)
         .set_roughness(4);
@@ -693,7 +748,7 @@ pub fn generate_noise_map(
     // higher frequency, but lower power, than the coarse-turbulence module,
     // adding some fine detail to it.
     let hillyTerrain_tu1 = Turbulence::<_, Perlin>::new(hillyTerrain_tu0)
-        .set_seed(CURRENT_SEED + 63)
+        .set_seed(CURRENT_SEED.wrapping_add(63))
         .set_frequency(21617.0)
         .set_power(1.0 / 117529.0 * HILLS_TWIST)
         .set_roughness(6);
@@ -722,7 +777,7 @@ pub fn generate_noise_map(
 
     // 1: [Plains-basis-0 module]: This billow-noise function, along with the
     // plains-basis-1 module, produces the plains.
-    let plainsTerrain_bi0 = Billow::<Perlin>::new(CURRENT_SEED + 70)
+    let plainsTerrain_bi0 = Billow::<Perlin>::new(CURRENT_SEED.wrapping_add(70))
         .set_frequency(1097.5)
         .set_persistence(0.5)
         .set_lacunarity(PLAINS_LACUNARITY)
@@ -738,7 +793,7 @@ pub fn generate_noise_map(
 
     // 3: [Plains-basis-1 module]: This billow-noise function, along with the
     // plains-basis-2 module, produces the plains.
-    let plainsTerrain_bi1 = Billow::<Perlin>::new(CURRENT_SEED + 71)
+    let plainsTerrain_bi1 = Billow::<Perlin>::new(CURRENT_SEED.wrapping_add(71))
         .set_frequency(1097.5)
         .set_persistence(0.5)
         .set_lacunarity(PLAINS_LACUNARITY)
@@ -784,7 +839,7 @@ pub fn generate_noise_map(
     // 1: [Sand-dunes module]: This ridged-multifractal-noise function generates
     // sand dunes. This ridged-multifractal noise is generated with a single
     // octave, which makes very smooth dunes.
-    let badlandsSand_rm = RidgedMulti::<Perlin>::new(CURRENT_SEED + 80)
+    let badlandsSand_rm = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(80))
         .set_frequency(6163.5)
         .set_lacunarity(BADLANDS_LACUNARITY)
         .set_octaves(1);
@@ -800,7 +855,7 @@ pub fn generate_noise_map(
     // generate the detail to add to the dunes. By enabling the distance
     // algorithm, small polygonal pits are generated; the edges of the pits
     // are joined to the edges of nearby pits.
-    let badlandsSand_wo = Worley::new(CURRENT_SEED + 81)
+    let badlandsSand_wo = Worley::new(CURRENT_SEED.wrapping_add(81))
         .set_frequency(16183.25)
         .set_return_type(ReturnType::Distance);
 
@@ -831,7 +886,7 @@ pub fn generate_noise_map(
 
     // 1: [Cliff-basis module]: This Perlin-noise function generates some coherent
     // noise that will be used to generate the cliffs.
-    let badlandsCliffs_fb = Fbm::<Perlin>::new(CURRENT_SEED + 90)
+    let badlandsCliffs_fb = Fbm::<Perlin>::new(CURRENT_SEED.wrapping_add(90))
         .set_frequency(CONTINENT_FREQUENCY * 839.0)
         .set_persistence(0.5)
         .set_lacunarity(BADLANDS_LACUNARITY)
@@ -870,7 +925,7 @@ pub fn generate_noise_map(
     // 5: [Coarse-turbulence module]: This turbulence module warps the output
     // value from the terraced-cliffs module, adding some coarse detail to it.
     let badlandsCliffs_tu0 = Turbulence::<_, Perlin>::new(badlandsCliffs_te)
-        .set_seed(CURRENT_SEED + 91)
+        .set_seed(CURRENT_SEED.wrapping_add(91))
         .set_frequency(16111.0)
         .set_power(1.0 / 141539.0 * BADLANDS_TWIST)
         .set_roughness(3);
@@ -880,7 +935,7 @@ pub fn generate_noise_map(
     // frequency, but lower power, than the coarse-turbulence module, adding
     // some fine detail to it.
     let badlandsCliffs_tu1 = Turbulence::<_, Perlin>::new(badlandsCliffs_tu0)
-        .set_seed(CURRENT_SEED + 92)
+        .set_seed(CURRENT_SEED.wrapping_add(92))
         .set_frequency(36107.0)
         .set_power(1.0 / 211543.0 * BADLANDS_TWIST)
         .set_roughness(3);
@@ -941,7 +996,7 @@ pub fn generate_noise_map(
 
     // 1: [Large-river-basis module]: This ridged-multifractal-noise function
     // creates the large, deep rivers.
-    let riverPositions_rm0 = RidgedMulti::<Perlin>::new(CURRENT_SEED + 100)
+    let riverPositions_rm0 = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(100))
         .set_frequency(18.75)
         .set_lacunarity(CONTINENT_LACUNARITY)
         .set_octaves(1);
@@ -961,7 +1016,7 @@ pub fn generate_noise_map(
 
     // 3: [Small-river-basis module]: This ridged-multifractal-noise function
     // creates the small, shallow rivers.
-    let riverPositions_rm1 = RidgedMulti::<Perlin>::new(CURRENT_SEED + 101)
+    let riverPositions_rm1 = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(101))
         .set_frequency(43.25)
         .set_lacunarity(CONTINENT_LACUNARITY)
         .set_octaves(1);
@@ -989,7 +1044,7 @@ pub fn generate_noise_map(
     //    from the combined-rivers module, which twists the rivers.  The high
     //    roughness produces less-smooth rivers.
     let riverPositions_tu = Turbulence::<_, Perlin>::new(riverPositions_mi)
-        .set_seed(CURRENT_SEED + 102)
+        .set_seed(CURRENT_SEED.wrapping_add(102))
         .set_frequency(9.25)
         .set_power(1.0 / 57.75)
         .set_roughness(6);
@@ -1031,7 +1086,7 @@ pub fn generate_noise_map(
     // roughly the same elevation. This BasicMulti module generates some
     // random values that will be used by subsequent noise functions to randomly
     // change the elevations of the mountain peaks.
-    let scaledMountainousTerrain_fb = Fbm::<Perlin>::new(CURRENT_SEED + 110)
+    let scaledMountainousTerrain_fb = Fbm::<Perlin>::new(CURRENT_SEED.wrapping_add(110))
         .set_frequency(14.5)
         .set_persistence(0.5)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
@@ -1097,7 +1152,7 @@ pub fn generate_noise_map(
     // roughly the same elevation. This BasicMulti module generates some
     // random values that will be used by subsequent noise functions to
     // randomly change the elevations of the hilltops.
-    let scaledHillyTerrain_fb = Fbm::<Perlin>::new(CURRENT_SEED + 120)
+    let scaledHillyTerrain_fb = Fbm::<Perlin>::new(CURRENT_SEED.wrapping_add(120))
         .set_frequency(13.5)
         .set_persistence(0.5)
         .set_lacunarity(HILLS_LACUNARITY)
@@ -1252,7 +1307,7 @@ pub fn generate_noise_map(
     // 3: [Oceanic-trench-basis module]: This ridged-multifractal-noise function
     // generates some coherent noise that will be used to generate the oceanic
     // trenches. The ridges represent the bottom of the trenches.
-    let continentalShelf_rm = RidgedMulti::<Perlin>::new(CURRENT_SEED + 130)
+    let continentalShelf_rm = RidgedMulti::<Perlin>::new(CURRENT_SEED.wrapping_add(130))
         .set_frequency(CONTINENT_FREQUENCY * 4.375)
         .set_lacunarity(CONTINENT_LACUNARITY)
         .set_octaves(16);
@@ -1502,7 +1557,7 @@ pub fn generate_noise_map(
     // 1: [Badlands-positions module]: This BasicMulti module generates some
     // random noise, which is used by subsequent noise functions to specify the
     // locations of the badlands.
-    let continentsWithBadlands_bm = Fbm::<Perlin>::new(CURRENT_SEED + 140)
+    let continentsWithBadlands_bm = Fbm::<Perlin>::new(CURRENT_SEED.wrapping_add(140))
         .set_frequency(16.5)
         .set_persistence(0.5)
         .set_lacunarity(CONTINENT_LACUNARITY)
@@ -1632,15 +1687,24 @@ pub fn generate_noise_map(
     //    continent-with-rivers subgroup.
     let unscaledFinalPlanet = Cache::new(continentsWithRivers);
 
-    PlaneMapBuilder::new(&unscaledFinalPlanet)
-        .set_size(width, depth)
-        .set_x_bounds(
-            -extent + (chunk_location.x as f64 * extent * 1.75),
-            extent + (chunk_location.x as f64 * extent * 1.75),
-        )
-        .set_y_bounds(
-            -extent + (chunk_location.y as f64 * extent * 1.75),
-            extent + (chunk_location.y as f64 * extent * 1.75),
-        )
+    build_noise_map(&unscaledFinalPlanet, extent, width, depth, origin)
+}
+
+/// Samples `noise` over `2 * extent` noise units from `origin`.
+fn build_noise_map(
+    noise: &impl NoiseFn<f64, 3>,
+    extent: f64,
+    width: usize,
+    depth: usize,
+    origin: [f64; 2],
+) -> NoiseMap {
+    // One more sample on each axis, at the same spacing, so the last row and
+    // column of vertices lie on the terrain instead of the map border.
+    let (step_x, step_y) = (2.0 * extent / width as f64, 2.0 * extent / depth as f64);
+
+    PlaneMapBuilder::new(noise)
+        .set_size(width + 1, depth + 1)
+        .set_x_bounds(origin[0], origin[0] + 2.0 * extent + step_x)
+        .set_y_bounds(origin[1], origin[1] + 2.0 * extent + step_y)
         .build()
 }
@@ -0,0 +1,332 @@
+use std::collections::HashSet;
+
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_rapier3d::{
+    dynamics::RigidBody,
+    geometry::{Collider, ComputedColliderShape},
+};
+use futures_lite::future;
+
+use crate::origin::WorldOrigin;
+
+use super::chunk::{
+    get_player_chunk, spawn_chunk_task, Chunk, ChunkLoader, ChunkSettings, ChunkSettingsParam,
+    ChunkTask, CHUNK_WORLD_SCALE, CHUNK_WORLD_SIZE, HEIGHT_INTENSITY, NORMAL_LOD,
+};
+use super::material::TerrainMaterialHandle;
+use super::mesh::create_node_mesh;
+
+/// Optional CDLOD terrain: instead of fixed-size chunks, a quadtree of nodes
+/// is split and merged by camera distance. Every node has the same vertex
+/// count, so far terrain only takes a handful of entities.
+#[derive(Resource, Clone, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct QuadtreeSettings {
+    pub enabled: bool,
+    /// Number of levels, the smallest nodes being the size of a chunk.
+    pub levels: u32,
+    /// Quads along each side of a node, must be even.
+    pub resolution: usize,
+    /// A node splits when the camera is closer than this many times the size
+    /// of its children. Below 1.5 nodes may not be done morphing when they
+    /// meet a coarser neighbor.
+    pub split_distance: f32,
+    /// Part of the distance band of each level spent morphing into the next.
+    pub morph_ratio: f32,
+    /// Roots loaded on each side of the one under the camera.
+    pub root_radius: i32,
+}
+
+impl Default for QuadtreeSettings {
+    fn default() -> Self {
+        QuadtreeSettings {
+            enabled: false,
+            levels: 8,
+            resolution: NORMAL_LOD,
+            split_distance: 3.0,
+            morph_ratio: 0.3,
+            root_radius: 1,
+        }
+    }
+}
+
+impl QuadtreeSettings {
+    /// Camera distance up to which nodes of `level` are shown.
+    pub fn lod_range(&self, level: u32) -> f32 {
+        NodeKey::size_at(level) * self.split_distance
+    }
+
+    /// Camera distances between which nodes of `level` morph into their
+    /// parent.
+    pub fn morph_range(&self, level: u32) -> [f32; 2] {
+        let end = self.lod_range(level);
+        let start = if level == 0 {
+            0.0
+        } else {
+            self.lod_range(level - 1)
+        };
+
+        [end - (end - start) * self.morph_ratio, end]
+    }
+
+    /// Nodes to show from the absolute `camera` position, the closest ones
+    /// being the smallest.
+    pub fn select_nodes(&self, camera: DVec3) -> HashSet<NodeKey> {
+        let root_level = self.levels.max(1) - 1;
+        let root_size = NodeKey::size_at(root_level) as f64;
+        let (x, z) = (
+            (camera.x / root_size).floor() as i64,
+            (camera.z / root_size).floor() as i64,
+        );
+
+        let mut nodes = HashSet::new();
+        for dx in -self.root_radius..=self.root_radius {
+            for dz in -self.root_radius..=self.root_radius {
+                let root = NodeKey {
+                    level: root_level,
+                    x: x + dx as i64,
+                    z: z + dz as i64,
+                };
+                self.select(root, camera, &mut nodes);
+            }
+        }
+
+        nodes
+    }
+
+    fn select(&self, key: NodeKey, camera: DVec3, nodes: &mut HashSet<NodeKey>) {
+        if key.level > 0 && key.distance(camera) < self.lod_range(key.level - 1) {
+            for child in key.children() {
+                self.select(child, camera, nodes);
+            }
+        } else {
+            nodes.insert(key);
+        }
+    }
+}
+
+/// Position of a node in the quadtree. Nodes of a level tile the absolute
+/// world from its center, each covering four nodes of the level below.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub struct NodeKey {
+    pub level: u32,
+    pub x: i64,
+    pub z: i64,
+}
+
+impl NodeKey {
+    pub fn size_at(level: u32) -> f32 {
+        CHUNK_WORLD_SIZE * (1 << level) as f32
+    }
+
+    pub fn size(&self) -> f32 {
+        NodeKey::size_at(self.level)
+    }
+
+    /// Absolute world corner of the node with the lowest coordinates.
+    pub fn min(&self) -> DVec2 {
+        DVec2::new(self.x as f64, self.z as f64) * self.size() as f64
+    }
+
+    pub fn center(&self) -> DVec2 {
+        self.min() + DVec2::splat(self.size() as f64 / 2.0)
+    }
+
+    pub fn children(&self) -> [NodeKey; 4] {
+        let (level, x, z) = (self.level - 1, self.x * 2, self.z * 2);
+        [
+            NodeKey { level, x, z },
+            NodeKey { level, x: x + 1, z },
+            NodeKey { level, x, z: z + 1 },
+            NodeKey {
+                level,
+                x: x + 1,
+                z: z + 1,
+            },
+        ]
+    }
+
+    pub fn overlaps(&self, other: &NodeKey) -> bool {
+        let (min, max) = (self.min(), self.min() + self.size() as f64);
+        let (other_min, other_max) = (other.min(), other.min() + other.size() as f64);
+
+        min.x < other_max.x && other_min.x < max.x && min.y < other_max.y && other_min.y < max.y
+    }
+
+    /// Distance from the absolute `camera` position to the node, taken as
+    /// flat at sea level.
+    pub fn distance(&self, camera: DVec3) -> f32 {
+        let (min, max) = (self.min(), self.min() + self.size() as f64);
+        let closest = DVec2::new(camera.x, camera.z).clamp(min, max);
+
+        DVec3::new(camera.x - closest.x, camera.y, camera.z - closest.y).length() as f32
+    }
+}
+
+#[derive(Component)]
+pub struct QuadtreeNode {
+    pub key: NodeKey,
+    pub mesh: Mesh,
+}
+
+impl QuadtreeNode {
+    fn new(key: NodeKey, settings: &QuadtreeSettings, chunk_settings: &ChunkSettings) -> Self {
+        // nodes use the same noise scale as the chunks: the mesh spans half
+        // its noise extent, then is scaled by CHUNK_WORLD_SCALE
+        let scale = CHUNK_WORLD_SCALE as f64;
+        let min = key.min();
+        let terrain = create_node_mesh(
+            key.size() as f64 / scale,
+            HEIGHT_INTENSITY,
+            settings.resolution,
+            [min.x * 2.0 / scale, min.y * 2.0 / scale],
+            settings.morph_range(key.level),
+            chunk_settings,
+        );
+
+        QuadtreeNode {
+            key,
+            mesh: terrain.mesh,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct QuadtreeNodeTask {
+    pub task: Task<QuadtreeNode>,
+    pub key: NodeKey,
+}
+
+pub fn quadtree_enabled(settings: Res<QuadtreeSettings>) -> bool {
+    settings.enabled
+}
+
+/// Terrain of either mode, generated or pending.
+type ModeTerrain = Or<(
+    With<Chunk>,
+    With<ChunkTask>,
+    With<QuadtreeNode>,
+    With<QuadtreeNodeTask>,
+)>;
+
+/// Clears the terrain of the previous mode when the quadtree is toggled.
+pub fn switch_terrain_mode(
+    mut commands: Commands,
+    mut was_enabled: Local<Option<bool>>,
+    settings: Res<QuadtreeSettings>,
+    chunk_settings: ChunkSettingsParam,
+    player_query: Query<&Transform, With<ChunkLoader>>,
+    origin: Res<WorldOrigin>,
+    terrain: Query<(Entity, Has<Chunk>, Has<ChunkTask>), ModeTerrain>,
+) {
+    let previous = was_enabled.replace(settings.enabled);
+    if previous.is_none() || previous == Some(settings.enabled) {
+        return;
+    }
+
+    for (entity, is_chunk, is_chunk_task) in terrain.iter() {
+        if (is_chunk || is_chunk_task) == settings.enabled {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    if settings.enabled {
+        return;
+    }
+
+    if let Ok(player_transform) = player_query.get_single() {
+        let coords = get_player_chunk(player_transform.translation, &origin);
+        spawn_chunk_task(&mut commands, coords, chunk_settings.get());
+    }
+}
+
+/// Streams the quadtree nodes around the camera. Nodes that are no longer
+/// selected stay until the nodes replacing them are generated, so no holes
+/// open while moving.
+pub fn stream_quadtree(
+    mut commands: Commands,
+    settings: Res<QuadtreeSettings>,
+    chunk_settings: ChunkSettingsParam,
+    player_query: Query<&Transform, With<ChunkLoader>>,
+    origin: Res<WorldOrigin>,
+    nodes: Query<(Entity, &QuadtreeNode)>,
+    tasks: Query<(Entity, &QuadtreeNodeTask)>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let thread_pool = AsyncComputeTaskPool::get();
+
+    let selected = settings.select_nodes(origin.to_world(player_transform.translation));
+    let loaded: HashSet<NodeKey> = nodes.iter().map(|(_, node)| node.key).collect();
+
+    let mut pending = Vec::new();
+    for (entity, task) in tasks.iter() {
+        if selected.contains(&task.key) {
+            pending.push(task.key);
+        } else {
+            // dropping the task cancels it
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for key in &selected {
+        if loaded.contains(key) || pending.contains(key) {
+            continue;
+        }
+
+        let key = *key;
+        let quadtree = settings.clone();
+        let chunk_settings = chunk_settings.get();
+        let task =
+            thread_pool.spawn(async move { QuadtreeNode::new(key, &quadtree, &chunk_settings) });
+
+        commands.spawn(QuadtreeNodeTask { task, key });
+        pending.push(key);
+    }
+
+    for (entity, node) in nodes.iter() {
+        if !selected.contains(&node.key) && !pending.iter().any(|key| key.overlaps(&node.key)) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn handle_quadtree_tasks(
+    mut commands: Commands,
+    mut node_tasks: Query<(Entity, &mut QuadtreeNodeTask)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<TerrainMaterialHandle>,
+    origin: Res<WorldOrigin>,
+) {
+    for (entity, mut task) in &mut node_tasks {
+        if let Some(node) = block_on(future::poll_once(&mut task.task)) {
+            let center = node.key.center();
+
+            commands.entity(entity).insert(MaterialMeshBundle {
+                mesh: meshes.add(node.mesh.clone()),
+                material: material.0.clone(),
+                transform: Transform {
+                    translation: origin.to_local(DVec3::new(center.x, 0.0, center.y)),
+                    scale: Vec3::splat(CHUNK_WORLD_SCALE),
+                    ..default()
+                },
+                ..default()
+            });
+
+            // only the nodes around the camera are detailed enough to walk on
+            if node.key.level == 0 {
+                commands.entity(entity).insert((
+                    RigidBody::Fixed,
+                    Collider::from_bevy_mesh(&node.mesh, &ComputedColliderShape::TriMesh).unwrap(),
+                ));
+            }
+
+            commands
+                .entity(entity)
+                .insert(node)
+                .remove::<QuadtreeNodeTask>();
+        }
+    }
+}
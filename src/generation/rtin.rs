@@ -0,0 +1,224 @@
+//! Right-triangulated irregular network, built from a square heightmap.
+//!
+//! The grid is split in two right triangles, which are recursively split
+//! along their hypotenuse until the height in the middle of the hypotenuse is
+//! close enough to the interpolated one. Based on the approach of
+//! https://github.com/mapbox/martini.
+
+/// Indices of the triangles approximating a `size * size` heightmap, where
+/// `size` is a power of two plus one.
+///
+/// No height is further than `max_error` from the triangulated surface,
+/// seabed included. The border of the grid is kept exact, whatever
+/// `max_error`, so neighboring chunks meet along the same edges and do not
+/// crack: only its vertices in line with their neighbors are dropped.
+pub fn triangulate(heights: &[f32], size: usize, max_error: f32) -> Vec<u32> {
+    let tile = size - 1;
+    assert!(tile.is_power_of_two(), "RTIN grid size must be 2^n + 1");
+
+    let errors = compute_errors(heights, size);
+
+    let mut triangles = Vec::new();
+    let max = tile as u32;
+    split(
+        &errors,
+        size,
+        max_error,
+        [0, 0],
+        [max, max],
+        [max, 0],
+        &mut triangles,
+    );
+    split(
+        &errors,
+        size,
+        max_error,
+        [max, max],
+        [0, 0],
+        [0, max],
+        &mut triangles,
+    );
+
+    triangles
+}
+
+/// Error of every vertex, when it is the middle of a hypotenuse. Each error
+/// includes the ones of the smaller triangles, so splitting a triangle also
+/// splits its parents.
+fn compute_errors(heights: &[f32], size: usize) -> Vec<f32> {
+    let tile = size - 1;
+    let height = |x: usize, z: usize| heights[z * size + x];
+
+    let mut errors = vec![0.0_f32; size * size];
+
+    let triangle_count = tile * tile * 2 - 2;
+    let parent_count = triangle_count - tile * tile;
+
+    for i in (0..triangle_count).rev() {
+        let ([ax, az], [bx, bz]) = triangle_coords(i, tile);
+        let (mx, mz) = ((ax + bx) / 2, (az + bz) / 2);
+        let (cx, cz) = (mx + mz - az, mz + ax - mx);
+
+        let interpolated = (height(ax, az) + height(bx, bz)) / 2.0;
+        let middle = mz * size + mx;
+        let mut error = (interpolated - height(mx, mz)).abs();
+        let on_border = mx == 0 || mz == 0 || mx == tile || mz == tile;
+        if on_border && error > 0.0 {
+            error = f32::INFINITY;
+        }
+        errors[middle] = errors[middle].max(error);
+
+        if i < parent_count {
+            let left = ((az + cz) / 2) * size + (ax + cx) / 2;
+            let right = ((bz + cz) / 2) * size + (bx + cx) / 2;
+            errors[middle] = errors[middle].max(errors[left]).max(errors[right]);
+        }
+    }
+
+    errors
+}
+
+/// Hypotenuse ends of the triangle `i` of the implicit binary tree.
+fn triangle_coords(i: usize, tile: usize) -> ([usize; 2], [usize; 2]) {
+    let mut id = i + 2;
+    let (mut a, mut b, mut c) = if id & 1 == 1 {
+        ([0, 0], [tile, tile], [tile, 0])
+    } else {
+        ([tile, tile], [0, 0], [0, tile])
+    };
+
+    loop {
+        id >>= 1;
+        if id <= 1 {
+            break;
+        }
+        let m = [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2];
+        if id & 1 == 1 {
+            b = a;
+            a = c;
+        } else {
+            a = b;
+            b = c;
+        }
+        c = m;
+    }
+
+    (a, b)
+}
+
+fn split(
+    errors: &[f32],
+    size: usize,
+    max_error: f32,
+    a: [u32; 2],
+    b: [u32; 2],
+    c: [u32; 2],
+    triangles: &mut Vec<u32>,
+) {
+    let m = [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2];
+    let is_leaf = a[0].abs_diff(c[0]) + a[1].abs_diff(c[1]) <= 1;
+
+    if !is_leaf && errors[m[1] as usize * size + m[0] as usize] > max_error {
+        split(errors, size, max_error, c, a, m, triangles);
+        split(errors, size, max_error, b, c, m, triangles);
+        return;
+    }
+
+    // keep the triangles facing up, like the regular grid
+    let cross = (b[1] as i64 - a[1] as i64) * (c[0] as i64 - a[0] as i64)
+        - (b[0] as i64 - a[0] as i64) * (c[1] as i64 - a[1] as i64);
+    let (b, c) = if cross > 0 { (b, c) } else { (c, b) };
+
+    let index = |[x, z]: [u32; 2]| z * size as u32 + x;
+    triangles.extend([index(a), index(b), index(c)]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 33;
+
+    fn grid(height: impl Fn(f32, f32) -> f32) -> Vec<f32> {
+        (0..SIZE * SIZE)
+            .map(|i| height((i % SIZE) as f32, (i / SIZE) as f32))
+            .collect()
+    }
+
+    fn bumps() -> Vec<f32> {
+        grid(|x, z| (x * 0.4).sin() * (z * 0.3).cos() * 0.5 + (x * 1.3 + z).sin() * 0.05)
+    }
+
+    /// Height of the triangle `a b c` over `p`, if it is inside.
+    fn height_over(heights: &[f32], triangle: &[u32], p: [f32; 2]) -> Option<f32> {
+        let vertex = |i: u32| {
+            let i = i as usize;
+            ([(i % SIZE) as f32, (i / SIZE) as f32], heights[i])
+        };
+        let ([ax, az], ha) = vertex(triangle[0]);
+        let ([bx, bz], hb) = vertex(triangle[1]);
+        let ([cx, cz], hc) = vertex(triangle[2]);
+
+        let area = (bx - ax) * (cz - az) - (cx - ax) * (bz - az);
+        let u = ((bx - p[0]) * (cz - p[1]) - (cx - p[0]) * (bz - p[1])) / area;
+        let v = ((cx - p[0]) * (az - p[1]) - (ax - p[0]) * (cz - p[1])) / area;
+        let w = 1.0 - u - v;
+
+        let inside = [u, v, w].iter().all(|t| *t >= -1e-5);
+        inside.then_some(u * ha + v * hb + w * hc)
+    }
+
+    #[test]
+    fn flat_grid_collapses_to_two_triangles() {
+        let triangles = triangulate(&grid(|_, _| 0.3), SIZE, 0.001);
+        assert_eq!(triangles.len(), 2 * 3);
+    }
+
+    #[test]
+    fn ridge_keeps_more_triangles() {
+        let ridge = grid(|x, _| (1.0 - (x - 11.0).abs() / 3.0).max(0.0));
+        let triangles = triangulate(&ridge, SIZE, 0.001);
+        assert!(triangles.len() > 2 * 3);
+        assert!(triangles.len() < (SIZE - 1) * (SIZE - 1) * 2 * 3);
+    }
+
+    #[test]
+    fn heights_stay_within_max_error() {
+        let heights = bumps();
+        let max_error = 0.02;
+        let triangles = triangulate(&heights, SIZE, max_error);
+        assert!(triangles.len() < (SIZE - 1) * (SIZE - 1) * 2 * 3);
+
+        for (i, height) in heights.iter().enumerate() {
+            let p = [(i % SIZE) as f32, (i / SIZE) as f32];
+            let mut covered = false;
+            for triangle in triangles.chunks(3) {
+                if let Some(surface) = height_over(&heights, triangle, p) {
+                    covered = true;
+                    assert!(
+                        (surface - height).abs() <= max_error + 1e-5,
+                        "vertex {p:?} is {} away from the surface",
+                        (surface - height).abs()
+                    );
+                }
+            }
+            assert!(covered, "vertex {p:?} is not covered");
+        }
+    }
+
+    #[test]
+    fn border_vertices_are_always_emitted() {
+        // the interior collapses, not the border
+        let triangles = triangulate(&bumps(), SIZE, 10.0);
+
+        for i in 0..SIZE {
+            for (x, z) in [(i, 0), (i, SIZE - 1), (0, i), (SIZE - 1, i)] {
+                let index = (z * SIZE + x) as u32;
+                assert!(
+                    triangles.contains(&index),
+                    "border vertex {x}, {z} is missing"
+                );
+            }
+        }
+    }
+}
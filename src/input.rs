@@ -0,0 +1,440 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_flycam::prelude::KeyBindings;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::console::{AddConsoleCommand, CommandResult, ConsoleCommand, ConsoleError};
+
+/// Where the bindings are read from at startup and saved to when remapped.
+pub const INPUT_CONFIG_PATH: &str = "config/input.ron";
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        let input_map = match InputMap::load(INPUT_CONFIG_PATH) {
+            Ok(input_map) => input_map,
+            Err(InputConfigError::Io(error)) if error.kind() == std::io::ErrorKind::NotFound => {
+                InputMap::default()
+            }
+            Err(error) => {
+                warn!("could not load {INPUT_CONFIG_PATH}, using the default bindings: {error}");
+                InputMap::default()
+            }
+        };
+
+        for (binding, actions) in input_map.conflicts() {
+            warn!("{binding:?} is bound to several actions: {actions:?}");
+        }
+
+        app.insert_resource(input_map)
+            .add_console_command(
+                "keys",
+                ConsoleCommand::new(
+                    "keys [action] | keys bind <action> <key> | keys preset qwerty|azerty",
+                    "Shows or remaps the bindings, saved to config/input.ron",
+                    keys_command,
+                )
+                .with_keywords(&["bind", "preset", "qwerty", "azerty"]),
+            )
+            .add_systems(Update, (sync_flycam_bindings, save_input_map));
+    }
+}
+
+/// Everything the user can bind a key, mouse or gamepad button to.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Reflect,
+)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    Ascend,
+    Descend,
+    Jump,
+    Sprint,
+    CycleCamera,
+    ToggleMap,
+    GrabCursor,
+    ReleaseCursor,
+    ToggleFps,
+    ToggleHud,
+    ToggleConsole,
+    TogglePerfOverlay,
+    ExportPerf,
+    ToggleDebugPanel,
+    ToggleWireframe,
+    ToggleChunkBorders,
+    ToggleLodColoring,
+    ToggleNormals,
+    ToggleSlopeHeatmap,
+    ToggleBiomeColoring,
+    ToggleTaskStates,
+    GizmoTranslate,
+    GizmoRotate,
+    GizmoScale,
+    ToggleSculpt,
+    Sculpt,
+    NextBrushTool,
+    BrushGrow,
+    BrushShrink,
+}
+
+/// Actions of contexts that are never active at the same time may share a
+/// binding: walking and flying, or walking and the editor. The editor
+/// viewport flies, so its gizmo keys stay off the movement keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionContext {
+    Global,
+    /// Shared by the fly and walk modes.
+    Movement,
+    Fly,
+    Walk,
+    Editor,
+}
+
+impl Action {
+    pub fn context(self) -> ActionContext {
+        match self {
+            Action::MoveForward | Action::MoveBackward | Action::MoveLeft | Action::MoveRight => {
+                ActionContext::Movement
+            }
+            Action::Ascend | Action::Descend => ActionContext::Fly,
+            Action::Jump | Action::Sprint => ActionContext::Walk,
+            Action::GizmoTranslate | Action::GizmoRotate | Action::GizmoScale => {
+                ActionContext::Editor
+            }
+            _ => ActionContext::Global,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        InputMap::qwerty()
+    }
+}
+
+impl InputMap {
+    pub fn qwerty() -> InputMap {
+        InputMap::with_movement(KeyCode::KeyW, KeyCode::KeyA, KeyCode::KeyS, KeyCode::KeyD)
+    }
+
+    /// Same as QWERTY, with the movement keys where they are on a French
+    /// keyboard.
+    pub fn azerty() -> InputMap {
+        InputMap::with_movement(KeyCode::KeyZ, KeyCode::KeyQ, KeyCode::KeyS, KeyCode::KeyD)
+    }
+
+    fn with_movement(forward: KeyCode, left: KeyCode, backward: KeyCode, right: KeyCode) -> Self {
+        use Binding::*;
+
+        let bindings = [
+            (
+                Action::MoveForward,
+                vec![Key(forward), Key(KeyCode::ArrowUp)],
+            ),
+            (
+                Action::MoveBackward,
+                vec![Key(backward), Key(KeyCode::ArrowDown)],
+            ),
+            (Action::MoveLeft, vec![Key(left), Key(KeyCode::ArrowLeft)]),
+            (
+                Action::MoveRight,
+                vec![Key(right), Key(KeyCode::ArrowRight)],
+            ),
+            (
+                Action::Ascend,
+                vec![Key(KeyCode::Space), Gamepad(GamepadButtonType::South)],
+            ),
+            (
+                Action::Descend,
+                vec![Key(KeyCode::ShiftLeft), Gamepad(GamepadButtonType::East)],
+            ),
+            (
+                Action::Jump,
+                vec![Key(KeyCode::Space), Gamepad(GamepadButtonType::South)],
+            ),
+            (
+                Action::Sprint,
+                vec![
+                    Key(KeyCode::ShiftLeft),
+                    Gamepad(GamepadButtonType::LeftThumb),
+                ],
+            ),
+            (
+                Action::CycleCamera,
+                vec![Key(KeyCode::KeyC), Gamepad(GamepadButtonType::North)],
+            ),
+            (Action::ToggleMap, vec![Key(KeyCode::KeyM)]),
+            (Action::GrabCursor, vec![Mouse(MouseButton::Middle)]),
+            (Action::ReleaseCursor, vec![Key(KeyCode::Escape)]),
+            (Action::ToggleFps, vec![Key(KeyCode::F12)]),
+            (Action::ToggleHud, vec![Key(KeyCode::F9)]),
+            (Action::ToggleConsole, vec![Key(KeyCode::Backquote)]),
+            (Action::TogglePerfOverlay, vec![Key(KeyCode::F10)]),
+            (Action::ExportPerf, vec![Key(KeyCode::F11)]),
+            (Action::ToggleDebugPanel, vec![Key(KeyCode::F8)]),
+            (Action::ToggleWireframe, vec![Key(KeyCode::F1)]),
+            (Action::ToggleChunkBorders, vec![Key(KeyCode::F2)]),
+            (Action::ToggleLodColoring, vec![Key(KeyCode::F3)]),
+            (Action::ToggleNormals, vec![Key(KeyCode::F4)]),
+            (Action::ToggleSlopeHeatmap, vec![Key(KeyCode::F5)]),
+            (Action::ToggleBiomeColoring, vec![Key(KeyCode::F6)]),
+            (Action::ToggleTaskStates, vec![Key(KeyCode::F7)]),
+            (Action::GizmoTranslate, vec![Key(KeyCode::KeyT)]),
+            (Action::GizmoRotate, vec![Key(KeyCode::KeyR)]),
+            (Action::GizmoScale, vec![Key(KeyCode::KeyE)]),
+            (Action::ToggleSculpt, vec![Key(KeyCode::KeyB)]),
+            (Action::Sculpt, vec![Mouse(MouseButton::Left)]),
+            (Action::NextBrushTool, vec![Key(KeyCode::Tab)]),
+            (Action::BrushGrow, vec![Key(KeyCode::BracketRight)]),
+            (Action::BrushShrink, vec![Key(KeyCode::BracketLeft)]),
+        ];
+
+        InputMap {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// First keyboard key bound to `action`.
+    pub fn key(&self, action: Action) -> Option<KeyCode> {
+        self.bindings(action)
+            .iter()
+            .find_map(|binding| match binding {
+                Binding::Key(key) => Some(*key),
+                _ => None,
+            })
+    }
+
+    /// Replaces the bindings of `action`.
+    pub fn bind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings.insert(action, bindings);
+    }
+
+    /// Bindings shared by actions that can be active at the same time.
+    pub fn conflicts(&self) -> Vec<(Binding, Vec<Action>)> {
+        let mut conflicts: Vec<(Binding, Vec<Action>)> = Vec::new();
+
+        for (&action, bindings) in &self.bindings {
+            for &binding in bindings {
+                let clashing: Vec<Action> = self
+                    .bindings
+                    .iter()
+                    .filter(|(&other, other_bindings)| {
+                        other != action
+                            && other_bindings.contains(&binding)
+                            && contexts_overlap(action.context(), other.context())
+                    })
+                    .map(|(&other, _)| other)
+                    .collect();
+
+                if clashing.is_empty() || conflicts.iter().any(|(b, _)| *b == binding) {
+                    continue;
+                }
+
+                let mut actions = vec![action];
+                actions.extend(clashing);
+                conflicts.push((binding, actions));
+            }
+        }
+
+        conflicts
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<InputMap, InputConfigError> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(ron::de::from_str(&text)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), InputConfigError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+fn contexts_overlap(a: ActionContext, b: ActionContext) -> bool {
+    use ActionContext::*;
+
+    match (a, b) {
+        (Global, _) | (_, Global) => true,
+        (Movement, Fly | Walk) | (Fly | Walk, Movement) => true,
+        (Editor, Movement | Fly) | (Movement | Fly, Editor) => true,
+        _ => a == b,
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum InputConfigError {
+    #[error("could not read or write the input config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse the input config: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not serialize the input config: {0}")]
+    Serialize(#[from] ron::Error),
+}
+
+/// Reads the state of the actions, whatever they are bound to.
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+    input_map: Res<'w, InputMap>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    gamepad_buttons: Res<'w, ButtonInput<GamepadButton>>,
+    gamepads: Res<'w, Gamepads>,
+}
+
+impl<'w> Actions<'w> {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.any(action, |actions, binding| match binding {
+            Binding::Key(key) => actions.keys.pressed(key),
+            Binding::Mouse(button) => actions.mouse.pressed(button),
+            Binding::Gamepad(button_type) => actions.gamepads.iter().any(|gamepad| {
+                actions
+                    .gamepad_buttons
+                    .pressed(GamepadButton::new(gamepad, button_type))
+            }),
+        })
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.any(action, |actions, binding| match binding {
+            Binding::Key(key) => actions.keys.just_pressed(key),
+            Binding::Mouse(button) => actions.mouse.just_pressed(button),
+            Binding::Gamepad(button_type) => actions.gamepads.iter().any(|gamepad| {
+                actions
+                    .gamepad_buttons
+                    .just_pressed(GamepadButton::new(gamepad, button_type))
+            }),
+        })
+    }
+
+    fn any(&self, action: Action, test: impl Fn(&Self, Binding) -> bool) -> bool {
+        self.input_map
+            .bindings(action)
+            .iter()
+            .any(|&binding| test(self, binding))
+    }
+}
+
+fn keys_command(world: &mut World, args: &[&str]) -> CommandResult {
+    let mut input_map = world.resource_mut::<InputMap>();
+
+    match args {
+        [] => {
+            let lines: Vec<String> = input_map
+                .bindings
+                .iter()
+                .map(|(action, bindings)| format!("{action:?}: {bindings:?}"))
+                .collect();
+            return Ok(lines.join("\n"));
+        }
+        [action] => {
+            let action = parse_ron::<Action>(action, "action")?;
+            return Ok(format!("{action:?}: {:?}", input_map.bindings(action)));
+        }
+        ["preset", "qwerty"] => *input_map = InputMap::qwerty(),
+        ["preset", "azerty"] => *input_map = InputMap::azerty(),
+        ["preset", preset] => {
+            return Err(ConsoleError::InvalidArgument(preset.to_string(), "preset"))
+        }
+        ["bind", action, key] => {
+            let action = parse_ron::<Action>(action, "action")?;
+            let key = parse_ron::<KeyCode>(key, "key")?;
+            input_map.bind(action, vec![Binding::Key(key)]);
+        }
+        _ => return Err(ConsoleError::Usage),
+    }
+
+    let mut output = format!("bindings saved to {INPUT_CONFIG_PATH}");
+    for (binding, actions) in input_map.conflicts() {
+        output.push_str(&format!(
+            "\n{binding:?} is bound to several actions: {actions:?}"
+        ));
+    }
+    Ok(output)
+}
+
+/// Parses the name of an enum variant, e.g. `MoveForward` or `KeyW`.
+fn parse_ron<T: serde::de::DeserializeOwned>(
+    arg: &str,
+    kind: &'static str,
+) -> Result<T, ConsoleError> {
+    ron::de::from_str(arg).map_err(|_| ConsoleError::InvalidArgument(arg.to_string(), kind))
+}
+
+/// The flycam reads its own key bindings, keep them in line with the map.
+fn sync_flycam_bindings(input_map: Res<InputMap>, mut key_bindings: ResMut<KeyBindings>) {
+    if !input_map.is_changed() {
+        return;
+    }
+
+    let defaults = KeyBindings::default();
+    let key = |action, default| input_map.key(action).unwrap_or(default);
+    key_bindings.move_forward = key(Action::MoveForward, defaults.move_forward);
+    key_bindings.move_backward = key(Action::MoveBackward, defaults.move_backward);
+    key_bindings.move_left = key(Action::MoveLeft, defaults.move_left);
+    key_bindings.move_right = key(Action::MoveRight, defaults.move_right);
+    key_bindings.move_ascend = key(Action::Ascend, defaults.move_ascend);
+    key_bindings.move_descend = key(Action::Descend, defaults.move_descend);
+}
+
+/// Persists the bindings every time they are remapped.
+fn save_input_map(input_map: Res<InputMap>) {
+    if !input_map.is_changed() || input_map.is_added() {
+        return;
+    }
+
+    if let Err(error) = input_map.save(INPUT_CONFIG_PATH) {
+        error!("could not save the bindings to {INPUT_CONFIG_PATH}: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_have_no_conflicts() {
+        for input_map in [InputMap::qwerty(), InputMap::azerty()] {
+            assert_eq!(input_map.conflicts(), Vec::new());
+        }
+    }
+
+    #[test]
+    fn gizmo_keys_clash_with_flying() {
+        let mut input_map = InputMap::qwerty();
+        input_map.bind(Action::GizmoScale, vec![Binding::Key(KeyCode::KeyS)]);
+
+        let conflicts = input_map.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].0, Binding::Key(KeyCode::KeyS));
+    }
+}
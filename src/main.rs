@@ -1,58 +1,134 @@
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
+use bevy::window::WindowMode;
 use bevy::{pbr::wireframe::WireframePlugin, prelude::*, window::WindowResolution};
 use bevy_atmosphere::plugin::AtmospherePlugin;
 use bevy_flycam::prelude::*;
 use bevy_rapier3d::prelude::*;
 
 pub mod camera;
+pub mod console;
+pub mod editor;
 pub mod generation;
+pub mod input;
+pub mod map;
 pub mod mouse_grab;
+pub mod origin;
+pub mod perf;
+pub mod player;
 pub mod postprocess;
+pub mod sculpt;
+pub mod settings;
 pub mod ui;
+pub mod water;
 pub mod world;
 
 use camera::CameraPlugin;
+use console::ConsolePlugin;
+use editor::EditorPlugin;
+use generation::chunk::RenderDistance;
+use generation::noise::NoiseSettings;
 use generation::GenerationPlugin;
+use input::InputPlugin;
+use map::MapPlugin;
 use mouse_grab::MouseGrabPlugin;
-use ui::FpsCounter;
+use origin::FloatingOriginPlugin;
+use perf::PerfOverlay;
+use player::PlayerPlugin;
+use sculpt::SculptPlugin;
+use settings::{Settings, SettingsError};
+use ui::{FpsCounter, HudPlugin};
+use water::WaterPlugin;
 use world::WorldPlugin;
 
 #[bevy_main]
 fn main() {
-    App::new()
-        .add_plugins((
-            DefaultPlugins.set(WindowPlugin {
-                primary_window: Some(Window {
-                    title: "Terrain Generation".to_string(),
-                    resolution: WindowResolution::new(1920.0, 1080.0),
-                    resizable: true,
-                    ..default()
-                }),
+    let settings = match Settings::from_args(std::env::args().skip(1)) {
+        Ok(settings) => settings,
+        Err(SettingsError::Help) => {
+            println!("{}", SettingsError::Help);
+            return;
+        }
+        Err(error) => {
+            eprintln!("error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut app = App::new();
+    // inserted before the plugins, so they keep these over their defaults
+    app.insert_resource(settings.graphics.msaa())
+        .insert_resource(RenderDistance(settings.world.render_distance))
+        .insert_resource(NoiseSettings {
+            seed: settings.world.seed,
+            ..default()
+        })
+        .insert_resource(MovementSettings {
+            sensitivity: settings.camera.sensitivity,
+            speed: settings.camera.speed,
+        });
+
+    app.add_plugins((
+        DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: settings.window.title.clone(),
+                resolution: WindowResolution::new(settings.window.width, settings.window.height),
+                mode: if settings.window.fullscreen {
+                    WindowMode::BorderlessFullscreen
+                } else {
+                    WindowMode::Windowed
+                },
+                resizable: true,
                 ..default()
             }),
-            AtmospherePlugin,
-            RapierPhysicsPlugin::<NoUserData>::default(),
-            WireframePlugin,
-            MouseGrabPlugin,
-            CameraPlugin,
-            GenerationPlugin,
-            WorldPlugin,
-            NoCameraPlayerPlugin,
-            FrameTimeDiagnosticsPlugin::default(),
-            FpsCounter,
-        ))
-        .insert_resource(MovementSettings {
-            sensitivity: 0.00010,
-            speed: 100.0,
-        })
-        .insert_resource(KeyBindings {
-            move_forward: KeyCode::KeyW,
-            move_left: KeyCode::KeyA,
-            move_backward: KeyCode::KeyS,
-            move_right: KeyCode::KeyD,
-            move_ascend: KeyCode::Space,
-            move_descend: KeyCode::ShiftLeft,
-            ..Default::default()
-        })
-        .run();
+            ..default()
+        }),
+        RapierPhysicsPlugin::<NoUserData>::default(),
+        InputPlugin,
+        MouseGrabPlugin,
+        CameraPlugin,
+        GenerationPlugin,
+        FloatingOriginPlugin,
+        NoCameraPlayerPlugin,
+        FrameTimeDiagnosticsPlugin,
+    ));
+
+    let plugins = &settings.plugins;
+    if plugins.atmosphere {
+        app.add_plugins(AtmospherePlugin);
+    }
+    if plugins.wireframe {
+        app.add_plugins(WireframePlugin);
+    }
+    if plugins.water {
+        app.add_plugins(WaterPlugin);
+    }
+    if plugins.world {
+        app.add_plugins(WorldPlugin);
+    }
+    if plugins.player {
+        app.add_plugins(PlayerPlugin);
+    }
+    if plugins.fps_counter {
+        app.add_plugins(FpsCounter);
+    }
+    if plugins.hud {
+        app.add_plugins(HudPlugin);
+    }
+    if plugins.perf_overlay {
+        app.add_plugins(PerfOverlay);
+    }
+    if plugins.sculpt {
+        app.add_plugins(SculptPlugin);
+    }
+    if plugins.map {
+        app.add_plugins(MapPlugin);
+    }
+    if plugins.editor {
+        app.add_plugins(EditorPlugin);
+    }
+    if plugins.console {
+        app.add_plugins(ConsolePlugin);
+    }
+
+    app.run();
 }
@@ -0,0 +1,642 @@
+use bevy::input::mouse::MouseWheel;
+use bevy::math::{DVec2, DVec3, I64Vec2};
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy::ui::RelativeCursorPosition;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+use bevy_dolly::prelude::Rig;
+use futures_lite::future;
+
+use crate::camera::{follow_rig, CameraController, CameraMode, MainCamera};
+use crate::console::{parse_arg, AddConsoleCommand, CommandResult, ConsoleCommand, ConsoleError};
+use crate::generation::biome::BiomeMap;
+use crate::generation::chunk::{
+    ChunkSettings, ChunkSettingsParam, CHUNK_WORLD_SCALE, CHUNK_WORLD_SIZE, HEIGHT_INTENSITY,
+};
+use crate::generation::color_ramp::{terrain_colors, TerrainColorRamp, TerrainColoring};
+use crate::generation::mesh::compute_normals;
+use crate::generation::noise::generate_noise_map;
+use crate::input::{Action, Actions};
+use crate::origin::WorldOrigin;
+use crate::player::{Player, PlayerSpawn};
+
+/// Chunks along each side of the minimap, centered on the camera chunk.
+const MINIMAP_CHUNKS: usize = 9;
+/// Minimap pixels along each side of a chunk.
+const MINIMAP_CHUNK_PIXELS: usize = 12;
+/// Size of the minimap on screen, in logical pixels.
+const MINIMAP_SIZE: f32 = 216.0;
+/// Pixels along each side of the world map.
+const WORLD_MAP_RESOLUTION: usize = 256;
+const MARKER_SIZE: f32 = 16.0;
+/// Height above the terrain the flying camera is teleported to.
+const TELEPORT_ALTITUDE: f32 = 30.0;
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Teleport>()
+            .add_console_command(
+                "tp",
+                ConsoleCommand::new(
+                    "tp <x> <z> | tp chunk <x> <z>",
+                    "Teleports to absolute world coordinates, or to the center of a chunk",
+                    teleport_command,
+                )
+                .with_keywords(&["chunk"]),
+            )
+            .add_systems(Startup, setup_map)
+            .add_systems(
+                Update,
+                (
+                    toggle_world_map,
+                    zoom_world_map,
+                    render_maps,
+                    teleport_on_click,
+                    grab_cursor_around_world_map,
+                    teleport,
+                    update_markers,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Moves the player, when the camera follows it, or else the camera to an
+/// absolute horizontal position, on the terrain surface.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Teleport(pub DVec2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MapKind {
+    Minimap,
+    WorldMap,
+}
+
+/// Image a map is rendered to, in the background.
+struct MapView {
+    image: Handle<Image>,
+    task: Option<Task<Image>>,
+    /// Area the image shows, as its center and size in world units.
+    area: Option<(DVec2, f64)>,
+    /// Area last sent to be rendered.
+    rendering: Option<(DVec2, f64)>,
+    /// Area to render once the current render is done.
+    next: Option<(DVec2, f64)>,
+}
+
+impl MapView {
+    fn new(image: Handle<Image>) -> Self {
+        MapView {
+            image,
+            task: None,
+            area: None,
+            rendering: None,
+            next: None,
+        }
+    }
+
+    /// Renders the area unless it is already shown or on its way, or the
+    /// terrain changed.
+    fn request(&mut self, center: DVec2, size: f64, terrain_changed: bool) {
+        if terrain_changed || self.rendering != Some((center, size)) {
+            self.next = Some((center, size));
+        }
+    }
+
+    fn update(
+        &mut self,
+        images: &mut Assets<Image>,
+        resolution: usize,
+        grid: Option<usize>,
+        settings: impl FnOnce() -> ChunkSettings,
+    ) {
+        if let Some(task) = self.task.as_mut() {
+            let Some(image) = block_on(future::poll_once(task)) else {
+                return;
+            };
+            images.insert(self.image.clone(), image);
+            self.area = self.rendering;
+            self.task = None;
+        }
+
+        let Some((center, size)) = self.next.take() else {
+            return;
+        };
+        let settings = settings();
+        self.rendering = Some((center, size));
+        self.task = Some(
+            AsyncComputeTaskPool::get()
+                .spawn(async move { render_map(center, size, resolution, grid, &settings) }),
+        );
+    }
+}
+
+/// Minimap in the corner of the screen, and the full-screen world map
+/// toggled with `Action::ToggleMap`. Clicking either teleports there.
+#[derive(Resource)]
+pub struct TerrainMap {
+    minimap: MapView,
+    world_map: MapView,
+    pub world_map_open: bool,
+    /// Absolute position the world map is centered on, where the camera was
+    /// when it was opened.
+    pub world_map_center: DVec2,
+    /// World units along each side of the world map, zoomed with the mouse
+    /// wheel.
+    pub world_map_size: f64,
+}
+
+impl TerrainMap {
+    fn view(&self, kind: MapKind) -> &MapView {
+        match kind {
+            MapKind::Minimap => &self.minimap,
+            MapKind::WorldMap => &self.world_map,
+        }
+    }
+}
+
+#[derive(Component)]
+struct MapImage(MapKind);
+
+/// Arrow showing where the camera is and where it looks.
+#[derive(Component)]
+struct MapMarker(MapKind);
+
+#[derive(Component)]
+struct WorldMapRoot;
+
+#[derive(Component)]
+struct WorldMapStatus;
+
+fn setup_map(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let minimap_resolution = MINIMAP_CHUNKS * MINIMAP_CHUNK_PIXELS;
+    let minimap = images.add(map_image(
+        minimap_resolution,
+        vec![0; minimap_resolution * minimap_resolution * 4],
+    ));
+    let world_map = images.add(map_image(
+        WORLD_MAP_RESOLUTION,
+        vec![0; WORLD_MAP_RESOLUTION * WORLD_MAP_RESOLUTION * 4],
+    ));
+    let arrow = images.add(arrow_image());
+
+    let marker = |kind| {
+        (
+            MapMarker(kind),
+            ImageBundle {
+                image: UiImage::new(arrow.clone()),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Px(MARKER_SIZE),
+                    height: Val::Px(MARKER_SIZE),
+                    // centered on its position
+                    margin: UiRect {
+                        left: Val::Px(-MARKER_SIZE / 2.0),
+                        top: Val::Px(-MARKER_SIZE / 2.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+                ..default()
+            },
+        )
+    };
+
+    commands
+        .spawn(NodeBundle {
+            background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+            border_color: BorderColor(Color::BLACK),
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Percent(1.),
+                bottom: Val::Percent(1.),
+                width: Val::Px(MINIMAP_SIZE),
+                height: Val::Px(MINIMAP_SIZE),
+                border: UiRect::all(Val::Px(2.0)),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    MapImage(MapKind::Minimap),
+                    ImageBundle {
+                        image: UiImage::new(minimap.clone()),
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    Interaction::default(),
+                    RelativeCursorPosition::default(),
+                ))
+                .with_children(|parent| {
+                    parent.spawn(marker(MapKind::Minimap));
+                });
+        });
+
+    commands
+        .spawn((
+            WorldMapRoot,
+            NodeBundle {
+                background_color: BackgroundColor(Color::BLACK.with_a(0.8)),
+                // over everything but the FPS counter
+                z_index: ZIndex::Global(i32::MAX - 1),
+                visibility: Visibility::Hidden,
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(8.0),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    MapImage(MapKind::WorldMap),
+                    ImageBundle {
+                        image: UiImage::new(world_map.clone()),
+                        style: Style {
+                            height: Val::Percent(85.0),
+                            aspect_ratio: Some(1.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    Interaction::default(),
+                    RelativeCursorPosition::default(),
+                ))
+                .with_children(|parent| {
+                    parent.spawn(marker(MapKind::WorldMap));
+                });
+
+            let style = TextStyle {
+                font_size: 16.0,
+                color: Color::WHITE,
+                ..default()
+            };
+            parent.spawn(TextBundle::from_section(
+                "Click to teleport, scroll to zoom",
+                style.clone(),
+            ));
+            parent.spawn((WorldMapStatus, TextBundle::from_section("", style)));
+        });
+
+    commands.insert_resource(TerrainMap {
+        minimap: MapView::new(minimap),
+        world_map: MapView::new(world_map),
+        world_map_open: false,
+        world_map_center: DVec2::ZERO,
+        world_map_size: 32768.0,
+    });
+}
+
+/// Opens the world map around the camera.
+fn toggle_world_map(
+    actions: Actions,
+    mut map: ResMut<TerrainMap>,
+    origin: Res<WorldOrigin>,
+    cameras: Query<&Transform, With<MainCamera>>,
+    mut roots: Query<&mut Visibility, With<WorldMapRoot>>,
+) {
+    let visible = if map.world_map_open {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    for mut visibility in roots.iter_mut() {
+        visibility.set_if_neq(visible);
+    }
+
+    if !actions.just_pressed(Action::ToggleMap) {
+        return;
+    }
+
+    map.world_map_open = !map.world_map_open;
+    if map.world_map_open {
+        if let Ok(camera) = cameras.get_single() {
+            map.world_map_center = origin.to_world_xz(camera.translation);
+        }
+    }
+}
+
+/// Frees the cursor to click on the world map, and grabs it back once the map
+/// closes, whether toggled or clicked, if it was grabbed before.
+fn grab_cursor_around_world_map(
+    map: Res<TerrainMap>,
+    mut was_open: Local<bool>,
+    mut was_grabbed: Local<bool>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if map.world_map_open == *was_open {
+        return;
+    }
+    *was_open = map.world_map_open;
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+
+    if map.world_map_open {
+        *was_grabbed = window.cursor.grab_mode != CursorGrabMode::None;
+        window.cursor.visible = true;
+        window.cursor.grab_mode = CursorGrabMode::None;
+    } else if *was_grabbed {
+        window.cursor.visible = false;
+        window.cursor.grab_mode = CursorGrabMode::Locked;
+    }
+}
+
+fn zoom_world_map(mut map: ResMut<TerrainMap>, mut wheel: EventReader<MouseWheel>) {
+    let scroll: f32 = wheel.read().map(|event| event.y).sum();
+    if !map.world_map_open || scroll == 0.0 {
+        return;
+    }
+
+    map.world_map_size = (map.world_map_size * 0.8f64.powf(scroll as f64)).clamp(2048.0, 262144.0);
+}
+
+/// Keeps the minimap centered on the camera chunk, and renders the world
+/// map only while it is open.
+fn render_maps(
+    mut map: ResMut<TerrainMap>,
+    mut images: ResMut<Assets<Image>>,
+    settings: ChunkSettingsParam,
+    coloring: Res<TerrainColoring>,
+    mut ramp_events: EventReader<AssetEvent<TerrainColorRamp>>,
+    origin: Res<WorldOrigin>,
+    cameras: Query<&Transform, With<MainCamera>>,
+) {
+    let ramp_changed = ramp_events.read().any(|event| match event {
+        AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => {
+            *id == coloring.ramp.id()
+        }
+        _ => false,
+    });
+    let terrain_changed = settings.is_changed() || ramp_changed;
+
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let map = &mut *map;
+
+    let chunk = origin.chunk_at(camera.translation);
+    let chunk_size = CHUNK_WORLD_SIZE as f64;
+    map.minimap.request(
+        chunk.as_dvec2() * chunk_size,
+        MINIMAP_CHUNKS as f64 * chunk_size,
+        terrain_changed,
+    );
+    if map.world_map_open {
+        map.world_map
+            .request(map.world_map_center, map.world_map_size, terrain_changed);
+    } else if terrain_changed {
+        // rendered again next time it opens
+        map.world_map.rendering = None;
+    }
+
+    map.minimap.update(
+        &mut images,
+        MINIMAP_CHUNKS * MINIMAP_CHUNK_PIXELS,
+        Some(MINIMAP_CHUNK_PIXELS),
+        || settings.get(),
+    );
+    map.world_map
+        .update(&mut images, WORLD_MAP_RESOLUTION, None, || settings.get());
+}
+
+/// Terrain of the `size` world units square around `center`, colored the
+/// way the chunks are, with a line every `grid` pixels.
+fn render_map(
+    center: DVec2,
+    size: f64,
+    resolution: usize,
+    grid: Option<usize>,
+    settings: &ChunkSettings,
+) -> Image {
+    let scale = CHUNK_WORLD_SCALE as f64;
+    let min = center - size / 2.0;
+    let extent = size / scale;
+    let origin = [min.x * 2.0 / scale, min.y * 2.0 / scale];
+    let noise_map = generate_noise_map(extent, resolution, resolution, origin, &settings.noise);
+
+    // same grid as a chunk mesh, so the colors match the terrain
+    let step = extent as f32 / resolution as f32;
+    let mut positions = Vec::with_capacity((resolution + 1) * (resolution + 1));
+    for d in 0..=resolution {
+        for w in 0..=resolution {
+            let height = noise_map.get_value(w, d) as f32 * HEIGHT_INTENSITY;
+            positions.push([w as f32 * step, height, d as f32 * step]);
+        }
+    }
+    let heights: Vec<f32> = positions.iter().map(|[_, y, _]| *y).collect();
+    let normals = compute_normals(&heights, resolution, resolution, extent as f32);
+    let biomes = BiomeMap::generate(
+        &settings.biome_table,
+        &heights,
+        resolution,
+        resolution,
+        origin,
+        2.0 * extent / resolution as f64,
+    );
+    let colors = terrain_colors(
+        settings.color_ramp.as_ref(),
+        &biomes.biomes,
+        &positions,
+        &normals,
+    );
+
+    let mut data = Vec::with_capacity(resolution * resolution * 4);
+    for d in 0..resolution {
+        for w in 0..resolution {
+            let [r, g, b, _] = colors[d * (resolution + 1) + w];
+            let shade = match grid {
+                Some(cell) if w % cell == 0 || d % cell == 0 => 0.6,
+                _ => 1.0,
+            };
+            let channel = |value: f32| ((value * shade).clamp(0.0, 1.0) * 255.0) as u8;
+            data.extend([channel(r), channel(g), channel(b), 255]);
+        }
+    }
+
+    map_image(resolution, data)
+}
+
+fn map_image(resolution: usize, data: Vec<u8>) -> Image {
+    Image::new(
+        Extent3d {
+            width: resolution as u32,
+            height: resolution as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+/// White arrow pointing up, on a transparent background.
+fn arrow_image() -> Image {
+    const SIZE: usize = 32;
+    let [tip, left, right] = [
+        Vec2::new(16.0, 2.0),
+        Vec2::new(4.0, 29.0),
+        Vec2::new(28.0, 29.0),
+    ];
+    let notch = Vec2::new(16.0, 22.0);
+
+    // right of every edge, going clockwise around a triangle
+    let inside = |point: Vec2, [a, b, c]: [Vec2; 3]| {
+        [(a, b), (b, c), (c, a)]
+            .iter()
+            .all(|(from, to)| (*to - *from).perp_dot(point - *from) >= 0.0)
+    };
+
+    let mut data = Vec::with_capacity(SIZE * SIZE * 4);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let point = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let filled = inside(point, [tip, right, notch]) || inside(point, [tip, notch, left]);
+            data.extend(if filled {
+                [255, 255, 255, 255]
+            } else {
+                [0, 0, 0, 0]
+            });
+        }
+    }
+
+    map_image(SIZE, data)
+}
+
+/// Teleports to the clicked place, closing the world map.
+fn teleport_on_click(
+    mut map: ResMut<TerrainMap>,
+    clicks: Query<(&MapImage, &Interaction, &RelativeCursorPosition), Changed<Interaction>>,
+    mut teleports: EventWriter<Teleport>,
+) {
+    for (image, interaction, cursor) in clicks.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let (Some(position), Some((center, size))) = (cursor.normalized, map.view(image.0).area)
+        else {
+            continue;
+        };
+
+        teleports.send(Teleport(center + (position.as_dvec2() - 0.5) * size));
+        if image.0 == MapKind::WorldMap {
+            map.world_map_open = false;
+        }
+    }
+}
+
+fn teleport(
+    mut commands: Commands,
+    mut teleports: EventReader<Teleport>,
+    terrain: ChunkSettingsParam,
+    origin: Res<WorldOrigin>,
+    mode: Res<State<CameraMode>>,
+    mut players: Query<(Entity, &mut Transform), With<Player>>,
+    mut cameras: Query<(&mut Transform, &mut CameraController, &mut Rig), Without<Player>>,
+) {
+    for Teleport(target) in teleports.read() {
+        let ground = origin.to_local(DVec3::new(
+            target.x,
+            terrain.surface_height(*target) as f64,
+            target.y,
+        ));
+
+        match players.get_single_mut() {
+            Ok((player, mut transform)) if *mode.get() == CameraMode::Follow => {
+                // the capsule is 2 units tall, centered on its translation
+                transform.translation = ground + Vec3::Y;
+                // held there until the terrain under it has a collider
+                commands.entity(player).insert(PlayerSpawn);
+
+                for (mut camera, _, mut rig) in cameras.iter_mut() {
+                    *rig = follow_rig(camera.rotation);
+                    camera.translation = ground + Vec3::Y * TELEPORT_ALTITUDE;
+                }
+            }
+            _ => {
+                for (mut camera, mut controller, _) in cameras.iter_mut() {
+                    camera.translation = ground + Vec3::Y * TELEPORT_ALTITUDE;
+                    controller.focus = ground;
+                }
+            }
+        }
+    }
+}
+
+fn teleport_command(world: &mut World, args: &[&str]) -> CommandResult {
+    let target = match args {
+        ["chunk", x, z] => {
+            let coords = I64Vec2::new(parse_arg(x)?, parse_arg(z)?);
+            coords.as_dvec2() * CHUNK_WORLD_SIZE as f64
+        }
+        [x, z] => DVec2::new(parse_arg(x)?, parse_arg(z)?),
+        _ => return Err(ConsoleError::Usage),
+    };
+
+    world.send_event(Teleport(target));
+    Ok(format!("teleporting to {:.0}, {:.0}", target.x, target.y))
+}
+
+/// Places the camera arrows on the maps, pointing where the camera looks.
+fn update_markers(
+    map: Res<TerrainMap>,
+    origin: Res<WorldOrigin>,
+    cameras: Query<&Transform, With<MainCamera>>,
+    mut markers: Query<
+        (&MapMarker, &mut Style, &mut Transform, &mut Visibility),
+        Without<MainCamera>,
+    >,
+    mut status: Query<&mut Text, With<WorldMapStatus>>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let position = origin.to_world_xz(camera.translation);
+    let forward = camera.forward();
+    // clockwise from the top of the map, which is toward -Z
+    let heading = forward.x.atan2(-forward.z);
+
+    for (marker, mut style, mut transform, mut visibility) in markers.iter_mut() {
+        let Some((center, size)) = map.view(marker.0).area else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        let uv = ((position - center) / size + 0.5).as_vec2();
+        if !(0.0..=1.0).contains(&uv.x) || !(0.0..=1.0).contains(&uv.y) {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+
+        *visibility = Visibility::Inherited;
+        style.left = Val::Percent(uv.x * 100.0);
+        style.top = Val::Percent(uv.y * 100.0);
+        transform.rotation = Quat::from_rotation_z(heading);
+    }
+
+    for mut text in status.iter_mut() {
+        text.sections[0].value = if map.world_map.task.is_some() {
+            "Rendering...".to_string()
+        } else {
+            String::new()
+        };
+    }
+}
@@ -1,5 +1,7 @@
 use bevy::{prelude::*, window::CursorGrabMode};
 
+use crate::input::{Action, Actions};
+
 pub struct MouseGrabPlugin;
 
 impl Plugin for MouseGrabPlugin {
@@ -8,19 +10,15 @@ impl Plugin for MouseGrabPlugin {
     }
 }
 
-fn grab_mouse(
-    mut windows: Query<&mut Window>,
-    key: Res<ButtonInput<KeyCode>>,
-    mouse: Res<ButtonInput<MouseButton>>,
-) {
+fn grab_mouse(mut windows: Query<&mut Window>, actions: Actions) {
     let mut window = windows.single_mut();
 
-    if mouse.just_pressed(MouseButton::Middle) {
+    if actions.just_pressed(Action::GrabCursor) {
         window.cursor.visible = false;
         window.cursor.grab_mode = CursorGrabMode::Locked;
     }
 
-    if key.just_pressed(KeyCode::Escape) {
+    if actions.just_pressed(Action::ReleaseCursor) {
         window.cursor.visible = true;
         window.cursor.grab_mode = CursorGrabMode::None;
     }
@@ -0,0 +1,106 @@
+use bevy::math::{DVec2, DVec3, I64Vec2};
+use bevy::prelude::*;
+use bevy_dolly::prelude::Rig;
+
+use crate::camera::{shift_rig, CameraController, CameraTransition};
+use crate::generation::chunk::{ChunkLoader, CHUNK_WORLD_SIZE};
+
+/// Horizontal distance from the origin at which the chunk loader triggers a
+/// recentering of the world.
+const RECENTER_DISTANCE: f32 = 1024.0;
+
+pub struct FloatingOriginPlugin;
+
+impl Plugin for FloatingOriginPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldOrigin>()
+            .register_type::<WorldOrigin>()
+            .add_systems(Update, recenter_world);
+    }
+}
+
+/// Chunk the rendering origin sits on. Transforms are relative to it, so they
+/// keep their precision however far the world is explored, while absolute
+/// positions are kept in `f64` and chunk coordinates in `i64`.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+#[reflect(Resource)]
+pub struct WorldOrigin {
+    pub chunk: I64Vec2,
+}
+
+impl WorldOrigin {
+    /// Absolute world position of the origin.
+    pub fn offset(&self) -> DVec3 {
+        let size = CHUNK_WORLD_SIZE as f64;
+        DVec3::new(self.chunk.x as f64 * size, 0.0, self.chunk.y as f64 * size)
+    }
+
+    pub fn to_world(&self, translation: Vec3) -> DVec3 {
+        self.offset() + translation.as_dvec3()
+    }
+
+    pub fn to_local(&self, position: DVec3) -> Vec3 {
+        (position - self.offset()).as_vec3()
+    }
+
+    /// Absolute coordinates of the chunk under `translation`.
+    pub fn chunk_at(&self, translation: Vec3) -> I64Vec2 {
+        self.chunk
+            + I64Vec2::new(
+                (translation.x / CHUNK_WORLD_SIZE).round() as i64,
+                (translation.z / CHUNK_WORLD_SIZE).round() as i64,
+            )
+    }
+
+    /// Translation of the center of the chunk at `coords`.
+    pub fn chunk_translation(&self, coords: I64Vec2) -> Vec3 {
+        let local = (coords - self.chunk).as_vec2() * CHUNK_WORLD_SIZE;
+        Vec3::new(local.x, 0.0, local.y)
+    }
+
+    /// Absolute horizontal position of `translation`.
+    pub fn to_world_xz(&self, translation: Vec3) -> DVec2 {
+        let position = self.to_world(translation);
+        DVec2::new(position.x, position.z)
+    }
+}
+
+/// Root entities placed in the world, UI nodes left out.
+type WorldRoot = (Without<Parent>, Without<Node>);
+
+/// Moves the origin to the chunk loader once it wanders too far, shifting
+/// every root entity back by the same amount. Rapier picks the moved
+/// transforms up and teleports the bodies, keeping their velocities. The
+/// camera state kept in world space outside of transforms moves with them.
+fn recenter_world(
+    mut origin: ResMut<WorldOrigin>,
+    mut roots: Query<(&mut Transform, Has<ChunkLoader>), WorldRoot>,
+    mut transition: ResMut<CameraTransition>,
+    mut cameras: Query<(&mut CameraController, Option<&mut Rig>)>,
+) {
+    let Some(loader) = roots
+        .iter()
+        .find_map(|(transform, is_loader)| is_loader.then_some(transform.translation))
+    else {
+        return;
+    };
+    if loader.xz().length() < RECENTER_DISTANCE {
+        return;
+    }
+
+    let shift = origin.chunk_at(loader) - origin.chunk;
+    let offset = origin.chunk_translation(origin.chunk + shift);
+    origin.chunk += shift;
+
+    for (mut transform, _) in &mut roots {
+        transform.translation -= offset;
+    }
+
+    transition.shift(offset);
+    for (mut controller, rig) in &mut cameras {
+        controller.focus -= offset;
+        if let Some(mut rig) = rig {
+            shift_rig(&mut rig, offset);
+        }
+    }
+}
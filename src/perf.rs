@@ -0,0 +1,293 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::diagnostic::{DiagnosticPath, DiagnosticsStore, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+
+use crate::generation::diagnostics::*;
+use crate::input::{Action, Actions, InputMap};
+
+/// Folder the CSV exports are written to.
+pub const PERF_EXPORT_DIR: &str = "perf";
+
+/// Bars of each sparkline, one per measurement.
+const SPARKLINE_BARS: usize = 60;
+const SPARKLINE_HEIGHT: f32 = 24.0;
+
+/// Diagnostics drawn with a sparkline of their last measurements.
+const GRAPHS: [(DiagnosticPath, &str); 4] = [
+    (FrameTimeDiagnosticsPlugin::FRAME_TIME, "Frame time"),
+    (CHUNK_NOISE_TIME, "Chunk noise"),
+    (CHUNK_MESH_TIME, "Chunk mesh"),
+    (CHUNK_COLLIDER_TIME, "Chunk collider"),
+];
+
+/// Diagnostics only shown as their last value.
+const COUNTERS: [(DiagnosticPath, &str); 5] = [
+    (PENDING_CHUNK_TASKS, "Pending chunks"),
+    (PENDING_REPLACE_TASKS, "Pending replacements"),
+    (LOADED_CHUNKS, "Loaded chunks"),
+    (TERRAIN_VERTICES, "Terrain vertices"),
+    (TERRAIN_MESH_MEMORY, "Terrain meshes"),
+];
+
+/// Chunk generation diagnostics, with graphs, toggled with
+/// `Action::TogglePerfOverlay` and written to a CSV file with
+/// `Action::ExportPerf`.
+pub struct PerfOverlay;
+
+impl Plugin for PerfOverlay {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_perf_overlay).add_systems(
+            Update,
+            (
+                perf_overlay_showhide,
+                (perf_text_update_system, sparkline_update_system),
+                export_perf,
+                update_export_hint,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Marker to find the container entity so we can show/hide the overlay
+#[derive(Component)]
+struct PerfRoot;
+
+/// Text showing the latest value of a diagnostic
+#[derive(Component)]
+struct PerfText {
+    path: DiagnosticPath,
+    label: &'static str,
+}
+
+/// Row of bars, the rightmost being the latest measurement
+#[derive(Component)]
+struct Sparkline(DiagnosticPath);
+
+#[derive(Component)]
+struct PerfStatus;
+
+fn setup_perf_overlay(mut commands: Commands, input_map: Res<InputMap>) {
+    let style = TextStyle {
+        font_size: 14.0,
+        color: Color::WHITE,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            PerfRoot,
+            NodeBundle {
+                background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+                z_index: ZIndex::Global(i32::MAX),
+                visibility: Visibility::Hidden,
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    // under the FPS counter
+                    right: Val::Percent(1.),
+                    top: Val::Px(40.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(2.0),
+                    padding: UiRect::all(Val::Px(4.0)),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            for (path, label) in GRAPHS {
+                parent.spawn((
+                    PerfText {
+                        path: path.clone(),
+                        label,
+                    },
+                    TextBundle::from_section("", style.clone()),
+                ));
+                parent
+                    .spawn((
+                        Sparkline(path),
+                        NodeBundle {
+                            background_color: BackgroundColor(Color::BLACK.with_a(0.3)),
+                            style: Style {
+                                height: Val::Px(SPARKLINE_HEIGHT),
+                                align_items: AlignItems::FlexEnd,
+                                column_gap: Val::Px(1.0),
+                                margin: UiRect::bottom(Val::Px(4.0)),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                    ))
+                    .with_children(|parent| {
+                        for _ in 0..SPARKLINE_BARS {
+                            parent.spawn(NodeBundle {
+                                background_color: BackgroundColor(Color::rgb(0.3, 0.8, 0.4)),
+                                style: Style {
+                                    width: Val::Px(2.0),
+                                    height: Val::Percent(0.0),
+                                    ..default()
+                                },
+                                ..default()
+                            });
+                        }
+                    });
+            }
+
+            for (path, label) in COUNTERS {
+                parent.spawn((
+                    PerfText { path, label },
+                    TextBundle::from_section("", style.clone()),
+                ));
+            }
+
+            parent.spawn((
+                PerfStatus,
+                TextBundle::from_section(
+                    export_hint(&input_map),
+                    TextStyle {
+                        color: Color::GRAY,
+                        ..style
+                    },
+                ),
+            ));
+        });
+}
+
+fn perf_text_update_system(
+    diagnostics: Res<DiagnosticsStore>,
+    mut query: Query<(&mut Text, &PerfText)>,
+) {
+    for (mut text, perf) in &mut query {
+        let Some(diagnostic) = diagnostics.get(&perf.path) else {
+            continue;
+        };
+        let suffix = &diagnostic.suffix;
+
+        text.sections[0].value = match (diagnostic.value(), diagnostic.average()) {
+            // timings vary a lot from one chunk to the next
+            (Some(value), Some(average)) if !suffix.is_empty() => {
+                format!("{}: {value:.2} {suffix} (avg {average:.2})", perf.label)
+            }
+            (Some(value), _) => format!("{}: {value:.0} {suffix}", perf.label),
+            (None, _) => format!("{}: N/A", perf.label),
+        };
+    }
+}
+
+fn sparkline_update_system(
+    diagnostics: Res<DiagnosticsStore>,
+    sparklines: Query<(&Sparkline, &Children)>,
+    mut bars: Query<&mut Style>,
+) {
+    for (sparkline, children) in sparklines.iter() {
+        let Some(diagnostic) = diagnostics.get(&sparkline.0) else {
+            continue;
+        };
+
+        let values: Vec<f64> = diagnostic.values().copied().collect();
+        let values = &values[values.len().saturating_sub(SPARKLINE_BARS)..];
+        let max = values.iter().copied().fold(f64::EPSILON, f64::max);
+
+        // right-aligned, so the graph scrolls to the left
+        let padding = children.len().saturating_sub(values.len());
+        for (index, child) in children.iter().enumerate() {
+            let Ok(mut style) = bars.get_mut(*child) else {
+                continue;
+            };
+            let value = index.checked_sub(padding).map_or(0.0, |i| values[i]);
+            style.height = Val::Percent((value / max * 100.0) as f32);
+        }
+    }
+}
+
+/// Toggle the overlay when pressing F10
+fn perf_overlay_showhide(mut q: Query<&mut Visibility, With<PerfRoot>>, actions: Actions) {
+    if actions.just_pressed(Action::TogglePerfOverlay) {
+        let mut vis = q.single_mut();
+        *vis = match *vis {
+            Visibility::Hidden => Visibility::Visible,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+fn export_perf(
+    actions: Actions,
+    diagnostics: Res<DiagnosticsStore>,
+    mut status: Query<&mut Text, With<PerfStatus>>,
+) {
+    if !actions.just_pressed(Action::ExportPerf) {
+        return;
+    }
+
+    let message = match write_csv(&diagnostics, PERF_EXPORT_DIR) {
+        Ok(path) => {
+            info!("exported the diagnostics to {}", path.display());
+            format!("Exported {}", path.display())
+        }
+        Err(error) => {
+            warn!("could not export the diagnostics: {error}");
+            format!("Export failed: {error}")
+        }
+    };
+    for mut text in &mut status {
+        text.sections[0].value = message.clone();
+    }
+}
+
+/// Tells which key exports the diagnostics, as currently bound.
+fn export_hint(input_map: &InputMap) -> String {
+    match input_map.key(Action::ExportPerf) {
+        Some(key) => format!("{key:?} to export to CSV"),
+        None => "Bind ExportPerf to export to CSV".to_string(),
+    }
+}
+
+/// Shows the new key once the export is rebound, over the last export status.
+fn update_export_hint(input_map: Res<InputMap>, mut status: Query<&mut Text, With<PerfStatus>>) {
+    if !input_map.is_changed() || input_map.is_added() {
+        return;
+    }
+    for mut text in &mut status {
+        text.sections[0].value = export_hint(&input_map);
+    }
+}
+
+/// Writes every measurement kept by the diagnostics, one per line, with the
+/// seconds since the oldest one.
+fn write_csv(diagnostics: &DiagnosticsStore, folder: impl AsRef<Path>) -> std::io::Result<PathBuf> {
+    let start = diagnostics
+        .iter()
+        .filter_map(|diagnostic| diagnostic.measurements().next())
+        .map(|measurement| measurement.time)
+        .min();
+
+    let mut csv = String::from("diagnostic,seconds,value\n");
+    for diagnostic in diagnostics.iter() {
+        for measurement in diagnostic.measurements() {
+            let seconds = start.map_or(0.0, |start| {
+                measurement.time.duration_since(start).as_secs_f64()
+            });
+            writeln!(
+                csv,
+                "{},{seconds:.4},{}",
+                diagnostic.path(),
+                measurement.value
+            )
+            .unwrap();
+        }
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let folder = folder.as_ref();
+    std::fs::create_dir_all(folder)?;
+    let path = folder.join(format!("perf-{timestamp}.csv"));
+    std::fs::write(&path, csv)?;
+
+    Ok(path)
+}
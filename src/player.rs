@@ -1,30 +1,56 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
+use crate::camera::CameraMode;
+use crate::generation::chunk::TerrainCollider;
+use crate::input::{Action, Actions};
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_player)
-            .add_systems(Update, keyboard_movement);
+        app.add_systems(Startup, setup_player).add_systems(
+            Update,
+            (
+                place_player,
+                keyboard_movement.run_if(in_state(CameraMode::Follow)),
+            )
+                .chain(),
+        );
     }
 }
 
+/// Height from which the player looks down for the terrain when spawning.
+const SPAWN_RAY_HEIGHT: f32 = 10000.0;
+
 #[derive(Component)]
 pub struct Player {
-    speed: f32,
-    lerp_factor: f32,
+    pub speed: f32,
+    pub sprint_multiplier: f32,
+    pub jump_speed: f32,
+    pub gravity: f32,
+    /// How fast the player turns toward the movement direction, per second.
+    pub turn_speed: f32,
+    vertical_speed: f32,
 }
 
 impl Default for Player {
     fn default() -> Self {
         Player {
             speed: 10.0,
-            lerp_factor: 0.95,
+            sprint_multiplier: 2.0,
+            jump_speed: 8.0,
+            gravity: 25.0,
+            turn_speed: 10.0,
+            vertical_speed: 0.0,
         }
     }
 }
 
+/// Keeps the player frozen until the terrain under it has a collider.
+#[derive(Component)]
+pub struct PlayerSpawn;
+
 fn setup_player(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -32,28 +58,28 @@ fn setup_player(
 ) {
     let player = (
         PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Capsule {
-                radius: 0.5,
-                depth: 1.0,
-                ..default()
-            })),
-            material: materials.add(Color::RED.into()),
-            transform: Transform::from_xyz(10.0, 10.0, 0.0),
+            mesh: meshes.add(Capsule3d::new(0.5, 1.0)),
+            material: materials.add(Color::RED),
+            transform: Transform::from_xyz(10.0, SPAWN_RAY_HEIGHT, 0.0),
+            visibility: Visibility::Hidden,
             ..default()
         },
         Player {
             speed: 20.0,
             ..default()
         },
+        PlayerSpawn,
         // physics
-        RigidBody::KinematicVelocityBased,
-        LockedAxes::ROTATION_LOCKED,
-        GravityScale(10.5),
-        Velocity {
-            linvel: Vec3::new(0.0, 0.0, 0.0),
-            angvel: Vec3::new(0.0, 0.0, 0.0),
-        },
+        RigidBody::KinematicPositionBased,
         KinematicCharacterController {
+            max_slope_climb_angle: 45.0_f32.to_radians(),
+            min_slope_slide_angle: 30.0_f32.to_radians(),
+            autostep: Some(CharacterAutostep {
+                max_height: CharacterLength::Absolute(0.5),
+                min_width: CharacterLength::Absolute(0.2),
+                include_dynamic_bodies: false,
+            }),
+            snap_to_ground: Some(CharacterLength::Absolute(0.5)),
             custom_mass: Some(500.0),
             ..default()
         },
@@ -63,53 +89,105 @@ fn setup_player(
     commands.spawn(player);
 }
 
+/// Drops the player on the terrain surface once its collider is there, not on
+/// the props standing on it.
+fn place_player(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    mut players: Query<(Entity, &mut Transform, &mut Visibility), With<PlayerSpawn>>,
+    terrain: Query<(), TerrainCollider>,
+) {
+    let is_terrain = |entity: Entity| terrain.contains(entity);
+
+    for (entity, mut transform, mut visibility) in players.iter_mut() {
+        let origin = Vec3::new(
+            transform.translation.x,
+            SPAWN_RAY_HEIGHT,
+            transform.translation.z,
+        );
+        let filter = QueryFilter::only_fixed().predicate(&is_terrain);
+
+        let Some((_, toi)) =
+            rapier_context.cast_ray(origin, Vec3::NEG_Y, 2.0 * SPAWN_RAY_HEIGHT, true, filter)
+        else {
+            continue;
+        };
+
+        // the capsule is 2 units tall, centered on its translation
+        transform.translation.y = origin.y - toi + 1.0;
+        *visibility = Visibility::Inherited;
+        commands.entity(entity).remove::<PlayerSpawn>();
+    }
+}
+
 fn keyboard_movement(
-    keys: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
     time: Res<Time>,
-    mut player_query: Query<(
-        &mut Transform,
-        &mut KinematicCharacterController,
-        &mut Velocity,
-        &Player,
-    )>,
+    mut player_query: Query<
+        (
+            &mut Transform,
+            &mut KinematicCharacterController,
+            Option<&KinematicCharacterControllerOutput>,
+            &mut Player,
+        ),
+        Without<PlayerSpawn>,
+    >,
     camera_query: Query<&Transform, (With<Camera3d>, Without<Player>)>,
 ) {
-    for (mut player_transform, mut player_controller, mut player_velocity, player) in
-        player_query.iter_mut()
-    {
-        let camera: &Transform = match camera_query.get_single() {
-            Ok(c) => c,
-            Err(e) => Err(format!("Error retrieving camera : {}", e)).unwrap(),
-        };
-        let old_direction = player_transform.rotation;
-        let mut direction = old_direction.clone().to_scaled_axis();
-        let mut any = false;
-
-        let mut velocity = Vec3::ZERO;
-
-        // handle keyboard
-        for key in keys.get_pressed() {
-            any = true;
-            match key {
-                KeyCode::Z => velocity += camera.forward(),
-                KeyCode::S => velocity += camera.back(),
-                KeyCode::Q => velocity += camera.left(),
-                KeyCode::D => velocity += camera.right(),
-                _ => (),
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+
+    // move on the ground plane, whatever the camera pitch
+    let forward = Vec3::new(camera.forward().x, 0.0, camera.forward().z).normalize_or_zero();
+    let right = Vec3::new(camera.right().x, 0.0, camera.right().z).normalize_or_zero();
+    let delta = time.delta_seconds();
+
+    for (mut transform, mut controller, output, mut player) in player_query.iter_mut() {
+        let mut direction = Vec3::ZERO;
+        if actions.pressed(Action::MoveForward) {
+            direction += forward;
+        }
+        if actions.pressed(Action::MoveBackward) {
+            direction -= forward;
+        }
+        if actions.pressed(Action::MoveLeft) {
+            direction -= right;
+        }
+        if actions.pressed(Action::MoveRight) {
+            direction += right;
+        }
+        let direction = direction.normalize_or_zero();
+
+        let mut speed = player.speed;
+        if actions.pressed(Action::Sprint) {
+            speed *= player.sprint_multiplier;
+        }
+
+        let grounded = output.is_some_and(|output| output.grounded);
+        // the output lags a frame, so a jump must not be cancelled by it
+        if grounded && player.vertical_speed <= 0.0 {
+            player.vertical_speed = 0.0;
+            if actions.just_pressed(Action::Jump) {
+                player.vertical_speed = player.jump_speed;
             }
+        } else {
+            player.vertical_speed -= player.gravity * delta;
         }
-        velocity = velocity.normalize_or_zero();
-
-        player_velocity.linvel.x = 0.0;
-        player_velocity.linvel.y = -9.0;
-        player_velocity.linvel.z = 0.0;
-        player_velocity.linvel += velocity * player.speed * time.delta_seconds() * 100.0;
-
-        // lerp rotate player towards direction
-        direction = direction.normalize_or_zero();
-        if any {
-            player_transform.rotation = Quat::from_rotation_y(direction.x.atan2(direction.z))
-                .lerp(old_direction, player.lerp_factor);
+
+        // a small downward push keeps the controller reporting the ground
+        let vertical = if grounded && player.vertical_speed == 0.0 {
+            -0.1
+        } else {
+            player.vertical_speed * delta
+        };
+        controller.translation = Some(direction * speed * delta + Vec3::Y * vertical);
+
+        // turn smoothly toward the movement direction
+        if direction != Vec3::ZERO {
+            let target = Quat::from_rotation_y(direction.x.atan2(direction.z));
+            let factor = 1.0 - (-player.turn_speed * delta).exp();
+            transform.rotation = transform.rotation.slerp(target, factor);
         }
     }
 }
@@ -0,0 +1,700 @@
+use bevy::math::{DVec2, I64Vec2};
+use bevy::prelude::*;
+use bevy::tasks::AsyncComputeTaskPool;
+use bevy::utils::{HashMap, HashSet};
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+use bevy_rapier3d::prelude::*;
+use noise::{NoiseFn, Perlin};
+
+use crate::camera::MainCamera;
+use crate::console::{
+    parse_arg, parse_switch, switch_label, AddConsoleCommand, CommandResult, ConsoleCommand,
+    ConsoleError,
+};
+use crate::generation::chunk::{
+    Chunk, ChunkDescriptor, ChunkSettingsParam, ReplaceTask, CHUNK_WORLD_SCALE, CHUNK_WORLD_SIZE,
+    MAP_SIZE,
+};
+use crate::generation::edits::{TerrainEdits, EDIT_SPACING};
+use crate::generation::quadtree::QuadtreeSettings;
+use crate::input::{Action, Actions};
+use crate::origin::WorldOrigin;
+use crate::ui::Hud;
+
+/// Farthest the brush reaches, the camera far plane.
+const SCULPT_DISTANCE: f32 = 20000.0;
+const MIN_RADIUS: f32 = 2.0;
+const MAX_RADIUS: f32 = 200.0;
+/// Factor the radius grows or shrinks by with `Action::BrushGrow` and
+/// `Action::BrushShrink`.
+const RADIUS_STEP: f32 = 1.25;
+/// Distance, relative to the radius, of the heights the smooth brush averages.
+const SMOOTH_REACH: f64 = 0.2;
+/// World units between the bumps of the noise brush.
+const NOISE_SCALE: f64 = 24.0;
+const NOISE_SEED: u32 = 7;
+/// Steepest slope the erode brush leaves, as height over distance.
+const TALUS: f32 = 0.6;
+
+/// Sculpts the chunks with brushes, aimed with the cursor, or the crosshair
+/// while looking around. Toggled with `Action::ToggleSculpt`. The quadtree
+/// terrain has no chunks to sculpt, so the brush stays off while it is on.
+pub struct SculptPlugin;
+
+impl Plugin for SculptPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SculptBrush>()
+            .register_type::<SculptBrush>()
+            .init_resource::<BrushState>()
+            .add_systems(
+                Update,
+                (
+                    sculpt_hotkeys,
+                    aim_brush,
+                    sculpt_terrain,
+                    rebuild_sculpted_chunks,
+                    draw_brush,
+                    update_sculpt_hud.run_if(resource_exists::<Hud>),
+                )
+                    .chain(),
+            )
+            .add_console_command(
+                "sculpt",
+                ConsoleCommand::new(
+                    "sculpt [on|off|clear|setting value]",
+                    "Shows or changes the sculpting brush, clear throws the edits away",
+                    sculpt_command,
+                )
+                .with_keywords(&[
+                    "on", "off", "clear", "tool", "radius", "strength", "falloff", "raise",
+                    "lower", "smooth", "flatten", "noise", "erode", "constant", "linear", "sharp",
+                ]),
+            );
+    }
+}
+
+/// What a brush does to the terrain under it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum BrushTool {
+    #[default]
+    Raise,
+    Lower,
+    /// Pulls the heights towards the average of their surroundings.
+    Smooth,
+    /// Pulls the heights towards the height where the stroke started.
+    Flatten,
+    /// Adds bumps.
+    Noise,
+    /// Moves material down the slopes steeper than `TALUS`.
+    Erode,
+}
+
+impl BrushTool {
+    const ALL: [BrushTool; 6] = [
+        BrushTool::Raise,
+        BrushTool::Lower,
+        BrushTool::Smooth,
+        BrushTool::Flatten,
+        BrushTool::Noise,
+        BrushTool::Erode,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BrushTool::Raise => "raise",
+            BrushTool::Lower => "lower",
+            BrushTool::Smooth => "smooth",
+            BrushTool::Flatten => "flatten",
+            BrushTool::Noise => "noise",
+            BrushTool::Erode => "erode",
+        }
+    }
+
+    fn next(self) -> BrushTool {
+        let index = BrushTool::ALL.iter().position(|tool| *tool == self);
+        BrushTool::ALL[index.map_or(0, |index| (index + 1) % BrushTool::ALL.len())]
+    }
+}
+
+/// How the brush weakens from its center to its radius.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum Falloff {
+    Constant,
+    Linear,
+    #[default]
+    Smooth,
+    /// Strong at the center only.
+    Sharp,
+}
+
+impl Falloff {
+    const ALL: [Falloff; 4] = [
+        Falloff::Constant,
+        Falloff::Linear,
+        Falloff::Smooth,
+        Falloff::Sharp,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Falloff::Constant => "constant",
+            Falloff::Linear => "linear",
+            Falloff::Smooth => "smooth",
+            Falloff::Sharp => "sharp",
+        }
+    }
+
+    /// Strength of the brush at `distance` from its center, relative to its
+    /// radius.
+    pub fn weight(self, distance: f32) -> f32 {
+        let t = 1.0 - distance.clamp(0.0, 1.0);
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => t,
+            Falloff::Smooth => t * t * (3.0 - 2.0 * t),
+            Falloff::Sharp => t * t * t,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, Reflect)]
+#[reflect(Resource)]
+pub struct SculptBrush {
+    /// Whether `Action::Sculpt` sculpts the terrain.
+    pub enabled: bool,
+    pub tool: BrushTool,
+    /// In world units.
+    pub radius: f32,
+    /// World units the terrain moves by per second, at the center of the
+    /// brush.
+    pub strength: f32,
+    pub falloff: Falloff,
+}
+
+impl Default for SculptBrush {
+    fn default() -> Self {
+        SculptBrush {
+            enabled: false,
+            tool: BrushTool::Raise,
+            radius: 16.0,
+            strength: 8.0,
+            falloff: Falloff::Smooth,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct BrushState {
+    /// Point and normal of the terrain under the brush.
+    target: Option<(Vec3, Vec3)>,
+    /// Absolute height the current stroke started at, for the flatten brush.
+    stroke_height: Option<f32>,
+    /// Coordinates of the chunks to build again with their edits.
+    sculpted: HashSet<I64Vec2>,
+}
+
+fn sculpt_hotkeys(
+    actions: Actions,
+    mut brush: ResMut<SculptBrush>,
+    quadtree: Res<QuadtreeSettings>,
+) {
+    if quadtree.enabled {
+        if brush.enabled {
+            brush.enabled = false;
+        }
+        return;
+    }
+
+    if actions.just_pressed(Action::ToggleSculpt) {
+        brush.enabled = !brush.enabled;
+    }
+    if !brush.enabled {
+        return;
+    }
+
+    if actions.just_pressed(Action::NextBrushTool) {
+        brush.tool = brush.tool.next();
+    }
+    if actions.just_pressed(Action::BrushGrow) {
+        brush.radius = (brush.radius * RADIUS_STEP).min(MAX_RADIUS);
+    }
+    if actions.just_pressed(Action::BrushShrink) {
+        brush.radius = (brush.radius / RADIUS_STEP).max(MIN_RADIUS);
+    }
+}
+
+/// Casts a ray from the cursor to the chunk colliders.
+fn aim_brush(
+    brush: Res<SculptBrush>,
+    mut state: ResMut<BrushState>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    rapier_context: Res<RapierContext>,
+    chunks: Query<(), With<Chunk>>,
+) {
+    state.target = None;
+    if !brush.enabled {
+        return;
+    }
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.get_single(), cameras.get_single())
+    else {
+        return;
+    };
+    let Some(viewport) = camera.logical_viewport_rect() else {
+        return;
+    };
+
+    // the cursor is hidden in the middle of the screen while looking around
+    let position = if window.cursor.grab_mode == CursorGrabMode::None {
+        match window.cursor_position() {
+            Some(cursor) if viewport.contains(cursor) => cursor - viewport.min,
+            _ => return,
+        }
+    } else {
+        viewport.size() / 2.0
+    };
+    let Some(ray) = camera.viewport_to_world(camera_transform, position) else {
+        return;
+    };
+
+    let is_chunk = |entity: Entity| chunks.contains(entity);
+    state.target = rapier_context
+        .cast_ray_and_get_normal(
+            ray.origin,
+            *ray.direction,
+            SCULPT_DISTANCE,
+            true,
+            QueryFilter::only_fixed().predicate(&is_chunk),
+        )
+        .map(|(_, hit)| (hit.point, hit.normal));
+}
+
+fn sculpt_terrain(
+    actions: Actions,
+    brush: Res<SculptBrush>,
+    mut state: ResMut<BrushState>,
+    mut edits: ResMut<TerrainEdits>,
+    chunks: Query<&Chunk>,
+    origin: Res<WorldOrigin>,
+    time: Res<Time>,
+) {
+    if !actions.pressed(Action::Sculpt) {
+        state.stroke_height = None;
+        return;
+    }
+    let Some((point, _)) = state.target else {
+        return;
+    };
+
+    let stroke_height = *state
+        .stroke_height
+        .get_or_insert(origin.to_world(point).y as f32);
+    let center = origin.to_world_xz(point);
+    let heights = TerrainHeights::new(chunks.iter());
+    let step = brush.strength * time.delta_seconds();
+
+    apply_brush(&brush, &mut edits, &heights, center, step, stroke_height);
+    state.sculpted.extend(touched_chunks(center, brush.radius));
+}
+
+/// Regenerates the sculpted chunks at their level of detail. Chunks already
+/// being replaced wait for it, as their tasks may have missed the last edits,
+/// and chunks that aren't loaded get the edits whenever they are.
+fn rebuild_sculpted_chunks(
+    mut commands: Commands,
+    mut state: ResMut<BrushState>,
+    chunks: Query<(Entity, &Chunk, Has<ReplaceTask>)>,
+    settings: ChunkSettingsParam,
+) {
+    if state.sculpted.is_empty() {
+        return;
+    }
+
+    let thread_pool = AsyncComputeTaskPool::get();
+    let mut waiting = HashSet::new();
+
+    for (entity, chunk, replacing) in &chunks {
+        if !state.sculpted.contains(&chunk.coords) {
+            continue;
+        }
+        if replacing {
+            waiting.insert(chunk.coords);
+            continue;
+        }
+
+        let (coords, lod) = (chunk.coords, chunk.lod);
+        let settings = settings.get();
+        let task = thread_pool.spawn(async move { Chunk::new(coords, lod, &settings) });
+
+        commands.entity(entity).insert(ReplaceTask {
+            task,
+            descriptor: ChunkDescriptor { lod, coords },
+        });
+    }
+
+    state.sculpted = waiting;
+}
+
+fn draw_brush(brush: Res<SculptBrush>, state: Res<BrushState>, mut gizmos: Gizmos) {
+    let Some((point, normal)) = state.target else {
+        return;
+    };
+
+    let color = match brush.tool {
+        BrushTool::Lower | BrushTool::Erode => Color::ORANGE_RED,
+        _ => Color::WHITE,
+    };
+    let normal = Direction3d::new(normal).unwrap_or(Direction3d::Y);
+    gizmos.circle(point + *normal * 0.1, normal, brush.radius, color);
+    gizmos.circle(point + *normal * 0.1, normal, brush.radius * 0.05, color);
+}
+
+fn update_sculpt_hud(
+    actions: Actions,
+    brush: Res<SculptBrush>,
+    quadtree: Res<QuadtreeSettings>,
+    mut hud: ResMut<Hud>,
+) {
+    if quadtree.enabled && actions.just_pressed(Action::ToggleSculpt) {
+        hud.set("Brush", "unavailable with the quadtree terrain");
+        return;
+    }
+    if !brush.is_changed() && !quadtree.is_changed() {
+        return;
+    }
+
+    if brush.enabled {
+        hud.set(
+            "Brush",
+            format!(
+                "{}, radius {:.0}, strength {:.1}, {} falloff",
+                brush.tool.name(),
+                brush.radius,
+                brush.strength,
+                brush.falloff.name()
+            ),
+        );
+    } else {
+        hud.remove("Brush");
+    }
+}
+
+/// Heights of the loaded chunks, without their edits.
+struct TerrainHeights<'a> {
+    chunks: HashMap<I64Vec2, &'a Chunk>,
+}
+
+impl<'a> TerrainHeights<'a> {
+    fn new(chunks: impl Iterator<Item = &'a Chunk>) -> Self {
+        TerrainHeights {
+            chunks: chunks.map(|chunk| (chunk.coords, chunk)).collect(),
+        }
+    }
+
+    fn unsculpted(&self, position: DVec2) -> Option<f32> {
+        let coords = (position / CHUNK_WORLD_SIZE as f64).round().as_i64vec2();
+        let chunk = self.chunks.get(&coords)?;
+        Some(chunk.unsculpted_height(position))
+    }
+}
+
+/// Heights of the terrain on the edit samples between `min` and `max`, before
+/// the brush changes any.
+struct HeightGrid {
+    min: I64Vec2,
+    width: i64,
+    heights: Vec<Option<f32>>,
+}
+
+impl HeightGrid {
+    fn new(edits: &TerrainEdits, terrain: &TerrainHeights, min: I64Vec2, max: I64Vec2) -> Self {
+        let mut heights = Vec::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let index = I64Vec2::new(x, y);
+                let position = TerrainEdits::sample_position(index);
+                heights.push(
+                    terrain
+                        .unsculpted(position)
+                        .map(|height| height + edits.offset(index)),
+                );
+            }
+        }
+
+        HeightGrid {
+            min,
+            width: max.x - min.x + 1,
+            heights,
+        }
+    }
+
+    fn get(&self, index: I64Vec2) -> Option<f32> {
+        let local = index - self.min;
+        if local.x < 0 || local.y < 0 || local.x >= self.width {
+            return None;
+        }
+        self.heights
+            .get((local.y * self.width + local.x) as usize)
+            .copied()
+            .flatten()
+    }
+
+    /// Average of the heights `reach` samples away on both axes.
+    fn average(&self, index: I64Vec2, reach: i64) -> Option<f32> {
+        let neighbors = [
+            I64Vec2::new(-reach, 0),
+            I64Vec2::new(reach, 0),
+            I64Vec2::new(0, -reach),
+            I64Vec2::new(0, reach),
+        ];
+        let heights: Vec<f32> = neighbors
+            .iter()
+            .filter_map(|offset| self.get(index + *offset))
+            .collect();
+
+        (!heights.is_empty()).then(|| heights.iter().sum::<f32>() / heights.len() as f32)
+    }
+}
+
+/// Sculpts the edit samples within the brush radius of `center`, moving them
+/// by at most `step` world units.
+fn apply_brush(
+    brush: &SculptBrush,
+    edits: &mut TerrainEdits,
+    terrain: &TerrainHeights,
+    center: DVec2,
+    step: f32,
+    stroke_height: f32,
+) {
+    let radius = brush.radius as f64;
+    let min = TerrainEdits::sample_index(center - radius);
+    let max = TerrainEdits::sample_index(center + radius) + 1;
+
+    let reach = match brush.tool {
+        BrushTool::Smooth => ((radius * SMOOTH_REACH / EDIT_SPACING).round() as i64).max(1),
+        _ => 1,
+    };
+    let grid = HeightGrid::new(edits, terrain, min - reach, max + reach);
+    let perlin = Perlin::new(NOISE_SEED);
+
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            let index = I64Vec2::new(x, y);
+            let position = TerrainEdits::sample_position(index);
+            let distance = ((position - center).length() / radius) as f32;
+            if distance >= 1.0 {
+                continue;
+            }
+            let amount = step * brush.falloff.weight(distance);
+
+            // towards a target height, without overshooting it
+            let pull = |target: Option<f32>| {
+                let height = grid.get(index)?;
+                Some((target? - height).clamp(-amount, amount))
+            };
+
+            let change = match brush.tool {
+                BrushTool::Raise => amount,
+                BrushTool::Lower => -amount,
+                BrushTool::Noise => {
+                    let noise = perlin.get((position / NOISE_SCALE).to_array());
+                    amount * noise as f32
+                }
+                BrushTool::Smooth => pull(grid.average(index, reach)).unwrap_or(0.0),
+                BrushTool::Flatten => pull(Some(stroke_height)).unwrap_or(0.0),
+                BrushTool::Erode => {
+                    erode(edits, &grid, index, amount);
+                    0.0
+                }
+            };
+            if change != 0.0 {
+                *edits.offset_mut(index) += change;
+            }
+        }
+    }
+}
+
+/// Moves up to `amount` of height from the sample at `index` to its lowest
+/// neighbor, when the slope between them is steeper than `TALUS`.
+fn erode(edits: &mut TerrainEdits, grid: &HeightGrid, index: I64Vec2, amount: f32) {
+    let Some(height) = grid.get(index) else {
+        return;
+    };
+
+    let lowest = [I64Vec2::NEG_X, I64Vec2::X, I64Vec2::NEG_Y, I64Vec2::Y]
+        .into_iter()
+        .filter_map(|offset| Some((index + offset, grid.get(index + offset)?)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    let Some((neighbor, neighbor_height)) = lowest else {
+        return;
+    };
+
+    // half the excess evens the slope out
+    let excess = height - neighbor_height - TALUS * EDIT_SPACING as f32;
+    if excess <= 0.0 {
+        return;
+    }
+    let moved = (excess / 2.0).min(amount);
+    *edits.offset_mut(index) -= moved;
+    *edits.offset_mut(neighbor) += moved;
+}
+
+/// Chunks whose mesh comes within `radius` of `center`, or one edit sample
+/// further, where the erode brush reaches.
+fn touched_chunks(center: DVec2, radius: f32) -> Vec<I64Vec2> {
+    // meshes reach half a chunk from their center
+    let half_mesh = MAP_SIZE * CHUNK_WORLD_SCALE as f64 / 2.0;
+    let reach = radius as f64 + EDIT_SPACING + half_mesh;
+    let size = CHUNK_WORLD_SIZE as f64;
+    let min = ((center - reach) / size).ceil().as_i64vec2();
+    let max = ((center + reach) / size).floor().as_i64vec2();
+
+    let mut chunks = Vec::new();
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            chunks.push(I64Vec2::new(x, y));
+        }
+    }
+    chunks
+}
+
+fn sculpt_command(world: &mut World, args: &[&str]) -> CommandResult {
+    match args {
+        [] => {
+            let brush = world.resource::<SculptBrush>();
+            let edited = world.resource::<TerrainEdits>().edited_chunks().count();
+            Ok(format!(
+                "sculpt {}, tool {}, radius {}, strength {}, falloff {}\n{edited} chunks sculpted",
+                switch_label(brush.enabled),
+                brush.tool.name(),
+                brush.radius,
+                brush.strength,
+                brush.falloff.name(),
+            ))
+        }
+        ["clear"] => {
+            let mut edits = world.resource_mut::<TerrainEdits>();
+            let edited: Vec<I64Vec2> = edits.edited_chunks().collect();
+            edits.clear();
+            let count = edited.len();
+            world.resource_mut::<BrushState>().sculpted.extend(edited);
+            Ok(format!("cleared the edits of {count} chunks"))
+        }
+        [switch] => {
+            let enabled = parse_switch(switch)?;
+            if enabled && world.resource::<QuadtreeSettings>().enabled {
+                return Err(ConsoleError::Failed(
+                    "the quadtree terrain can't be sculpted, turn it off with `lod quadtree off`"
+                        .to_string(),
+                ));
+            }
+            world.resource_mut::<SculptBrush>().enabled = enabled;
+            Ok(format!("sculpt {}", switch_label(enabled)))
+        }
+        [setting, value] => {
+            let mut brush = world.resource_mut::<SculptBrush>();
+            match *setting {
+                "tool" => {
+                    brush.tool = BrushTool::ALL
+                        .into_iter()
+                        .find(|tool| tool.name() == *value)
+                        .ok_or_else(|| {
+                            ConsoleError::InvalidArgument(value.to_string(), "brush tool")
+                        })?;
+                }
+                "radius" => {
+                    let radius: f32 = parse_arg(value)?;
+                    if !(MIN_RADIUS..=MAX_RADIUS).contains(&radius) {
+                        return Err(ConsoleError::Failed(format!(
+                            "the radius is between {MIN_RADIUS} and {MAX_RADIUS}"
+                        )));
+                    }
+                    brush.radius = radius;
+                }
+                "strength" => {
+                    let strength: f32 = parse_arg(value)?;
+                    if strength <= 0.0 {
+                        return Err(ConsoleError::Failed(
+                            "the strength must be positive".to_string(),
+                        ));
+                    }
+                    brush.strength = strength;
+                }
+                "falloff" => {
+                    brush.falloff = Falloff::ALL
+                        .into_iter()
+                        .find(|falloff| falloff.name() == *value)
+                        .ok_or_else(|| {
+                            ConsoleError::InvalidArgument(value.to_string(), "falloff")
+                        })?;
+                }
+                _ => return Err(ConsoleError::Usage),
+            }
+            Ok(format!("{setting} set to {value}"))
+        }
+        _ => Err(ConsoleError::Usage),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noise::utils::NoiseMap;
+
+    use super::*;
+    use crate::generation::chunk::HEIGHT_INTENSITY;
+
+    fn stroke(edits: &mut TerrainEdits, tool: BrushTool, center: DVec2) {
+        let brush = SculptBrush {
+            enabled: true,
+            tool,
+            falloff: Falloff::Linear,
+            ..Default::default()
+        };
+        let terrain = TerrainHeights::new(std::iter::empty());
+        apply_brush(&brush, edits, &terrain, center, 4.0, 0.0);
+    }
+
+    #[test]
+    fn strokes_only_move_the_terrain_inside_the_radius() {
+        let center = DVec2::new(150.0, -70.0);
+        let radius = SculptBrush::default().radius as f64;
+        let mut edits = TerrainEdits::default();
+        stroke(&mut edits, BrushTool::Raise, center);
+
+        // re-sample a 64 world units square around the brush, as a chunk would
+        let (resolution, size) = (64, 64.0);
+        let min = center - size / 2.0;
+        let scale = CHUNK_WORLD_SCALE as f64;
+        let mut noise_map = NoiseMap::new(resolution + 1, resolution + 1);
+        edits.apply(
+            &mut noise_map,
+            size / scale,
+            [min.x * 2.0 / scale, min.y * 2.0 / scale],
+        );
+
+        let to_world = HEIGHT_INTENSITY * CHUNK_WORLD_SCALE;
+        for d in 0..=resolution {
+            for w in 0..=resolution {
+                let position = min + DVec2::new(w as f64, d as f64) * size / resolution as f64;
+                let distance = (position - center).length();
+                let height = noise_map.get_value(w, d) as f32 * to_world;
+
+                if distance < radius - 2.0 * EDIT_SPACING {
+                    assert!(height > 0.0, "not raised at {position}");
+                } else if distance > radius + 2.0 * EDIT_SPACING {
+                    assert_eq!(height, 0.0, "raised at {position}");
+                }
+                assert!((height - edits.height_offset(position)).abs() < 1e-4);
+            }
+        }
+
+        // the same stroke lowering undoes it
+        stroke(&mut edits, BrushTool::Lower, center);
+        for d in 0..=resolution {
+            for w in 0..=resolution {
+                let position = min + DVec2::new(w as f64, d as f64) * size / resolution as f64;
+                assert!(edits.height_offset(position).abs() < 1e-4);
+            }
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Read at startup, unless another file is given with `--config`.
+pub const SETTINGS_PATH: &str = "config/settings.ron";
+
+const USAGE: &str = "\
+usage: terrain_generation [options]
+
+  --config <path>          settings file (default: config/settings.ron)
+  --width <pixels>         window width
+  --height <pixels>        window height
+  --fullscreen             borderless fullscreen window
+  --speed <units/s>        flycam speed
+  --sensitivity <value>    flycam mouse sensitivity
+  --render-distance <n>    chunks loaded around the camera
+  --seed <n>               planet seed
+  --msaa <1|2|4|8>         multisample anti-aliasing samples
+  --enable <plugin>        enable an optional plugin
+  --disable <plugin>       disable an optional plugin
+  --editor                 open the terrain editor, same as --enable editor
+
+optional plugins: atmosphere, water, world, player, wireframe, fps_counter, hud, perf_overlay, console, sculpt, map, editor";
+
+/// Startup configuration, from the settings file then the command line. Read
+/// once into the resources and plugins of the app.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window: WindowSettings,
+    pub camera: CameraSettings,
+    pub world: WorldSettings,
+    pub graphics: GraphicsSettings,
+    pub plugins: PluginSettings,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub title: String,
+    pub width: f32,
+    pub height: f32,
+    pub fullscreen: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            title: "Terrain Generation".to_string(),
+            width: 1920.0,
+            height: 1080.0,
+            fullscreen: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    /// Flycam speed, in world units per second.
+    pub speed: f32,
+    pub sensitivity: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            speed: 100.0,
+            sensitivity: 0.00010,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldSettings {
+    /// Radius, in chunks, of the terrain loaded around the camera.
+    pub render_distance: i32,
+    pub seed: u32,
+}
+
+impl Default for WorldSettings {
+    fn default() -> Self {
+        WorldSettings {
+            render_distance: crate::generation::chunk::RENDER_DISTANCE,
+            seed: crate::generation::noise::NoiseSettings::default().seed,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    /// Samples per pixel: 1 turns anti-aliasing off, else 2, 4 or 8.
+    pub msaa: u32,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        GraphicsSettings { msaa: 4 }
+    }
+}
+
+impl GraphicsSettings {
+    pub fn msaa(&self) -> Msaa {
+        match self.msaa {
+            1 => Msaa::Off,
+            2 => Msaa::Sample2,
+            8 => Msaa::Sample8,
+            _ => Msaa::Sample4,
+        }
+    }
+}
+
+/// Plugins the game runs without.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginSettings {
+    pub atmosphere: bool,
+    pub water: bool,
+    pub world: bool,
+    pub player: bool,
+    pub wireframe: bool,
+    pub fps_counter: bool,
+    pub hud: bool,
+    /// Chunk generation diagnostics, toggled with `Action::TogglePerfOverlay`.
+    pub perf_overlay: bool,
+    /// Developer console, toggled with `Action::ToggleConsole`.
+    pub console: bool,
+    /// Terrain sculpting brushes, toggled with `Action::ToggleSculpt`.
+    pub sculpt: bool,
+    /// Minimap, and the world map toggled with `Action::ToggleMap`.
+    pub map: bool,
+    /// Docks the game view in the terrain editor.
+    pub editor: bool,
+}
+
+impl Default for PluginSettings {
+    fn default() -> Self {
+        PluginSettings {
+            atmosphere: true,
+            water: true,
+            world: true,
+            player: true,
+            wireframe: true,
+            fps_counter: true,
+            hud: true,
+            perf_overlay: true,
+            console: true,
+            sculpt: true,
+            map: true,
+            editor: false,
+        }
+    }
+}
+
+impl PluginSettings {
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "atmosphere" => Some(&mut self.atmosphere),
+            "water" => Some(&mut self.water),
+            "world" => Some(&mut self.world),
+            "player" => Some(&mut self.player),
+            "wireframe" => Some(&mut self.wireframe),
+            "fps_counter" => Some(&mut self.fps_counter),
+            "hud" => Some(&mut self.hud),
+            "perf_overlay" => Some(&mut self.perf_overlay),
+            "console" => Some(&mut self.console),
+            "sculpt" => Some(&mut self.sculpt),
+            "map" => Some(&mut self.map),
+            "editor" => Some(&mut self.editor),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("could not read {}: {source}", .path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("could not parse {}: {source}", .path.display())]
+    Parse {
+        path: PathBuf,
+        source: ron::error::SpannedError,
+    },
+    #[error("unknown argument `{0}`\n\n{USAGE}")]
+    UnknownArgument(String),
+    #[error("missing value after `{0}`")]
+    MissingValue(String),
+    #[error("`{value}` is not a valid value for `{argument}`")]
+    InvalidArgument { argument: String, value: String },
+    #[error("unknown plugin `{0}`, expected one of: atmosphere, water, world, player, wireframe, fps_counter, hud, perf_overlay, console, sculpt, map, editor")]
+    UnknownPlugin(String),
+    #[error("invalid setting `{setting}`: {reason}")]
+    Invalid {
+        setting: &'static str,
+        reason: &'static str,
+    },
+    #[error("{USAGE}")]
+    Help,
+}
+
+impl Settings {
+    /// Reads the settings file, then applies the command-line overrides. A
+    /// missing default settings file is not an error.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Settings, SettingsError> {
+        let args: Vec<String> = args.into_iter().collect();
+
+        let config = args
+            .iter()
+            .position(|arg| arg == "--config")
+            .map(|index| {
+                args.get(index + 1)
+                    .map(PathBuf::from)
+                    .ok_or_else(|| SettingsError::MissingValue("--config".to_string()))
+            })
+            .transpose()?;
+
+        let mut settings = match &config {
+            Some(path) => Settings::load(path)?,
+            None if Path::new(SETTINGS_PATH).exists() => Settings::load(SETTINGS_PATH)?,
+            None => Settings::default(),
+        };
+
+        settings.apply_args(&args)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Settings, SettingsError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|source| SettingsError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        ron::de::from_str(&text).map_err(|source| SettingsError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn apply_args(&mut self, args: &[String]) -> Result<(), SettingsError> {
+        let mut args = args.iter();
+
+        while let Some(argument) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| SettingsError::MissingValue(argument.clone()))
+            };
+
+            match argument.as_str() {
+                "--config" => {
+                    value()?;
+                }
+                "--width" => self.window.width = parse(argument, value()?)?,
+                "--height" => self.window.height = parse(argument, value()?)?,
+                "--fullscreen" => self.window.fullscreen = true,
+                "--editor" => self.plugins.editor = true,
+                "--speed" => self.camera.speed = parse(argument, value()?)?,
+                "--sensitivity" => self.camera.sensitivity = parse(argument, value()?)?,
+                "--render-distance" => self.world.render_distance = parse(argument, value()?)?,
+                "--seed" => self.world.seed = parse(argument, value()?)?,
+                "--msaa" => self.graphics.msaa = parse(argument, value()?)?,
+                "--enable" | "--disable" => {
+                    let name = value()?;
+                    let flag = self
+                        .plugins
+                        .flag(name)
+                        .ok_or_else(|| SettingsError::UnknownPlugin(name.clone()))?;
+                    *flag = argument == "--enable";
+                }
+                "--help" | "-h" => return Err(SettingsError::Help),
+                _ => return Err(SettingsError::UnknownArgument(argument.clone())),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let invalid = |setting, reason| Err(SettingsError::Invalid { setting, reason });
+
+        if !at_least(self.window.width, 1.0) || !at_least(self.window.height, 1.0) {
+            return invalid("window", "width and height must be at least 1 pixel");
+        }
+        if !positive(self.camera.speed) {
+            return invalid("camera.speed", "must be positive");
+        }
+        if !positive(self.camera.sensitivity) {
+            return invalid("camera.sensitivity", "must be positive");
+        }
+        if !(1..=32).contains(&self.world.render_distance) {
+            return invalid("world.render_distance", "must be between 1 and 32 chunks");
+        }
+        if ![1, 2, 4, 8].contains(&self.graphics.msaa) {
+            return invalid("graphics.msaa", "must be 1, 2, 4 or 8 samples");
+        }
+
+        Ok(())
+    }
+}
+
+/// False for NaN too.
+fn at_least(value: f32, min: f32) -> bool {
+    value >= min
+}
+
+fn positive(value: f32) -> bool {
+    value > 0.0
+}
+
+fn parse<T: std::str::FromStr>(argument: &str, value: &str) -> Result<T, SettingsError> {
+    value.parse().map_err(|_| SettingsError::InvalidArgument {
+        argument: argument.to_string(),
+        value: value.to_string(),
+    })
+}
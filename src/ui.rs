@@ -1,4 +1,12 @@
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::generation::chunk::{
+    get_player_chunk, Chunk, ChunkLoader, TerrainCollider, CHUNK_WORLD_SCALE, HEIGHT_INTENSITY,
+};
+use crate::generation::noise::{NoiseSettings, SEA_LEVEL};
+use crate::input::{Action, Actions};
+use crate::origin::WorldOrigin;
 
 use bevy::diagnostic::DiagnosticsStore;
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
@@ -127,11 +135,203 @@ fn fps_text_update_system(
 }
 
 /// Toggle the FPS counter when pressing F12
-fn fps_counter_showhide(
-    mut q: Query<&mut Visibility, With<FpsRoot>>,
-    kbd: Res<ButtonInput<KeyCode>>,
+fn fps_counter_showhide(mut q: Query<&mut Visibility, With<FpsRoot>>, actions: Actions) {
+    if actions.just_pressed(Action::ToggleFps) {
+        let mut vis = q.single_mut();
+        *vis = match *vis {
+            Visibility::Hidden => Visibility::Visible,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Hud>()
+            .add_systems(Startup, setup_hud)
+            .add_systems(
+                Update,
+                (
+                    (terrain_hud_system, hud_text_update_system).chain(),
+                    hud_showhide,
+                ),
+            );
+    }
+}
+
+/// Height from which the ground under the camera is looked for.
+const GROUND_RAY_HEIGHT: f32 = 10000.0;
+
+/// Lines of the HUD panel, in the order they were first set. Any system can
+/// show its own with `Hud::set`.
+#[derive(Resource, Clone, Default, PartialEq)]
+pub struct Hud {
+    entries: Vec<(&'static str, String)>,
+}
+
+impl Hud {
+    pub fn set(&mut self, label: &'static str, value: impl Into<String>) {
+        let value = value.into();
+        match self.entries.iter_mut().find(|(entry, _)| *entry == label) {
+            Some((_, current)) => *current = value,
+            None => self.entries.push((label, value)),
+        }
+    }
+
+    pub fn remove(&mut self, label: &str) {
+        self.entries.retain(|(entry, _)| *entry != label);
+    }
+}
+
+/// Marker to find the container entity so we can show/hide the HUD
+#[derive(Component)]
+struct HudRoot;
+
+/// Marker to find the text entity so we can update it
+#[derive(Component)]
+struct HudText;
+
+fn setup_hud(mut commands: Commands) {
+    // same look as the FPS counter, in the top-left corner
+    commands
+        .spawn((
+            HudRoot,
+            NodeBundle {
+                background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+                z_index: ZIndex::Global(i32::MAX),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Percent(1.),
+                    top: Val::Percent(1.),
+                    bottom: Val::Auto,
+                    right: Val::Auto,
+                    padding: UiRect::all(Val::Px(4.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((HudText, TextBundle::default()));
+        });
+}
+
+/// Where the camera is, and what is under it.
+fn terrain_hud_system(
+    mut hud: ResMut<Hud>,
+    rapier_context: Res<RapierContext>,
+    origin: Res<WorldOrigin>,
+    noise: Res<NoiseSettings>,
+    loaders: Query<&Transform, With<ChunkLoader>>,
+    chunks: Query<(&Chunk, &Transform), Without<ChunkLoader>>,
+    colliders: Query<(), TerrainCollider>,
 ) {
-    if kbd.just_pressed(KeyCode::F12) {
+    let Ok(camera) = loaders.get_single() else {
+        return;
+    };
+    // only touched when a value differs, or the text is rebuilt every frame
+    let mut next = hud.clone();
+
+    let position = origin.to_world(camera.translation);
+    next.set(
+        "Position",
+        format!("{:.1} {:.1} {:.1}", position.x, position.y, position.z),
+    );
+
+    let coords = get_player_chunk(camera.translation, &origin);
+    let chunk = chunks.iter().find(|(chunk, _)| chunk.coords == coords);
+    next.set("Chunk", format!("{} {}", coords.x, coords.y));
+    next.set(
+        "LOD",
+        chunk.map_or("not loaded".to_string(), |(chunk, _)| chunk.lod.to_string()),
+    );
+
+    let ray_origin = Vec3::new(
+        camera.translation.x,
+        GROUND_RAY_HEIGHT,
+        camera.translation.z,
+    );
+    match rapier_context.cast_ray_and_get_normal(
+        ray_origin,
+        Vec3::NEG_Y,
+        2.0 * GROUND_RAY_HEIGHT,
+        true,
+        // the ground, not the props standing on it
+        QueryFilter::only_fixed().predicate(&|entity| colliders.contains(entity)),
+    ) {
+        Some((_, hit)) => {
+            let ground = hit.point.y;
+            let slope = hit.normal.angle_between(Vec3::Y).to_degrees();
+            next.set("Ground", format!("{ground:.1}"));
+            next.set("Altitude", format!("{:.1}", camera.translation.y - ground));
+            next.set("Slope", format!("{slope:.0} deg"));
+
+            // chunks know their biomes, other terrain is only classed by shape
+            let terrain = match chunk {
+                Some((chunk, transform)) => {
+                    let local = (hit.point - transform.translation).xz() / CHUNK_WORLD_SCALE;
+                    format!("{:?}", chunk.biome_at(local))
+                }
+                None => terrain_class(ground, slope).to_string(),
+            };
+            next.set("Terrain", terrain);
+        }
+        None => {
+            for label in ["Ground", "Altitude", "Slope", "Terrain"] {
+                next.set(label, "-");
+            }
+        }
+    }
+
+    next.set("Seed", noise.seed.to_string());
+    hud.set_if_neq(next);
+}
+
+fn terrain_class(height: f32, slope: f32) -> &'static str {
+    if height < SEA_LEVEL as f32 * HEIGHT_INTENSITY * CHUNK_WORLD_SCALE {
+        "Sea floor"
+    } else if slope > 45.0 {
+        "Cliff"
+    } else {
+        "Land"
+    }
+}
+
+fn hud_text_update_system(hud: Res<Hud>, mut query: Query<&mut Text, With<HudText>>) {
+    if !hud.is_changed() {
+        return;
+    }
+
+    let label_style = TextStyle {
+        font_size: 16.0,
+        color: Color::GRAY,
+        ..default()
+    };
+    let value_style = TextStyle {
+        font_size: 16.0,
+        color: Color::WHITE,
+        ..default()
+    };
+
+    for mut text in &mut query {
+        text.sections.clear();
+        for (index, (label, value)) in hud.entries.iter().enumerate() {
+            let line_break = if index == 0 { "" } else { "\n" };
+            text.sections.push(TextSection::new(
+                format!("{line_break}{label}: "),
+                label_style.clone(),
+            ));
+            text.sections
+                .push(TextSection::new(value.clone(), value_style.clone()));
+        }
+    }
+}
+
+/// Toggle the HUD when pressing F9
+fn hud_showhide(mut q: Query<&mut Visibility, With<HudRoot>>, actions: Actions) {
+    if actions.just_pressed(Action::ToggleHud) {
         let mut vis = q.single_mut();
         *vis = match *vis {
             Visibility::Hidden => Visibility::Visible,
@@ -0,0 +1,161 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::generation::biome::BiomeTable;
+
+use super::water_height;
+
+/// Makes a dynamic rapier body float on the water. The body's `ExternalForce`
+/// and `Damping` are driven by the water and get overwritten every frame.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component)]
+pub struct Buoyancy {
+    /// Mass of a unit of water volume. Rapier colliders default to a density
+    /// of 1.0, so lighter bodies float and heavier ones sink.
+    pub water_density: f32,
+    /// Linear damping once fully submerged.
+    pub linear_drag: f32,
+    /// Angular damping once fully submerged.
+    pub angular_drag: f32,
+}
+
+impl Default for Buoyancy {
+    fn default() -> Self {
+        Buoyancy {
+            water_density: 1.0,
+            linear_drag: 1.5,
+            angular_drag: 1.0,
+        }
+    }
+}
+
+pub fn insert_buoyancy_forces(
+    mut commands: Commands,
+    bodies: Query<(Entity, Has<ExternalForce>, Has<Damping>), Added<Buoyancy>>,
+) {
+    for (entity, has_force, has_damping) in bodies.iter() {
+        if !has_force {
+            commands.entity(entity).insert(ExternalForce::default());
+        }
+        if !has_damping {
+            commands.entity(entity).insert(Damping::default());
+        }
+    }
+}
+
+/// Pushes the bodies up by the weight of the water they displace, and slows
+/// them down as much as they are submerged.
+pub fn apply_buoyancy(
+    table: Res<BiomeTable>,
+    rapier_config: Res<RapierConfiguration>,
+    mut bodies: Query<(
+        &Buoyancy,
+        &RigidBody,
+        &Collider,
+        &GlobalTransform,
+        &mut ExternalForce,
+        &mut Damping,
+    )>,
+) {
+    let water = water_height(&table);
+
+    for (buoyancy, body, collider, transform, mut force, mut damping) in &mut bodies {
+        if *body != RigidBody::Dynamic {
+            continue;
+        }
+
+        let (submerged, volume) = submerged_volume(collider, transform.translation(), water);
+        let fraction = if volume > 0.0 {
+            submerged / volume
+        } else {
+            0.0
+        };
+
+        force.force = -rapier_config.gravity * buoyancy.water_density * submerged;
+        force.torque = Vec3::ZERO;
+        damping.linear_damping = buoyancy.linear_drag * fraction;
+        damping.angular_damping = buoyancy.angular_drag * fraction;
+    }
+}
+
+/// Volume of the collider under `water`, and its whole volume. Balls are
+/// exact, other shapes are approximated by their unrotated bounding box.
+pub fn submerged_volume(collider: &Collider, center: Vec3, water: f32) -> (f32, f32) {
+    if let Some(ball) = collider.as_ball() {
+        let radius = ball.radius();
+        // spherical cap of height `depth`
+        let depth = (water - (center.y - radius)).clamp(0.0, 2.0 * radius);
+        let submerged = PI * depth * depth * (3.0 * radius - depth) / 3.0;
+
+        return (submerged, 4.0 / 3.0 * PI * radius.powi(3));
+    }
+
+    let half_extents: Vec3 = collider.raw.compute_local_aabb().half_extents().into();
+    let depth = (water - (center.y - half_extents.y)).clamp(0.0, 2.0 * half_extents.y);
+    let area = 4.0 * half_extents.x * half_extents.z;
+
+    (area * depth, area * 2.0 * half_extents.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::scene::SceneSpawner;
+
+    use super::*;
+
+    #[test]
+    fn light_ball_floats_at_the_surface() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            RapierPhysicsPlugin::<NoUserData>::default(),
+        ))
+        // read by the async collider systems of rapier
+        .init_resource::<Assets<Mesh>>()
+        .init_resource::<SceneSpawner>()
+        .insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed {
+                dt: 1.0 / 60.0,
+                substeps: 1,
+            },
+            ..default()
+        })
+        .init_resource::<BiomeTable>()
+        .add_systems(
+            Update,
+            (insert_buoyancy_forces, apply_deferred, apply_buoyancy).chain(),
+        );
+
+        let water = water_height(&BiomeTable::default());
+        let ball = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.0, water + 5.0, 0.0)),
+                RigidBody::Dynamic,
+                Collider::ball(1.0),
+                ColliderMassProperties::Density(0.5),
+                Velocity::default(),
+                Buoyancy::default(),
+            ))
+            .id();
+
+        for _ in 0..1200 {
+            app.update();
+        }
+
+        // half as dense as the water, so half submerged
+        let height = app.world.get::<Transform>(ball).unwrap().translation.y;
+        let velocity = app.world.get::<Velocity>(ball).unwrap().linvel;
+        assert!(
+            (height - water).abs() < 0.1,
+            "the ball rests at {height}, the water is at {water}"
+        );
+        assert!(
+            velocity.length() < 0.05,
+            "the ball still moves at {velocity}"
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+
+use crate::generation::biome::BiomeTable;
+use crate::generation::chunk::{Chunk, CHUNK_WORLD_SCALE};
+
+pub mod buoyancy;
+pub mod surface;
+
+use self::buoyancy::*;
+use self::surface::*;
+
+pub struct WaterPlugin;
+
+impl Plugin for WaterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<WaterMaterial>::default());
+        init_water_material(&mut app.world);
+        app.register_type::<WaterSettings>()
+            .register_type::<Buoyancy>()
+            .add_systems(Update, (spawn_chunk_water, update_water_material))
+            .add_systems(
+                Update,
+                (insert_buoyancy_forces, apply_deferred, apply_buoyancy).chain(),
+            );
+    }
+}
+
+/// Height of the water surface in world units. It follows the sea level of the
+/// biome table, so the water covers exactly the ocean vertices.
+pub fn water_height(table: &BiomeTable) -> f32 {
+    table.sea_level * CHUNK_WORLD_SCALE
+}
+
+#[derive(Resource, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct WaterSettings {
+    /// Quads along each side of the water surface of a chunk.
+    pub subdivisions: usize,
+    pub roughness: f32,
+    pub material: WaterMaterialSettings,
+}
+
+impl Default for WaterSettings {
+    fn default() -> Self {
+        WaterSettings {
+            subdivisions: 32,
+            roughness: 0.08,
+            material: WaterMaterialSettings::default(),
+        }
+    }
+}
+
+impl WaterSettings {
+    fn apply(&self, material: &mut WaterMaterial) {
+        material.base.alpha_mode = AlphaMode::Blend;
+        material.base.perceptual_roughness = self.roughness;
+        material.base.reflectance = 0.6;
+        material.extension.settings = self.material;
+    }
+}
+
+/// Material shared by every water surface.
+#[derive(Resource)]
+pub struct WaterMaterialHandle(pub Handle<WaterMaterial>);
+
+fn init_water_material(world: &mut World) {
+    let settings = WaterSettings::default();
+
+    let mut material = WaterMaterial {
+        base: StandardMaterial::default(),
+        extension: WaterExtension {
+            settings: settings.material,
+        },
+    };
+    settings.apply(&mut material);
+    let handle = world.resource_mut::<Assets<WaterMaterial>>().add(material);
+
+    world.insert_resource(settings);
+    world.insert_resource(WaterMaterialHandle(handle));
+}
+
+/// Covers the new chunks that dip under the sea level with a water surface.
+fn spawn_chunk_water(
+    mut commands: Commands,
+    chunks: Query<(Entity, &Chunk), Added<Chunk>>,
+    table: Res<BiomeTable>,
+    settings: Res<WaterSettings>,
+    material: Res<WaterMaterialHandle>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (entity, chunk) in chunks.iter() {
+        let lowest = chunk.heights.iter().copied().fold(f32::INFINITY, f32::min);
+        if lowest >= table.sea_level {
+            continue;
+        }
+
+        let mesh = create_water_mesh(chunk, table.sea_level, settings.subdivisions);
+        let water = commands
+            .spawn((
+                WaterSurface,
+                MaterialMeshBundle {
+                    mesh: meshes.add(mesh),
+                    material: material.0.clone(),
+                    ..default()
+                },
+                NotShadowCaster,
+            ))
+            .id();
+        commands.entity(entity).add_child(water);
+    }
+}
+
+fn update_water_material(
+    settings: Res<WaterSettings>,
+    handle: Res<WaterMaterialHandle>,
+    mut materials: ResMut<Assets<WaterMaterial>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    if let Some(material) = materials.get_mut(&handle.0) {
+        settings.apply(material);
+    }
+}
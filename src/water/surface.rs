@@ -0,0 +1,144 @@
+use bevy::pbr::{
+    ExtendedMaterial, MaterialExtension, MaterialExtensionKey, MaterialExtensionPipeline,
+};
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, MeshVertexAttribute, MeshVertexBufferLayout};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{
+    AsBindGroup, PrimitiveTopology, RenderPipelineDescriptor, ShaderRef,
+    SpecializedMeshPipelineError, VertexFormat,
+};
+
+use crate::generation::chunk::{Chunk, CHUNK_WORLD_SCALE, CHUNK_WORLD_SIZE};
+
+/// Depth of the water under every vertex of the surface, in world units.
+pub const ATTRIBUTE_WATER_DEPTH: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_WaterDepth", 988540920, VertexFormat::Float32);
+
+const WATER_SHADER: &str = "shaders/water.wgsl";
+
+pub type WaterMaterial = ExtendedMaterial<StandardMaterial, WaterExtension>;
+
+pub use self::uniform::WaterMaterialSettings;
+
+// same as `TerrainMaterialSettings`: the field checks of the derive are
+// reported as dead code
+#[allow(dead_code)]
+mod uniform {
+    use bevy::prelude::*;
+    use bevy::render::render_resource::ShaderType;
+
+    /// Colors are linear RGBA, the alpha of `foam_color` scales the foam.
+    #[derive(Clone, Copy, Debug, ShaderType, Reflect)]
+    pub struct WaterMaterialSettings {
+        pub shallow_color: Vec4,
+        pub deep_color: Vec4,
+        pub foam_color: Vec4,
+        /// World units of water over which the color turns deep.
+        pub depth_scale: f32,
+        /// Depth under which the shore foams and the waves calm down.
+        pub foam_depth: f32,
+        pub wave_amplitude: f32,
+        pub wave_length: f32,
+        pub wave_speed: f32,
+    }
+}
+
+impl Default for WaterMaterialSettings {
+    fn default() -> Self {
+        WaterMaterialSettings {
+            shallow_color: Vec4::new(0.1, 0.45, 0.5, 0.45),
+            deep_color: Vec4::new(0.01, 0.06, 0.18, 0.92),
+            foam_color: Vec4::new(0.9, 0.95, 1.0, 0.85),
+            depth_scale: 12.0,
+            foam_depth: 1.5,
+            wave_amplitude: 0.25,
+            wave_length: 14.0,
+            wave_speed: 2.0,
+        }
+    }
+}
+
+/// Tints the water by depth, animates waves and adds foam along the shore.
+#[derive(Asset, AsBindGroup, Clone, Debug, Reflect)]
+pub struct WaterExtension {
+    #[uniform(100)]
+    pub settings: WaterMaterialSettings,
+}
+
+impl MaterialExtension for WaterExtension {
+    fn vertex_shader() -> ShaderRef {
+        WATER_SHADER.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        WATER_SHADER.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if descriptor.label.as_deref() == Some("prepass_pipeline") {
+            return Ok(());
+        }
+
+        descriptor.vertex.buffers = vec![layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            ATTRIBUTE_WATER_DEPTH.at_shader_location(8),
+        ])?];
+
+        Ok(())
+    }
+}
+
+/// Marks the water surface spawned as a child of a chunk.
+#[derive(Component)]
+pub struct WaterSurface;
+
+/// Flat grid at `level` over the part of `chunk` it does not share with its
+/// neighbors, in chunk mesh units so it can be parented to the chunk.
+pub fn create_water_mesh(chunk: &Chunk, level: f32, subdivisions: usize) -> Mesh {
+    let size = CHUNK_WORLD_SIZE / CHUNK_WORLD_SCALE;
+    let count = (subdivisions + 1) * (subdivisions + 1);
+
+    let mut positions: Vec<[f32; 3]> = Vec::with_capacity(count);
+    let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(count);
+    let mut depths: Vec<f32> = Vec::with_capacity(count);
+
+    for d in 0..=subdivisions {
+        for w in 0..=subdivisions {
+            let uv = Vec2::new(w as f32, d as f32) / subdivisions as f32;
+            let local = (uv - 0.5) * size;
+
+            positions.push([local.x, level, local.y]);
+            uvs.push(uv.to_array());
+            depths.push((level - chunk.height_at(local)) * CHUNK_WORLD_SCALE);
+        }
+    }
+
+    let row = subdivisions as u32 + 1;
+    let mut triangles: Vec<u32> = Vec::with_capacity(subdivisions * subdivisions * 6);
+    for d in 0..subdivisions as u32 {
+        for w in 0..subdivisions as u32 {
+            triangles.extend([d * row + w, (d + 1) * row + w, (d + 1) * row + w + 1]);
+            triangles.extend([d * row + w, (d + 1) * row + w + 1, d * row + w + 1]);
+        }
+    }
+
+    let mut mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    );
+    mesh.insert_indices(Indices::U32(triangles));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 1.0, 0.0]; count]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(ATTRIBUTE_WATER_DEPTH, depths);
+
+    mesh
+}
@@ -1,35 +1,84 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
+use crate::camera::MainCamera;
+use crate::console::{parse_arg, AddConsoleCommand, CommandResult, ConsoleCommand, ConsoleError};
+use crate::water::buoyancy::Buoyancy;
+
+/// Most balls spawned by a single `spawn ball` command.
+const MAX_SPAWNED_BALLS: usize = 100;
+
 pub struct WorldPlugin;
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_world);
+        app.add_console_command(
+            "spawn",
+            ConsoleCommand::new(
+                "spawn ball [n]",
+                "Drops balls in front of the camera",
+                spawn_command,
+            )
+            .with_keywords(&["ball"]),
+        )
+        .add_systems(Startup, setup_world);
     }
 }
 
-fn setup_world(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    commands.spawn((
+fn ball(mesh: Handle<Mesh>, material: Handle<StandardMaterial>, translation: Vec3) -> impl Bundle {
+    (
         PbrBundle {
-            mesh: meshes.add(Sphere::default()),
-            material: materials.add(Color::BLUE),
-            transform: Transform {
-                translation: Vec3 {
-                    x: 0.0,
-                    y: 10.0,
-                    z: 0.0,
-                },
-                ..default()
-            },
+            mesh,
+            material,
+            transform: Transform::from_translation(translation),
             ..default()
         },
         RigidBody::Dynamic,
         Restitution::new(0.5),
         Collider::ball(1.0),
+        // half as dense as water, so it floats
+        ColliderMassProperties::Density(0.5),
+        Buoyancy::default(),
+    )
+}
+
+fn setup_world(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn(ball(
+        meshes.add(Sphere::default()),
+        materials.add(Color::BLUE),
+        Vec3::new(0.0, 10.0, 0.0),
     ));
 }
+
+fn spawn_command(world: &mut World, args: &[&str]) -> CommandResult {
+    let count = match args {
+        ["ball"] => 1,
+        ["ball", count] => parse_arg::<usize>(count)?.min(MAX_SPAWNED_BALLS),
+        _ => return Err(ConsoleError::Usage),
+    };
+
+    let Ok(camera) = world
+        .query_filtered::<&Transform, With<MainCamera>>()
+        .get_single(world)
+        .copied()
+    else {
+        return Err(ConsoleError::Failed("there is no camera".to_string()));
+    };
+    let mesh = world.resource_mut::<Assets<Mesh>>().add(Sphere::default());
+    let material = world
+        .resource_mut::<Assets<StandardMaterial>>()
+        .add(Color::BLUE);
+
+    // stacked a bit in front of the camera, so they fall into view
+    let start = camera.translation + camera.forward() * 6.0;
+    for index in 0..count {
+        let translation = start + Vec3::Y * 2.5 * index as f32;
+        world.spawn(ball(mesh.clone(), material.clone(), translation));
+    }
+
+    Ok(format!("spawned {count} balls"))
+}